@@ -1,6 +1,7 @@
 use extism_pdk::*;
 use labeled::buckle::{Buckle, Component};
 use faasten_interface_types::{dent_create, dent_update, gate, BlobClose, BlobFinalize, BlobRead, BlobResult, BlobWrite, DentCreate, DentInvoke, DentInvokeResult, DentLink, DentListResult, DentLsFaceted, DentLsFacetedResult, DentLsGateResult, DentOpen, DentOpenResult, DentResult, DentUnlink, DentUpdate, DirectGate, Function, Gate};
+use faasten_runtime_interface::{Batch, BatchResult, BlobReadNext, BlobSeek, BlobStat, DentInvokeBlobs, FanOut, FanOutResult, InputBlobs, InvokeAwait, InvokeHandleResult, MaybeBuckle, ServiceInvokeResult, ServiceUpdate};
 
 #[host_fn]
 extern "ExtismHost" {
@@ -12,6 +13,8 @@ extern "ExtismHost" {
     fn dent_create(dent_create_json: Json<DentCreate>) -> Json<DentResult>;
     fn dent_close(input_fd: u64) -> Json<DentResult>;
     fn dent_update(dent_update_json: Json<DentUpdate>) -> Json<DentResult>;
+    fn dent_update_service(service_update_json: Json<ServiceUpdate>) -> Json<DentResult>;
+    fn dent_relabel(fd: u64, new_label: Json<Buckle>) -> Json<DentResult>;
     fn dent_read(fd: u64) -> Json<DentResult>;
     fn dent_link(dent_link_json: Json<DentLink>) -> Json<DentResult>;
     fn dent_unlink(dent_unlink_json: Json<DentUnlink>) -> Json<DentResult>;
@@ -19,15 +22,90 @@ extern "ExtismHost" {
     fn dent_ls_faceted(dent_ls_faceted_json: Json<DentLsFaceted>) -> Json<DentLsFacetedResult>;
     fn dent_ls_gate(gate_fd: u64) -> Json<DentLsGateResult>;
     fn dent_invoke(dent_invoke_json: Json<DentInvoke>) -> Json<DentInvokeResult>;
+    fn dent_invoke_blobs(dent_invoke_blobs_json: Json<DentInvokeBlobs>) -> Json<DentInvokeResult>;
+    fn service_invoke(dent_invoke_blobs_json: Json<DentInvokeBlobs>) -> Json<ServiceInvokeResult>;
+    fn invoke_poll(handle: u64) -> Json<InvokeHandleResult>;
+    fn invoke_await(invoke_await_json: Json<InvokeAwait>) -> Json<InvokeHandleResult>;
+    fn invoke_cancel(handle: u64) -> Json<DentResult>;
+    fn fan_out(fan_out_json: Json<FanOut>) -> Json<FanOutResult>;
+    fn input_blobs() -> Json<InputBlobs>;
     fn dent_get_blob(fd: u64) -> Json<BlobResult>;
     fn blob_create() -> Json<BlobResult>;
     fn blob_write(blob_write_json: Json<BlobWrite>) -> Json<BlobResult>;
     fn blob_finalize(blob_finalize_json: Json<BlobFinalize>) -> Json<BlobResult>;
     fn blob_read(blob_read_json: Json<BlobRead>) -> Json<BlobResult>;
+    fn blob_seek(blob_seek_json: Json<BlobSeek>) -> Json<BlobResult>;
+    fn blob_tell(fd: u64) -> Json<BlobResult>;
+    fn blob_read_next(blob_read_next_json: Json<BlobReadNext>) -> Json<BlobResult>;
+    fn blob_read_to_end(fd: u64) -> Json<BlobResult>;
+    fn file_to_blob(fd: u64) -> Json<BlobResult>;
+    fn blob_to_file(blob_fd: u64, file_fd: u64) -> Json<DentResult>;
+    fn blob_stat(fd: u64) -> Json<BlobStat>;
     fn blob_close(blob_close_json: Json<BlobClose>) -> Json<BlobResult>;
+    fn blob_abort(fd: u64) -> Json<BlobResult>;
+    fn batch(batch_json: Json<Batch>) -> Json<BatchResult>;
+    fn txn_begin() -> Json<DentResult>;
+    fn txn_commit() -> Json<DentResult>;
+    fn txn_abort() -> Json<DentResult>;
 }
 
 
+// protobuf-encoded variants of the cloudcalls above. Same arguments and results, smaller byte payloads
+#[host_fn]
+extern "ExtismHost" {
+    fn get_current_label_pb() -> Prost<Buckle>;
+    fn buckle_parse_pb(input_str: &str) -> Prost<MaybeBuckle>;
+    fn taint_with_label_pb(input_label: Prost<Buckle>) -> Prost<Buckle>;
+    fn declassify_pb(target_secrecy: Prost<Component>) -> Prost<Buckle>;
+    fn root_pb() -> Prost<DentResult>;
+    fn dent_open_pb(dent_open: Prost<DentOpen>) -> Prost<DentOpenResult>;
+    fn dent_create_pb(dent_create: Prost<DentCreate>) -> Prost<DentResult>;
+    fn dent_close_pb(input_fd: u64) -> Prost<DentResult>;
+    fn dent_update_pb(dent_update: Prost<DentUpdate>) -> Prost<DentResult>;
+    fn dent_update_service_pb(service_update: Prost<ServiceUpdate>) -> Prost<DentResult>;
+    fn dent_relabel_pb(fd: u64, new_label: Prost<Buckle>) -> Prost<DentResult>;
+    fn dent_read_pb(fd: u64) -> Prost<DentResult>;
+    fn dent_link_pb(dent_link: Prost<DentLink>) -> Prost<DentResult>;
+    fn dent_unlink_pb(dent_unlink: Prost<DentUnlink>) -> Prost<DentResult>;
+    fn dent_list_pb(fd: u64) -> Prost<DentListResult>;
+    fn dent_ls_faceted_pb(dent_ls_faceted: Prost<DentLsFaceted>) -> Prost<DentLsFacetedResult>;
+    fn dent_ls_gate_pb(gate_fd: u64) -> Prost<DentLsGateResult>;
+    fn dent_invoke_pb(dent_invoke: Prost<DentInvoke>) -> Prost<DentInvokeResult>;
+    fn dent_invoke_blobs_pb(dent_invoke_blobs: Prost<DentInvokeBlobs>) -> Prost<DentInvokeResult>;
+    fn service_invoke_pb(dent_invoke_blobs: Prost<DentInvokeBlobs>) -> Prost<ServiceInvokeResult>;
+    fn invoke_poll_pb(handle: u64) -> Prost<InvokeHandleResult>;
+    fn invoke_await_pb(invoke_await: Prost<InvokeAwait>) -> Prost<InvokeHandleResult>;
+    fn invoke_cancel_pb(handle: u64) -> Prost<DentResult>;
+    fn fan_out_pb(fan_out: Prost<FanOut>) -> Prost<FanOutResult>;
+    fn input_blobs_pb() -> Prost<InputBlobs>;
+    fn dent_get_blob_pb(fd: u64) -> Prost<BlobResult>;
+    fn blob_create_pb() -> Prost<BlobResult>;
+    fn blob_write_pb(blob_write: Prost<BlobWrite>) -> Prost<BlobResult>;
+    fn blob_finalize_pb(blob_finalize: Prost<BlobFinalize>) -> Prost<BlobResult>;
+    fn blob_read_pb(blob_read: Prost<BlobRead>) -> Prost<BlobResult>;
+    fn blob_seek_pb(blob_seek: Prost<BlobSeek>) -> Prost<BlobResult>;
+    fn blob_tell_pb(fd: u64) -> Prost<BlobResult>;
+    fn blob_read_next_pb(blob_read_next: Prost<BlobReadNext>) -> Prost<BlobResult>;
+    fn blob_read_to_end_pb(fd: u64) -> Prost<BlobResult>;
+    fn file_to_blob_pb(fd: u64) -> Prost<BlobResult>;
+    fn blob_to_file_pb(blob_fd: u64, file_fd: u64) -> Prost<DentResult>;
+    fn blob_stat_pb(fd: u64) -> Prost<BlobStat>;
+    fn blob_close_pb(blob_close: Prost<BlobClose>) -> Prost<BlobResult>;
+    fn blob_abort_pb(fd: u64) -> Prost<BlobResult>;
+    fn batch_pb(batch: Prost<Batch>) -> Prost<BatchResult>;
+    fn txn_begin_pb() -> Prost<DentResult>;
+    fn txn_commit_pb() -> Prost<DentResult>;
+    fn txn_abort_pb() -> Prost<DentResult>;
+}
+
+// cloudcalls exchanging plain values instead of encoded messages
+#[link(wasm_import_module = "extism:host/user")]
+extern "C" {
+    // reads a blob into a block of extism memory (e.g from Memory::new), returns the number of bytes read or -1.
+    // The faasten SDK wraps this as Blob::read_at, copying the bytes into a guest buffer
+    fn blob_read_into(fd: u64, offset: u64, buf: u64) -> i64;
+}
+
 #[plugin_fn]
 pub fn run() -> FnResult<String> {
     unsafe {
@@ -0,0 +1,309 @@
+/*
+    Cloudcall argument and result types that are specific to this runtime. Shared by the
+    runtime and by guest functions. Messages are declared the same way the faasten interface
+    types are generated, so they can be encoded with both serde and prost.
+ */
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use labeled::buckle::Buckle;
+use faasten_interface_types::{DentInvoke, DentInvokeResult, DentListResult, DentOpenResult, DentResult, Service};
+
+/*
+    A label that may be missing, e.g because it failed to parse. Used where the
+    JSON cloudcalls return Option<Buckle>, which has no protobuf encoding.
+ */
+#[derive(Clone, PartialEq, ::prost::Message, Serialize, Deserialize)]
+pub struct MaybeBuckle {
+    #[prost(message, optional, tag = "1")]
+    pub label: Option<Buckle>,
+}
+
+/*
+    One operation of a batch. fd_ref and target_ref refer to earlier operations of the same
+    batch by index. When set, the file descriptor produced by that operation is used in place of
+    the operation's own fd (fd_ref) or of DentLink's target_fd (target_ref).
+ */
+#[derive(Clone, PartialEq, ::prost::Message, Serialize, Deserialize)]
+pub struct BatchOp {
+    #[prost(oneof = "batch_op::Op", tags = "1, 2, 3, 4, 5, 6, 7, 8")]
+    pub op: Option<batch_op::Op>,
+    #[prost(uint32, optional, tag = "9")]
+    pub fd_ref: Option<u32>,
+    #[prost(uint32, optional, tag = "10")]
+    pub target_ref: Option<u32>,
+}
+
+pub mod batch_op {
+    use serde::{Deserialize, Serialize};
+    use faasten_interface_types::{DentCreate, DentLink, DentOpen, DentUnlink, DentUpdate};
+
+    #[derive(Clone, PartialEq, ::prost::Oneof, Serialize, Deserialize)]
+    pub enum Op {
+        #[prost(message, tag = "1")]
+        Open(DentOpen),
+        #[prost(message, tag = "2")]
+        Create(DentCreate),
+        #[prost(message, tag = "3")]
+        Link(DentLink),
+        #[prost(message, tag = "4")]
+        Unlink(DentUnlink),
+        #[prost(message, tag = "5")]
+        Update(DentUpdate),
+        #[prost(uint64, tag = "6")]
+        Read(u64),
+        #[prost(uint64, tag = "7")]
+        List(u64),
+        #[prost(uint64, tag = "8")]
+        Close(u64),
+    }
+}
+
+#[derive(Clone, PartialEq, ::prost::Message, Serialize, Deserialize)]
+pub struct Batch {
+    #[prost(message, repeated, tag = "1")]
+    pub ops: Vec<BatchOp>,
+    /* stop executing the batch at the first failed operation */
+    #[prost(bool, tag = "2")]
+    pub stop_on_failure: bool,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message, Serialize, Deserialize)]
+pub struct BatchOpResult {
+    #[prost(oneof = "batch_op_result::Result", tags = "1, 2, 3")]
+    pub result: Option<batch_op_result::Result>,
+}
+
+pub mod batch_op_result {
+    use serde::{Deserialize, Serialize};
+    use faasten_interface_types::{DentInvoke, DentInvokeResult, DentListResult, DentOpenResult, DentResult};
+
+    #[derive(Clone, PartialEq, ::prost::Oneof, Serialize, Deserialize)]
+    pub enum Result {
+        #[prost(message, tag = "1")]
+        Open(DentOpenResult),
+        #[prost(message, tag = "2")]
+        Dent(DentResult),
+        #[prost(message, tag = "3")]
+        List(DentListResult),
+    }
+}
+
+/*
+    success is true only if every operation succeeded. results holds one entry per executed
+    operation, in order.
+ */
+#[derive(Clone, PartialEq, ::prost::Message, Serialize, Deserialize)]
+pub struct BatchResult {
+    #[prost(bool, tag = "1")]
+    pub success: bool,
+    #[prost(message, repeated, tag = "2")]
+    pub results: Vec<BatchOpResult>,
+}
+
+impl BatchOpResult {
+    pub fn success(&self) -> bool {
+        match &self.result {
+            Some(batch_op_result::Result::Open(r)) => r.success,
+            Some(batch_op_result::Result::Dent(r)) => r.success,
+            Some(batch_op_result::Result::List(r)) => r.success,
+            None => false
+        }
+    }
+}
+
+impl From<DentOpenResult> for BatchOpResult {
+    fn from(r: DentOpenResult) -> Self {
+        BatchOpResult { result: Some(batch_op_result::Result::Open(r)) }
+    }
+}
+
+impl From<DentResult> for BatchOpResult {
+    fn from(r: DentResult) -> Self {
+        BatchOpResult { result: Some(batch_op_result::Result::Dent(r)) }
+    }
+}
+
+impl From<DentListResult> for BatchOpResult {
+    fn from(r: DentListResult) -> Self {
+        BatchOpResult { result: Some(batch_op_result::Result::List(r)) }
+    }
+}
+
+/*
+    Argument of dent_update_service. Updates only the provided fields of a service, like
+    dent_update does, but with explicit url and verb fields: in DentUpdate's Service the
+    empty url and the zero verb can't be told apart from unset ones, so they can't be set.
+    The url and verb of service are ignored.
+ */
+#[derive(Clone, PartialEq, ::prost::Message, Serialize, Deserialize)]
+pub struct ServiceUpdate {
+    #[prost(uint64, tag = "1")]
+    pub fd: u64,
+    #[prost(message, optional, tag = "2")]
+    pub service: Option<Service>,
+    #[prost(string, optional, tag = "3")]
+    pub url: Option<String>,
+    #[prost(int32, optional, tag = "4")]
+    pub verb: Option<i32>,
+}
+
+/*
+    Result of blob_stat. name is the blob's content hash in the blobstore. For blobs that are
+    still being written, it is the content hash of the bytes written so far.
+ */
+#[derive(Clone, PartialEq, ::prost::Message, Serialize, Deserialize)]
+pub struct BlobStat {
+    #[prost(bool, tag = "1")]
+    pub success: bool,
+    #[prost(uint64, tag = "2")]
+    pub fd: u64,
+    #[prost(string, optional, tag = "3")]
+    pub name: Option<String>,
+    #[prost(uint64, tag = "4")]
+    pub len: u64,
+    #[prost(bool, tag = "5")]
+    pub finalized: bool,
+}
+
+/*
+    Moves the cursor of a finalized blob, the same way std::io::Seek does. offset is relative
+    to the start of the blob, the current cursor position, or the end of the blob, depending on whence.
+ */
+#[derive(Clone, PartialEq, ::prost::Message, Serialize, Deserialize)]
+pub struct BlobSeek {
+    #[prost(uint64, tag = "1")]
+    pub fd: u64,
+    #[prost(int64, tag = "2")]
+    pub offset: i64,
+    #[prost(enumeration = "SeekWhence", tag = "3")]
+    pub whence: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration, Serialize, Deserialize)]
+#[repr(i32)]
+pub enum SeekWhence {
+    Start = 0,
+    Current = 1,
+    End = 2,
+}
+
+/*
+    Reads from the cursor of a finalized blob and advances the cursor past the bytes read.
+    length defaults to 4KB, same as with BlobRead.
+ */
+#[derive(Clone, PartialEq, ::prost::Message, Serialize, Deserialize)]
+pub struct BlobReadNext {
+    #[prost(uint64, tag = "1")]
+    pub fd: u64,
+    #[prost(uint64, optional, tag = "2")]
+    pub length: Option<u64>,
+}
+
+/*
+    A gate invocation that passes blobs to the invoked function. blobs are file descriptors of
+    finalized blobs (in the blobs table) that are opened into the callee's blobs table, in the same
+    order. If payload_blob is set, the contents of that blob are used as the payload in place of
+    invoke's payload. Services are sent the blob as a streamed request body instead. timeout_ms
+    overrides the runtime's default invocation timeout.
+ */
+#[derive(Clone, PartialEq, ::prost::Message, Serialize, Deserialize)]
+pub struct DentInvokeBlobs {
+    #[prost(message, optional, tag = "1")]
+    pub invoke: Option<DentInvoke>,
+    #[prost(uint64, optional, tag = "2")]
+    pub payload_blob: Option<u64>,
+    #[prost(uint64, repeated, tag = "3")]
+    pub blobs: Vec<u64>,
+    #[prost(uint64, optional, tag = "4")]
+    pub timeout_ms: Option<u64>,
+}
+
+/* File descriptors of the blobs passed to this function by its invoker, in the order they were passed */
+#[derive(Clone, PartialEq, ::prost::Message, Serialize, Deserialize)]
+pub struct InputBlobs {
+    #[prost(uint64, repeated, tag = "1")]
+    pub fds: Vec<u64>,
+}
+
+/* Waits for the result of an asynchronous invocation, for at most timeout_ms if set */
+#[derive(Clone, PartialEq, ::prost::Message, Serialize, Deserialize)]
+pub struct InvokeAwait {
+    #[prost(uint64, tag = "1")]
+    pub handle: u64,
+    #[prost(uint64, optional, tag = "2")]
+    pub timeout_ms: Option<u64>,
+}
+
+/*
+    Result of collecting an asynchronous invocation. success is false if the handle is unknown
+    (e.g because its result was already collected). ready is false if the result hasn't arrived yet.
+ */
+#[derive(Clone, PartialEq, ::prost::Message, Serialize, Deserialize)]
+pub struct InvokeHandleResult {
+    #[prost(bool, tag = "1")]
+    pub success: bool,
+    #[prost(bool, tag = "2")]
+    pub ready: bool,
+    #[prost(message, optional, tag = "3")]
+    pub result: Option<DentInvokeResult>,
+}
+
+/*
+    Gates and services to invoke at once. Invocations are always waited for, so the
+    sync field of each invocation is ignored.
+ */
+#[derive(Clone, PartialEq, ::prost::Message, Serialize, Deserialize)]
+pub struct FanOut {
+    #[prost(message, repeated, tag = "1")]
+    pub invokes: Vec<DentInvoke>,
+}
+
+/*
+    success is true only if every invocation succeeded. results holds one entry per invocation, in order.
+    Entries of gates have no response metadata.
+ */
+#[derive(Clone, PartialEq, ::prost::Message, Serialize, Deserialize)]
+pub struct FanOutResult {
+    #[prost(bool, tag = "1")]
+    pub success: bool,
+    #[prost(message, repeated, tag = "2")]
+    pub results: Vec<ServiceInvokeResult>,
+}
+
+/*
+    Result of invoking a service, same as DentInvokeResult with the metadata of the service's
+    response. headers are the response's headers. status and url (the final url, after following
+    redirects) are missing if no response was received. content_length is the length of the body
+    returned as data or written to a blob.
+ */
+#[derive(Clone, PartialEq, ::prost::Message, Serialize, Deserialize)]
+pub struct ServiceInvokeResult {
+    #[prost(bool, tag = "1")]
+    pub success: bool,
+    #[prost(uint64, optional, tag = "2")]
+    pub fd: Option<u64>,
+    #[prost(bytes = "vec", optional, tag = "3")]
+    pub data: Option<Vec<u8>>,
+    #[prost(map = "string, bytes", tag = "4")]
+    pub headers: HashMap<String, Vec<u8>>,
+    #[prost(uint32, optional, tag = "5")]
+    pub status: Option<u32>,
+    #[prost(string, optional, tag = "6")]
+    pub url: Option<String>,
+    #[prost(uint64, optional, tag = "7")]
+    pub content_length: Option<u64>,
+}
+
+impl From<DentInvokeResult> for ServiceInvokeResult {
+    fn from(result: DentInvokeResult) -> Self {
+        let DentInvokeResult { success, fd, data, headers } = result;
+        Self { success, fd, data, headers, ..Default::default() }
+    }
+}
+
+impl From<ServiceInvokeResult> for DentInvokeResult {
+    fn from(result: ServiceInvokeResult) -> Self {
+        let ServiceInvokeResult { success, fd, data, headers, .. } = result;
+        Self { success, fd, data, headers }
+    }
+}
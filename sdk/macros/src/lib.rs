@@ -0,0 +1,111 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{Ident, ItemFn};
+
+/*
+    Turns fn(Input) -> Result<Output, E> into the function's run export. The payload is
+    deserialized from JSON into Input and Output is serialized to JSON, unless the attribute
+    is written #[faasten::function(bytes)], in which case the payload is passed as a Vec<u8>
+    and Output is anything that converts into a Vec<u8>. Errors are reported to the runtime,
+    which fails the invocation with E's Display output.
+ */
+#[proc_macro_attribute]
+pub fn function(attr: TokenStream, item: TokenStream) -> TokenStream {
+    expand(attr.into(), item.into()).into()
+}
+
+// expands #[faasten::function], on proc_macro2 tokens so the expansion can be tested
+fn expand(attr: TokenStream2, item: TokenStream2) -> TokenStream2 {
+    let runner = if attr.is_empty() {
+        quote!(run_json)
+    } else {
+        let runner = match syn::parse2::<Ident>(attr) {
+            Ok(runner) => runner,
+            Err(e) => return e.to_compile_error()
+        };
+        match runner.to_string().as_str() {
+            "json" => quote!(run_json),
+            "bytes" => quote!(run_bytes),
+            _ => return quote!(compile_error!("expected #[faasten::function], #[faasten::function(json)] or #[faasten::function(bytes)]");)
+        }
+    };
+
+    let func = match syn::parse2::<ItemFn>(item) {
+        Ok(func) => func,
+        Err(e) => return e.to_compile_error()
+    };
+    if func.sig.inputs.len() != 1 {
+        return syn::Error::new_spanned(&func.sig, "a faasten function takes exactly one argument, its input")
+            .to_compile_error();
+    }
+
+    // the export has a name of its own, so it can't clash with the function's
+    let name = &func.sig.ident;
+    quote! {
+        #func
+
+        #[export_name = "run"]
+        pub extern "C" fn __faasten_run() -> i32 {
+            ::faasten::__private::#runner(#name)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn function() -> TokenStream2 {
+        quote! {
+            fn greet(name: String) -> Result<String, String> {
+                Ok(format!("hello {}", name))
+            }
+        }
+    }
+
+    fn export(runner: TokenStream2) -> String {
+        let function = function();
+        quote! {
+            #function
+
+            #[export_name = "run"]
+            pub extern "C" fn __faasten_run() -> i32 {
+                ::faasten::__private::#runner(greet)
+            }
+        }
+        .to_string()
+    }
+
+    #[test]
+    fn exports_run_calling_the_runner() {
+        assert_eq!(expand(quote!(), function()).to_string(), export(quote!(run_json)));
+        assert_eq!(expand(quote!(json), function()).to_string(), export(quote!(run_json)));
+        assert_eq!(expand(quote!(bytes), function()).to_string(), export(quote!(run_bytes)));
+    }
+
+    #[test]
+    fn rejects_unknown_formats() {
+        let expanded = expand(quote!(xml), function()).to_string();
+        assert!(expanded.starts_with("compile_error !"), "{}", expanded);
+        assert!(!expanded.contains("__faasten_run"));
+
+        let expanded = expand(quote!("json"), function()).to_string();
+        assert!(expanded.contains("compile_error"), "{}", expanded);
+    }
+
+    #[test]
+    fn functions_take_one_argument() {
+        for item in [quote!(fn none() -> Result<(), String> { Ok(()) }), quote!(fn two(a: u8, b: u8) -> Result<u8, String> { Ok(a + b) })] {
+            let expanded = expand(quote!(), item).to_string();
+            assert!(expanded.contains("exactly one argument"), "{}", expanded);
+            assert!(!expanded.contains("__faasten_run"));
+        }
+    }
+
+    #[test]
+    fn only_functions_are_accepted() {
+        let expanded = expand(quote!(), quote!(struct Input;)).to_string();
+        assert!(expanded.contains("compile_error"), "{}", expanded);
+    }
+}
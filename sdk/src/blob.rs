@@ -0,0 +1,138 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use extism_pdk::Prost;
+use faasten_interface_types::{BlobClose, BlobFinalize, BlobWrite};
+use faasten_runtime_interface::{BlobReadNext, BlobSeek, BlobStat, SeekWhence};
+
+use crate::dent::{check, Dent, File};
+use crate::error::{call, Error, Result};
+use crate::host;
+
+/*
+    A finalized blob in the runtime's blobs table. Reads and seeks move the blob's cursor,
+    which is kept by the runtime. The blob is closed when the handle is dropped.
+ */
+pub struct Blob {
+    fd: u64,
+}
+
+/*
+    A blob being written. It becomes readable once finalized, and is discarded if the handle
+    is dropped before that.
+ */
+pub struct NewBlob {
+    fd: u64,
+    finalized: bool,
+}
+
+impl Blob {
+    pub(crate) fn from_fd(fd: u64) -> Self {
+        Self { fd }
+    }
+
+    pub fn fd(&self) -> u64 {
+        self.fd
+    }
+
+    // content hash and length of the blob
+    pub fn stat(&self) -> Result<BlobStat> {
+        let stat = call(unsafe { host::blob_stat_pb(self.fd) })?;
+        check("blob_stat", stat.success)?;
+        Ok(stat)
+    }
+
+    // the blob's contents from the cursor on. Moves the cursor to the end. Fails past the runtime's max_blob_read_bytes
+    pub fn contents(&mut self) -> Result<Vec<u8>> {
+        let result = call(unsafe { host::blob_read_to_end_pb(self.fd) })?;
+        check("blob_read_to_end", result.success)?;
+        Ok(result.data.unwrap_or_default())
+    }
+
+    // reads the blob at offset into buf without moving the cursor. Returns the number of bytes read
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        let num_bytes_read = host::blob_read_at(self.fd, offset, buf).map_err(Error::Host)?;
+        num_bytes_read.ok_or(Error::Failed("blob_read_into"))
+    }
+
+    // replaces the contents of file with the blob's, without passing them through the function
+    pub fn to_file(&self, file: &File) -> Result<()> {
+        let result = call(unsafe { host::blob_to_file_pb(self.fd, file.fd()) })?;
+        check("blob_to_file", result.success)
+    }
+}
+
+impl Read for Blob {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let blob_read_next = BlobReadNext { fd: self.fd, length: Some(buf.len() as u64) };
+        let result = call(unsafe { host::blob_read_next_pb(Prost(blob_read_next)) })?;
+        check("blob_read_next", result.success)?;
+
+        let data = result.data.unwrap_or_default();
+        buf[..data.len()].copy_from_slice(&data);
+        Ok(data.len())
+    }
+}
+
+impl Seek for Blob {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (offset, whence) = match pos {
+            SeekFrom::Start(offset) => (offset as i64, SeekWhence::Start),
+            SeekFrom::Current(offset) => (offset, SeekWhence::Current),
+            SeekFrom::End(offset) => (offset, SeekWhence::End),
+        };
+        let blob_seek = BlobSeek { fd: self.fd, offset, whence: whence.into() };
+        let result = call(unsafe { host::blob_seek_pb(Prost(blob_seek)) })?;
+        check("blob_seek", result.success)?;
+
+        // the new position is returned as the length
+        Ok(result.len)
+    }
+}
+
+impl Drop for Blob {
+    fn drop(&mut self) {
+        let _ = unsafe { host::blob_close_pb(Prost(BlobClose { fd: self.fd })) };
+    }
+}
+
+impl NewBlob {
+    pub fn create() -> Result<Self> {
+        let result = call(unsafe { host::blob_create_pb() })?;
+        check("blob_create", result.success)?;
+        Ok(Self { fd: result.fd, finalized: false })
+    }
+
+    pub fn finalize(mut self) -> Result<Blob> {
+        let result = call(unsafe { host::blob_finalize_pb(Prost(BlobFinalize { fd: self.fd })) })?;
+        check("blob_finalize", result.success)?;
+        self.finalized = true;
+        Ok(Blob::from_fd(result.fd))
+    }
+}
+
+impl Write for NewBlob {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let blob_write = BlobWrite { fd: self.fd, data: buf.to_vec() };
+        let result = call(unsafe { host::blob_write_pb(Prost(blob_write)) })?;
+        check("blob_write", result.success)?;
+        Ok(buf.len())
+    }
+
+    // writes go straight to the runtime
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for NewBlob {
+    fn drop(&mut self) {
+        if !self.finalized {
+            let _ = unsafe { host::blob_abort_pb(self.fd) };
+        }
+    }
+}
+
+// blobs passed to this invocation by its caller, in the order they were passed
+pub fn input_blobs() -> Result<Vec<Blob>> {
+    let input_blobs = call(unsafe { host::input_blobs_pb() })?;
+    Ok(input_blobs.fds.into_iter().map(Blob::from_fd).collect())
+}
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use extism_pdk::Prost;
+use faasten_interface_types::{dent_create, dent_open, dent_update, DentCreate, DentKind, DentLink, DentLsFaceted,
+    DentOpen, DentUnlink, DentUpdate};
+use labeled::buckle::Buckle;
+
+use crate::blob::Blob;
+use crate::error::{call, Error, Result};
+use crate::host;
+
+// fd of the root directory. It is open for the whole invocation and never closed
+const ROOT_FD: u64 = 0;
+
+/*
+    A handle to an object in the runtime's dents table. The object is closed when the
+    handle is dropped.
+ */
+pub trait Dent {
+    fn fd(&self) -> u64;
+
+    // raises the object's label, or lowers it as far as the function's privilege allows
+    fn relabel(&self, label: Buckle) -> Result<()> {
+        let result = call(unsafe { host::dent_relabel_pb(self.fd(), Prost(label)) })?;
+        check("dent_relabel", result.success)
+    }
+}
+
+pub(crate) fn check(cloudcall: &'static str, success: bool) -> Result<()> {
+    if success { Ok(()) } else { Err(Error::Failed(cloudcall)) }
+}
+
+// an fd in the dents table, closed when dropped
+struct Handle(u64);
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        if self.0 != ROOT_FD {
+            let _ = unsafe { host::dent_close_pb(self.0) };
+        }
+    }
+}
+
+macro_rules! dent_handles {
+    ($($name:ident),*) => {
+        $(
+            pub struct $name(Handle);
+
+            impl Dent for $name {
+                fn fd(&self) -> u64 {
+                    self.0.0
+                }
+            }
+        )*
+    };
+}
+
+dent_handles!(Dir, File, FacetedDir, Gate, Service, BlobEntry);
+
+// an opened directory entry, of whichever kind it turned out to be
+pub enum Entry {
+    Dir(Dir),
+    File(File),
+    FacetedDir(FacetedDir),
+    Gate(Gate),
+    Service(Service),
+    Blob(BlobEntry),
+}
+
+impl Entry {
+    fn new(fd: u64, kind: i32) -> Result<Self> {
+        let handle = Handle(fd);
+        match DentKind::try_from(kind) {
+            Ok(DentKind::DentDirectory) => Ok(Self::Dir(Dir(handle))),
+            Ok(DentKind::DentFile) => Ok(Self::File(File(handle))),
+            Ok(DentKind::DentFacetedDirectory) => Ok(Self::FacetedDir(FacetedDir(handle))),
+            Ok(DentKind::DentGate) => Ok(Self::Gate(Gate(handle))),
+            Ok(DentKind::DentService) => Ok(Self::Service(Service(handle))),
+            Ok(DentKind::DentBlob) => Ok(Self::Blob(BlobEntry(handle))),
+            Err(_) => Err(Error::WrongKind)
+        }
+    }
+
+    pub fn into_dir(self) -> Result<Dir> {
+        match self { Self::Dir(dir) => Ok(dir), _ => Err(Error::WrongKind) }
+    }
+
+    pub fn into_file(self) -> Result<File> {
+        match self { Self::File(file) => Ok(file), _ => Err(Error::WrongKind) }
+    }
+
+    pub fn into_faceted_dir(self) -> Result<FacetedDir> {
+        match self { Self::FacetedDir(dir) => Ok(dir), _ => Err(Error::WrongKind) }
+    }
+
+    pub fn into_gate(self) -> Result<Gate> {
+        match self { Self::Gate(gate) => Ok(gate), _ => Err(Error::WrongKind) }
+    }
+
+    pub fn into_service(self) -> Result<Service> {
+        match self { Self::Service(service) => Ok(service), _ => Err(Error::WrongKind) }
+    }
+
+    pub fn into_blob(self) -> Result<BlobEntry> {
+        match self { Self::Blob(blob) => Ok(blob), _ => Err(Error::WrongKind) }
+    }
+}
+
+// creates an unlinked object, label defaults to public
+fn create(kind: dent_create::Kind, label: Option<Buckle>) -> Result<Handle> {
+    let result = call(unsafe { host::dent_create_pb(Prost(DentCreate { label, kind: Some(kind) })) })?;
+    match result.fd {
+        Some(fd) if result.success => Ok(Handle(fd)),
+        _ => Err(Error::Failed("dent_create"))
+    }
+}
+
+fn open(fd: u64, entry: dent_open::Entry) -> Result<Entry> {
+    let result = call(unsafe { host::dent_open_pb(Prost(DentOpen { fd, entry: Some(entry) })) })?;
+    check("dent_open", result.success)?;
+    Entry::new(result.fd, result.kind)
+}
+
+impl Dir {
+    pub fn root() -> Self {
+        Self(Handle(ROOT_FD))
+    }
+
+    pub fn create(label: Buckle) -> Result<Self> {
+        create(dent_create::Kind::Directory, Some(label)).map(Self)
+    }
+
+    pub fn open(&self, name: &str) -> Result<Entry> {
+        open(self.fd(), dent_open::Entry::Name(name.to_string()))
+    }
+
+    pub fn open_dir(&self, name: &str) -> Result<Dir> {
+        self.open(name)?.into_dir()
+    }
+
+    pub fn open_file(&self, name: &str) -> Result<File> {
+        self.open(name)?.into_file()
+    }
+
+    // names of the directory's entries and their kinds
+    pub fn list(&self) -> Result<HashMap<String, DentKind>> {
+        let result = call(unsafe { host::dent_list_pb(self.fd()) })?;
+        check("dent_list", result.success)?;
+        Ok(result.entries.into_iter()
+            .filter_map(|(name, kind)| Some((name, DentKind::try_from(kind).ok()?)))
+            .collect())
+    }
+
+    pub fn link(&self, name: &str, target: &impl Dent) -> Result<()> {
+        let dent_link = DentLink { dir_fd: self.fd(), name: name.to_string(), target_fd: target.fd() };
+        let result = call(unsafe { host::dent_link_pb(Prost(dent_link)) })?;
+        check("dent_link", result.success)
+    }
+
+    pub fn unlink(&self, name: &str) -> Result<()> {
+        let dent_unlink = DentUnlink { dir_fd: self.fd(), name: name.to_string() };
+        let result = call(unsafe { host::dent_unlink_pb(Prost(dent_unlink)) })?;
+        check("dent_unlink", result.success)
+    }
+}
+
+impl File {
+    pub fn create(label: Buckle) -> Result<Self> {
+        create(dent_create::Kind::File, Some(label)).map(Self)
+    }
+
+    // reading a file taints the current label with the file's label
+    pub fn read(&self) -> Result<Vec<u8>> {
+        let result = call(unsafe { host::dent_read_pb(self.fd()) })?;
+        check("dent_read", result.success)?;
+        Ok(result.data.unwrap_or_default())
+    }
+
+    // replaces the file's contents
+    pub fn write(&self, data: impl Into<Vec<u8>>) -> Result<()> {
+        let dent_update = DentUpdate { fd: self.fd(), kind: Some(dent_update::Kind::File(data.into())) };
+        let result = call(unsafe { host::dent_update_pb(Prost(dent_update)) })?;
+        check("dent_update", result.success)
+    }
+
+    // copies the file's contents into a new blob, without passing them through the function
+    pub fn to_blob(&self) -> Result<Blob> {
+        let result = call(unsafe { host::file_to_blob_pb(self.fd()) })?;
+        check("file_to_blob", result.success)?;
+        Ok(Blob::from_fd(result.fd))
+    }
+}
+
+impl FacetedDir {
+    pub fn create() -> Result<Self> {
+        create(dent_create::Kind::FacetedDirectory, None).map(Self)
+    }
+
+    // the facet (directory) of the given label
+    pub fn open(&self, label: Buckle) -> Result<Dir> {
+        open(self.fd(), dent_open::Entry::Facet(label.into()))?.into_dir()
+    }
+
+    // labels of the facets that can flow to clearance (default: public)
+    pub fn list(&self, clearance: Option<Buckle>) -> Result<Vec<Buckle>> {
+        let dent_ls_faceted = DentLsFaceted { fd: self.fd(), clearance: clearance.map(Into::into) };
+        let result = call(unsafe { host::dent_ls_faceted_pb(Prost(dent_ls_faceted)) })?;
+        check("dent_ls_faceted", result.success)?;
+        Ok(result.facets.into_iter().map(Into::into).collect())
+    }
+}
+
+impl BlobEntry {
+    // links a finalized blob into the file system under label
+    pub fn create(blob: &Blob, label: Buckle) -> Result<Self> {
+        create(dent_create::Kind::Blob(blob.fd()), Some(label)).map(Self)
+    }
+
+    pub fn open(&self) -> Result<Blob> {
+        let result = call(unsafe { host::dent_get_blob_pb(self.fd()) })?;
+        check("dent_get_blob", result.success)?;
+        Ok(Blob::from_fd(result.fd))
+    }
+}
@@ -0,0 +1,42 @@
+/*
+    Runs the function behind the #[faasten::function] export: reads the payload, calls the
+    function, and hands its output or error to the runtime. Not meant to be used directly.
+ */
+use std::fmt::Display;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::host;
+
+// return code of a failed function, its error is set as the invocation's error
+pub const FAILURE: i32 = 1;
+
+pub fn run_json<I: DeserializeOwned, O: Serialize, E: Display>(f: impl FnOnce(I) -> Result<O, E>) -> i32 {
+    let output = read_input()
+        .and_then(|input| serde_json::from_slice(&input).map_err(|e| format!("invalid input: {}", e)))
+        .and_then(|input| f(input).map_err(|e| e.to_string()))
+        .and_then(|output| serde_json::to_vec(&output).map_err(|e| format!("invalid output: {}", e)));
+    finish(output)
+}
+
+pub fn run_bytes<O: Into<Vec<u8>>, E: Display>(f: impl FnOnce(Vec<u8>) -> Result<O, E>) -> i32 {
+    let output = read_input()
+        .and_then(|input| f(input).map(Into::into).map_err(|e| e.to_string()));
+    finish(output)
+}
+
+fn read_input() -> Result<Vec<u8>, String> {
+    host::input().map_err(|e| format!("couldn't read input: {}", e))
+}
+
+fn finish(output: Result<Vec<u8>, String>) -> i32 {
+    let error = match output {
+        Ok(output) => match host::output(output) {
+            Ok(()) => return 0,
+            Err(e) => format!("couldn't write output: {}", e)
+        },
+        Err(e) => e
+    };
+
+    host::set_error(&error);
+    FAILURE
+}
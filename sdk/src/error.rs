@@ -0,0 +1,39 @@
+use extism_pdk::Prost;
+
+#[derive(Debug)]
+pub enum Error {
+    // the cloudcall couldn't be made, e.g its arguments or result couldn't be encoded
+    Host(extism_pdk::Error),
+    // the runtime refused the named cloudcall, e.g because of a label check or a bad fd
+    Failed(&'static str),
+    // an invocation failed, with the runtime's description of why if there is one
+    Invoke(String),
+    // a handle of the wrong kind, e.g opening a name that is a file as a directory
+    WrongKind,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Host(e) => write!(f, "host error: {}", e),
+            Self::Failed(cloudcall) => write!(f, "{} failed", cloudcall),
+            Self::Invoke(e) => write!(f, "invocation failed: {}", e),
+            Self::WrongKind => write!(f, "wrong kind of directory entry"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for std::io::Error {
+    fn from(e: Error) -> Self {
+        std::io::Error::other(e)
+    }
+}
+
+// unwraps the result of a cloudcall
+pub(crate) fn call<T>(result: std::result::Result<Prost<T>, extism_pdk::Error>) -> Result<T> {
+    result.map(|Prost(value)| value).map_err(Error::Host)
+}
@@ -0,0 +1,84 @@
+use extism_pdk::*;
+use labeled::buckle::{Buckle, Component};
+use faasten_interface_types::{BlobClose, BlobFinalize, BlobResult, BlobWrite, DentCreate, DentInvoke, DentInvokeResult,
+    DentLink, DentListResult, DentLsFaceted, DentLsFacetedResult, DentOpen, DentOpenResult, DentResult, DentUnlink, DentUpdate};
+use faasten_runtime_interface::{BlobReadNext, BlobSeek, BlobStat, DentInvokeBlobs, InputBlobs,
+    InvokeAwait, InvokeHandleResult, MaybeBuckle, ServiceInvokeResult};
+
+// the cloudcalls used by the SDK. The protobuf-encoded variants are used, since they are smaller on the wire
+#[host_fn]
+extern "ExtismHost" {
+    pub fn get_current_label_pb() -> Prost<Buckle>;
+    pub fn buckle_parse_pb(input_str: &str) -> Prost<MaybeBuckle>;
+    pub fn taint_with_label_pb(input_label: Prost<Buckle>) -> Prost<Buckle>;
+    pub fn declassify_pb(target_secrecy: Prost<Component>) -> Prost<Buckle>;
+    pub fn dent_open_pb(dent_open: Prost<DentOpen>) -> Prost<DentOpenResult>;
+    pub fn dent_create_pb(dent_create: Prost<DentCreate>) -> Prost<DentResult>;
+    pub fn dent_close_pb(input_fd: u64) -> Prost<DentResult>;
+    pub fn dent_update_pb(dent_update: Prost<DentUpdate>) -> Prost<DentResult>;
+    pub fn dent_relabel_pb(fd: u64, new_label: Prost<Buckle>) -> Prost<DentResult>;
+    pub fn dent_read_pb(fd: u64) -> Prost<DentResult>;
+    pub fn dent_link_pb(dent_link: Prost<DentLink>) -> Prost<DentResult>;
+    pub fn dent_unlink_pb(dent_unlink: Prost<DentUnlink>) -> Prost<DentResult>;
+    pub fn dent_list_pb(fd: u64) -> Prost<DentListResult>;
+    pub fn dent_ls_faceted_pb(dent_ls_faceted: Prost<DentLsFaceted>) -> Prost<DentLsFacetedResult>;
+    pub fn dent_invoke_pb(dent_invoke: Prost<DentInvoke>) -> Prost<DentInvokeResult>;
+    pub fn dent_invoke_blobs_pb(dent_invoke_blobs: Prost<DentInvokeBlobs>) -> Prost<DentInvokeResult>;
+    pub fn service_invoke_pb(dent_invoke_blobs: Prost<DentInvokeBlobs>) -> Prost<ServiceInvokeResult>;
+    pub fn invoke_poll_pb(handle: u64) -> Prost<InvokeHandleResult>;
+    pub fn invoke_await_pb(invoke_await: Prost<InvokeAwait>) -> Prost<InvokeHandleResult>;
+    pub fn invoke_cancel_pb(handle: u64) -> Prost<DentResult>;
+    pub fn input_blobs_pb() -> Prost<InputBlobs>;
+    pub fn dent_get_blob_pb(fd: u64) -> Prost<BlobResult>;
+    pub fn blob_create_pb() -> Prost<BlobResult>;
+    pub fn blob_write_pb(blob_write: Prost<BlobWrite>) -> Prost<BlobResult>;
+    pub fn blob_finalize_pb(blob_finalize: Prost<BlobFinalize>) -> Prost<BlobResult>;
+    pub fn blob_seek_pb(blob_seek: Prost<BlobSeek>) -> Prost<BlobResult>;
+    pub fn blob_read_next_pb(blob_read_next: Prost<BlobReadNext>) -> Prost<BlobResult>;
+    pub fn blob_read_to_end_pb(fd: u64) -> Prost<BlobResult>;
+    pub fn file_to_blob_pb(fd: u64) -> Prost<BlobResult>;
+    pub fn blob_to_file_pb(blob_fd: u64, file_fd: u64) -> Prost<DentResult>;
+    pub fn blob_stat_pb(fd: u64) -> Prost<BlobStat>;
+    pub fn blob_close_pb(blob_close: Prost<BlobClose>) -> Prost<BlobResult>;
+    pub fn blob_abort_pb(fd: u64) -> Prost<BlobResult>;
+}
+
+// cloudcalls exchanging plain values instead of encoded messages
+#[link(wasm_import_module = "extism:host/user")]
+extern "C" {
+    fn blob_read_into(fd: u64, offset: u64, buf: u64) -> i64;
+}
+
+/*
+    Reads the blob at offset into buf. The runtime writes the bytes into a block of extism memory,
+    which is then copied into buf. Returns None if the runtime refused the read.
+ */
+pub fn blob_read_at(fd: u64, offset: u64, buf: &mut [u8]) -> Result<Option<usize>, extism_pdk::Error> {
+    if buf.is_empty() {
+        return Ok(Some(0));
+    }
+
+    let memory = Memory::new(buf.len())?;
+    let num_bytes_read = unsafe { blob_read_into(fd, offset, memory.offset()) };
+    let num_bytes_read = usize::try_from(num_bytes_read).ok().map(|n| n.min(buf.len()));
+    if let Some(n) = num_bytes_read {
+        memory.load(&mut buf[..n]);
+    }
+    memory.free();
+    Ok(num_bytes_read)
+}
+
+pub fn input() -> Result<Vec<u8>, extism_pdk::Error> {
+    extism_pdk::input()
+}
+
+pub fn output(data: Vec<u8>) -> Result<(), extism_pdk::Error> {
+    extism_pdk::output(data)
+}
+
+// same as extism's plugin_fn, the error is stored in extism memory for the runtime
+pub fn set_error(error: &str) {
+    if let Ok(memory) = Memory::from_bytes(error) {
+        unsafe { extism_pdk::extism::error_set(memory.offset()) };
+    }
+}
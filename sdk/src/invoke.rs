@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use extism_pdk::Prost;
+use faasten_interface_types::{DentInvoke, DentInvokeResult};
+use faasten_runtime_interface::{DentInvokeBlobs, InvokeAwait, ServiceInvokeResult};
+
+use crate::blob::Blob;
+use crate::dent::{Dent, Gate, Service};
+use crate::error::{call, Error, Result};
+use crate::host;
+
+/*
+    Result of a successful invocation. Responses of services also carry the response's metadata,
+    read with status, url and content_length.
+ */
+pub struct Response {
+    pub data: Vec<u8>,
+    // set if the result was written to a blob
+    pub blob: Option<Blob>,
+    pub headers: HashMap<String, Vec<u8>>,
+    status: Option<u32>,
+    url: Option<String>,
+    content_length: Option<u64>,
+}
+
+impl Response {
+    // HTTP status of a service's response
+    pub fn status(&self) -> Option<u16> {
+        self.status?.try_into().ok()
+    }
+
+    // final url of a service's response, after following redirects
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    // length of a service's response body, whether returned as data or written to a blob
+    pub fn content_length(&self) -> Option<u64> {
+        self.content_length
+    }
+
+    fn new(result: impl Into<ServiceInvokeResult>) -> Result<Self> {
+        let ServiceInvokeResult { success, fd, data, headers, status, url, content_length } = result.into();
+        let data = data.unwrap_or_default();
+        if !success {
+            return Err(Error::Invoke(String::from_utf8_lossy(&data).into_owned()));
+        }
+        Ok(Self { data, blob: fd.map(Blob::from_fd), headers, status, url, content_length })
+    }
+}
+
+/*
+    A gate invoked asynchronously. Its result is collected with poll or wait, and it is
+    cancelled if the handle is dropped before that.
+ */
+pub struct PendingInvoke {
+    handle: u64,
+    collected: bool,
+}
+
+impl PendingInvoke {
+    // the result if the invocation is done, without waiting for it
+    pub fn poll(&mut self) -> Result<Option<Response>> {
+        let result = call(unsafe { host::invoke_poll_pb(self.handle) })?;
+        self.collect(result.success, result.ready, result.result)
+    }
+
+    // waits at most timeout (forever if None) for the result. Returns None if it isn't done by then
+    pub fn wait(&mut self, timeout: Option<Duration>) -> Result<Option<Response>> {
+        let invoke_await = InvokeAwait { handle: self.handle, timeout_ms: timeout.map(|t| t.as_millis() as u64) };
+        let result = call(unsafe { host::invoke_await_pb(Prost(invoke_await)) })?;
+        self.collect(result.success, result.ready, result.result)
+    }
+
+    fn collect(&mut self, success: bool, ready: bool, result: Option<DentInvokeResult>) -> Result<Option<Response>> {
+        if !success {
+            return Err(Error::Failed("invoke_await"));
+        }
+        match result {
+            Some(result) if ready => {
+                self.collected = true;
+                Response::new(result).map(Some)
+            }
+            _ => Ok(None)
+        }
+    }
+}
+
+impl Drop for PendingInvoke {
+    fn drop(&mut self) {
+        if !self.collected {
+            let _ = unsafe { host::invoke_cancel_pb(self.handle) };
+        }
+    }
+}
+
+fn dent_invoke(fd: u64, sync: bool, payload: Vec<u8>, toblob: bool, parameters: HashMap<String, String>) -> Result<DentInvokeResult> {
+    call(unsafe { host::dent_invoke_pb(Prost(DentInvoke { fd, sync, payload, toblob, parameters })) })
+}
+
+impl Gate {
+    // invokes the gate and waits for its result. The result taints the current label
+    pub fn invoke(&self, payload: impl Into<Vec<u8>>) -> Result<Response> {
+        Response::new(dent_invoke(self.fd(), true, payload.into(), false, Default::default())?)
+    }
+
+    // same as invoke, also passing blobs to the invoked function
+    pub fn invoke_with_blobs(&self, payload: impl Into<Vec<u8>>, blobs: &[&Blob]) -> Result<Response> {
+        let dent_invoke_blobs = DentInvokeBlobs {
+            invoke: Some(DentInvoke { fd: self.fd(), sync: true, payload: payload.into(), toblob: false, parameters: Default::default() }),
+            payload_blob: None,
+            blobs: blobs.iter().map(|blob| blob.fd()).collect(),
+            timeout_ms: None,
+        };
+        Response::new(call(unsafe { host::dent_invoke_blobs_pb(Prost(dent_invoke_blobs)) })?)
+    }
+
+    // invokes the gate without waiting for its result
+    pub fn spawn(&self, payload: impl Into<Vec<u8>>) -> Result<PendingInvoke> {
+        let result = dent_invoke(self.fd(), false, payload.into(), false, Default::default())?;
+        match result.fd {
+            Some(handle) if result.success => Ok(PendingInvoke { handle, collected: false }),
+            _ => Err(Error::Failed("dent_invoke"))
+        }
+    }
+}
+
+impl Service {
+    // sends a request to the service, with parameters filled into its templates
+    pub fn invoke(&self, payload: impl Into<Vec<u8>>, parameters: HashMap<String, String>) -> Result<Response> {
+        self.send(payload.into(), None, false, parameters)
+    }
+
+    // same as invoke, but the response body is written to a blob
+    pub fn invoke_to_blob(&self, payload: impl Into<Vec<u8>>, parameters: HashMap<String, String>) -> Result<Response> {
+        self.send(payload.into(), None, true, parameters)
+    }
+
+    // sends a request to the service with a blob streamed as the body
+    pub fn upload(&self, body: &Blob, parameters: HashMap<String, String>) -> Result<Response> {
+        self.send(Vec::new(), Some(body.fd()), false, parameters)
+    }
+
+    fn send(&self, payload: Vec<u8>, payload_blob: Option<u64>, toblob: bool, parameters: HashMap<String, String>) -> Result<Response> {
+        let dent_invoke_blobs = DentInvokeBlobs {
+            invoke: Some(DentInvoke { fd: self.fd(), sync: true, payload, toblob, parameters }),
+            payload_blob,
+            blobs: Vec::new(),
+            timeout_ms: None,
+        };
+        Response::new(call(unsafe { host::service_invoke_pb(Prost(dent_invoke_blobs)) })?)
+    }
+}
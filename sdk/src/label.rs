@@ -0,0 +1,35 @@
+/*
+    Helpers for the function's current label. Reading secret data raises (taints) the current
+    label. A function can't lower its own current label, only the runtime does, e.g while invoking
+    a service with the service's privilege.
+ */
+use extism_pdk::Prost;
+use labeled::buckle::{Buckle, Component};
+
+use crate::error::{call, Error, Result};
+use crate::host;
+
+// the function's current label
+pub fn current() -> Result<Buckle> {
+    call(unsafe { host::get_current_label_pb() })
+}
+
+// parses a label, e.g "alice,true"
+pub fn parse(label: &str) -> Result<Buckle> {
+    let maybe_label = call(unsafe { host::buckle_parse_pb(label) })?;
+    maybe_label.label.ok_or(Error::Failed("buckle_parse"))
+}
+
+// raises the current label to its lub with label. Returns the new current label
+pub fn taint(label: Buckle) -> Result<Buckle> {
+    call(unsafe { host::taint_with_label_pb(Prost(label)) })
+}
+
+/*
+    The current label with its secrecy lowered to secrecy, if the function's privilege allows it,
+    or the current label unchanged if it doesn't. Only computes the label: the runtime doesn't
+    lower the current label, so the function stays tainted.
+ */
+pub fn declassify(secrecy: Component) -> Result<Buckle> {
+    call(unsafe { host::declassify_pb(Prost(secrecy)) })
+}
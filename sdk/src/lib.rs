@@ -0,0 +1,47 @@
+/*
+    Guest SDK for Faasten functions. Wraps the runtime's cloudcalls in typed handles that
+    close themselves when dropped, and in Result-returning methods.
+
+    Start from the root directory with Dir::root(), open entries by name and convert them to
+    the handle of their kind, e.g Dir::root().open("config")?.into_file()?.read()?.
+
+    The function itself is a plain fn(Input) -> Result<Output, E> marked #[faasten::function],
+    which exports it as the function's run entry point.
+
+    Built for a native target, the SDK runs against the in-memory host in mock, so the same
+    function code can be unit-tested with cargo test.
+ */
+mod blob;
+mod dent;
+mod entry;
+mod error;
+#[cfg(target_arch = "wasm32")]
+mod host;
+mod invoke;
+pub mod label;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod mock;
+
+// lets the mock's tests use #[faasten::function], which refers to this crate as ::faasten
+#[cfg(test)]
+extern crate self as faasten;
+
+// natively, the cloudcalls are served by the mock host
+#[cfg(not(target_arch = "wasm32"))]
+use mock::host;
+
+pub use blob::{input_blobs, Blob, NewBlob};
+pub use dent::{BlobEntry, Dent, Dir, Entry, FacetedDir, File, Gate, Service};
+pub use error::{Error, Result};
+pub use invoke::{PendingInvoke, Response};
+
+pub use faasten_interface_types::DentKind;
+pub use faasten_runtime_interface::BlobStat;
+pub use labeled::buckle::{Buckle, Component};
+pub use faasten_macros::function;
+
+// used by the code #[faasten::function] expands to
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::entry::{run_bytes, run_json};
+}
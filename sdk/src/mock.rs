@@ -0,0 +1,1028 @@
+/*
+    In-process stand-in for the runtime, so functions can be unit-tested natively with cargo test.
+    On non-wasm targets the SDK's handles call into this module instead of the runtime's cloudcalls.
+
+    Objects live in an in-memory file system with the same label checks as the runtime: reading
+    an object taints the current label with the object's label, writing requires the current
+    label to flow to the object's, and lowering labels takes privilege. Gates and services are
+    Rust closures linked with link_gate and link_service. Each thread has a host of its own, so
+    tests running in parallel don't see each other's objects.
+ */
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+use faasten_interface_types::{dent_create, dent_open, dent_update, BlobFinalize, BlobResult, BlobWrite, DentCreate,
+    DentInvoke, DentInvokeResult, DentKind, DentLink, DentListResult, DentLsFaceted, DentLsFacetedResult, DentOpen,
+    DentOpenResult, DentResult, DentUnlink, DentUpdate};
+use faasten_runtime_interface::{BlobSeek, BlobStat, DentInvokeBlobs, InvokeHandleResult, SeekWhence, ServiceInvokeResult};
+use labeled::{buckle::{Buckle, Component}, HasPrivilege, Label};
+
+// fd of the root directory, and its index in the objects
+const ROOT_FD: u64 = 0;
+const ROOT: usize = 0;
+
+// same default length as the runtime's blob reads
+const DEFAULT_READ_LENGTH: u64 = 4096;
+
+thread_local! {
+    static HOST: RefCell<MockHost> = RefCell::new(MockHost::new());
+}
+
+type GateHandler = Rc<dyn Fn(Vec<u8>) -> Result<Vec<u8>, String>>;
+type ServiceHandler = Rc<dyn Fn(HashMap<String, String>, Vec<u8>) -> (u16, Vec<u8>)>;
+
+enum Object {
+    Directory(HashMap<String, usize>),
+    File(Vec<u8>),
+    // the facets' labels and their directories
+    FacetedDirectory(Vec<(Buckle, usize)>),
+    // the gate's function and the label of its results
+    Gate(GateHandler, Buckle),
+    // the service's handler and the label its responses taint the function with
+    Service(ServiceHandler, Buckle),
+    Blob(Rc<Vec<u8>>),
+}
+
+struct MockHost {
+    label: Buckle,
+    privilege: Component,
+    // every object with its label, indexed by the dents table
+    objects: Vec<(Buckle, Object)>,
+    dents: HashMap<u64, usize>,
+    max_dent_id: u64,
+    // finalized blobs with their cursors, and blobs being written
+    blobs: HashMap<u64, (Rc<Vec<u8>>, u64)>,
+    create_blobs: HashMap<u64, Vec<u8>>,
+    max_blob_id: u64,
+    input_blobs: Vec<u64>,
+    // results of asynchronous invocations and the labels they taint the function with when collected
+    invoke_results: HashMap<u64, (DentInvokeResult, Buckle)>,
+    max_invoke_id: u64,
+    input: Vec<u8>,
+    output: Option<Vec<u8>>,
+    error: Option<String>,
+}
+
+fn with_host<T>(f: impl FnOnce(&mut MockHost) -> T) -> T {
+    HOST.with(|host| f(&mut host.borrow_mut()))
+}
+
+/*
+    Replaces this thread's host with an empty one: an empty root directory, the public label and no
+    privilege, i.e Component::dc_true(), which implies no other component. Component::dc_false() is
+    the opposite, it implies every component and so allows any declassification.
+ */
+pub fn reset() {
+    with_host(|host| *host = MockHost::new());
+}
+
+pub fn set_label(label: Buckle) {
+    with_host(|host| host.label = label);
+}
+
+pub fn set_privilege(privilege: Component) {
+    with_host(|host| host.privilege = privilege);
+}
+
+// payload read by a #[faasten::function] export
+pub fn set_input(payload: impl Into<Vec<u8>>) {
+    with_host(|host| host.input = payload.into());
+}
+
+// output written by a #[faasten::function] export
+pub fn output() -> Option<Vec<u8>> {
+    with_host(|host| host.output.clone())
+}
+
+// error set by a failed #[faasten::function] export
+pub fn error() -> Option<String> {
+    with_host(|host| host.error.clone())
+}
+
+// blobs the function finds with input_blobs, as if passed by its caller
+pub fn set_input_blobs(blobs: Vec<Vec<u8>>) {
+    with_host(|host| {
+        let fds = blobs.into_iter().map(|contents| host.open_blob(Rc::new(contents))).collect();
+        host.input_blobs = fds;
+    });
+}
+
+/*
+    Test setup and inspection. These create missing directories along path (with the public
+    label) and bypass label checks.
+ */
+pub fn write_file(path: &str, label: Buckle, data: impl Into<Vec<u8>>) {
+    with_host(|host| host.link_at(path, label, Object::File(data.into())));
+}
+
+pub fn read_file(path: &str) -> Option<Vec<u8>> {
+    with_host(|host| match host.lookup(path).map(|object| &host.objects[object].1) {
+        Some(Object::File(data)) => Some(data.clone()),
+        _ => None
+    })
+}
+
+// links a gate whose function is handler. Invoking it taints the function with result_label
+pub fn link_gate(path: &str, result_label: Buckle, handler: impl Fn(Vec<u8>) -> Result<Vec<u8>, String> + 'static) {
+    with_host(|host| host.link_at(path, Buckle::public(), Object::Gate(Rc::new(handler), result_label)));
+}
+
+// links a service answered by handler, which gets the parameters and payload and returns a status and body
+pub fn link_service(
+    path: &str,
+    taint: Buckle,
+    handler: impl Fn(HashMap<String, String>, Vec<u8>) -> (u16, Vec<u8>) + 'static
+) {
+    with_host(|host| host.link_at(path, Buckle::public(), Object::Service(Rc::new(handler), taint)));
+}
+
+impl MockHost {
+    fn new() -> Self {
+        Self {
+            label: Buckle::public(),
+            privilege: Component::dc_true(),
+            objects: vec![(Buckle::public(), Object::Directory(HashMap::new()))],
+            dents: HashMap::from([(ROOT_FD, ROOT)]),
+            max_dent_id: ROOT_FD + 1,
+            blobs: HashMap::new(),
+            create_blobs: HashMap::new(),
+            max_blob_id: 0,
+            input_blobs: Vec::new(),
+            invoke_results: HashMap::new(),
+            max_invoke_id: 0,
+            input: Vec::new(),
+            output: None,
+            error: None,
+        }
+    }
+
+    fn taint(&mut self, label: &Buckle) {
+        self.label = self.label.clone().lub(label.clone());
+    }
+
+    // reading an object taints the current label with the object's label
+    fn read(&mut self, object: usize) {
+        let label = self.objects[object].0.clone();
+        self.taint(&label);
+    }
+
+    // writing an object requires the current label to flow to the object's label
+    fn can_write(&self, object: usize) -> bool {
+        self.label.can_flow_to(&self.objects[object].0)
+    }
+
+    fn add(&mut self, label: Buckle, object: Object) -> usize {
+        self.objects.push((label, object));
+        self.objects.len() - 1
+    }
+
+    fn open(&mut self, object: usize) -> u64 {
+        let fd = self.max_dent_id;
+        self.max_dent_id += 1;
+        self.dents.insert(fd, object);
+        fd
+    }
+
+    fn open_blob(&mut self, contents: Rc<Vec<u8>>) -> u64 {
+        let fd = self.max_blob_id;
+        self.max_blob_id += 1;
+        self.blobs.insert(fd, (contents, 0));
+        fd
+    }
+
+    fn kind(&self, object: usize) -> DentKind {
+        match &self.objects[object].1 {
+            Object::Directory(_) => DentKind::DentDirectory,
+            Object::File(_) => DentKind::DentFile,
+            Object::FacetedDirectory(_) => DentKind::DentFacetedDirectory,
+            Object::Gate(..) => DentKind::DentGate,
+            Object::Service(..) => DentKind::DentService,
+            Object::Blob(_) => DentKind::DentBlob,
+        }
+    }
+
+    fn entries(&mut self, dir: usize) -> Option<&mut HashMap<String, usize>> {
+        match &mut self.objects[dir].1 {
+            Object::Directory(entries) => Some(entries),
+            _ => None
+        }
+    }
+
+    fn lookup(&self, path: &str) -> Option<usize> {
+        path.split('/').filter(|name| !name.is_empty()).try_fold(ROOT, |dir, name| match &self.objects[dir].1 {
+            Object::Directory(entries) => entries.get(name).copied(),
+            _ => None
+        })
+    }
+
+    fn link_at(&mut self, path: &str, label: Buckle, object: Object) {
+        let mut names: Vec<&str> = path.split('/').filter(|name| !name.is_empty()).collect();
+        let name = names.pop().expect("empty path");
+
+        let mut dir = ROOT;
+        for dir_name in names {
+            dir = match self.entries(dir).and_then(|entries| entries.get(dir_name).copied()) {
+                Some(next) => next,
+                None => {
+                    let next = self.add(Buckle::public(), Object::Directory(HashMap::new()));
+                    self.entries(dir).expect("not a directory").insert(dir_name.to_string(), next);
+                    next
+                }
+            };
+        }
+
+        let object = self.add(label, object);
+        self.entries(dir).expect("not a directory").insert(name.to_string(), object);
+    }
+
+    // the facet of fdir with the given label, created if it doesn't exist yet
+    fn facet(&mut self, fdir: usize, label: Buckle) -> usize {
+        if let Object::FacetedDirectory(facets) = &self.objects[fdir].1 {
+            if let Some((_, dir)) = facets.iter().find(|(facet_label, _)| *facet_label == label) {
+                return *dir;
+            }
+        }
+
+        let dir = self.add(label.clone(), Object::Directory(HashMap::new()));
+        if let Object::FacetedDirectory(facets) = &mut self.objects[fdir].1 {
+            facets.push((label, dir));
+        }
+        dir
+    }
+
+    fn dent_open(&mut self, dent_open: DentOpen) -> DentOpenResult {
+        let DentOpen { fd, entry } = dent_open;
+        let failed = DentOpenResult { success: false, fd: 0, kind: DentKind::DentDirectory.into() };
+
+        let Some(base) = self.dents.get(&fd).copied() else {
+            return failed;
+        };
+        let target = match (self.kind(base), entry) {
+            (DentKind::DentDirectory, Some(dent_open::Entry::Name(name))) => {
+                self.read(base);
+                self.entries(base).and_then(|entries| entries.get(&name).copied())
+            }
+            (DentKind::DentFacetedDirectory, Some(dent_open::Entry::Facet(label))) => Some(self.facet(base, label.into())),
+            (DentKind::DentFacetedDirectory, Some(dent_open::Entry::Name(label))) => {
+                Buckle::parse(&label).ok().map(|label| self.facet(base, label))
+            }
+            _ => None
+        };
+
+        match target {
+            Some(target) => DentOpenResult { success: true, fd: self.open(target), kind: self.kind(target).into() },
+            None => failed
+        }
+    }
+
+    // same as the runtime, the current label must flow to the new object's label
+    fn dent_create(&mut self, dent_create: DentCreate) -> DentResult {
+        let DentCreate { label, kind } = dent_create;
+        let label = label.unwrap_or(Buckle::public());
+        if !self.label.can_flow_to(&label) {
+            return DentResult { success: false, fd: None, data: None };
+        }
+
+        // gates and services are linked with link_gate and link_service instead
+        let object = match kind {
+            Some(dent_create::Kind::Directory) => Some(Object::Directory(HashMap::new())),
+            Some(dent_create::Kind::File) => Some(Object::File(Vec::new())),
+            Some(dent_create::Kind::FacetedDirectory) => Some(Object::FacetedDirectory(Vec::new())),
+            Some(dent_create::Kind::Blob(blobfd)) => self.blobs.get(&blobfd).map(|(contents, _)| Object::Blob(contents.clone())),
+            _ => None
+        };
+
+        match object {
+            Some(object) => {
+                let object = self.add(label, object);
+                DentResult { success: true, fd: Some(self.open(object)), data: None }
+            }
+            None => DentResult { success: false, fd: None, data: None }
+        }
+    }
+
+    fn dent_update(&mut self, dent_update: DentUpdate) -> DentResult {
+        let DentUpdate { fd, kind } = dent_update;
+        let Some(object) = self.dents.get(&fd).copied().filter(|object| self.can_write(*object)) else {
+            return DentResult { success: false, fd: None, data: None };
+        };
+
+        let updated = match (&mut self.objects[object].1, kind) {
+            (Object::File(data), Some(dent_update::Kind::File(new_data))) => {
+                *data = new_data;
+                true
+            }
+            (Object::Blob(contents), Some(dent_update::Kind::Blob(blobfd))) => match self.blobs.get(&blobfd) {
+                Some((new_contents, _)) => {
+                    *contents = new_contents.clone();
+                    true
+                }
+                None => false
+            }
+            _ => false
+        };
+        DentResult { success: updated, fd: Some(fd), data: None }
+    }
+
+    /*
+        Same as the runtime, raising is always allowed and lowering requires sufficient privilege. Reading the
+        object taints the current label, and lowering the object's label never lowers the current label.
+     */
+    fn dent_relabel(&mut self, fd: u64, new_label: Buckle) -> DentResult {
+        let failed = DentResult { success: false, fd: None, data: None };
+        let Some(object) = self.dents.get(&fd).copied() else {
+            return failed;
+        };
+        if self.kind(object) == DentKind::DentFacetedDirectory {
+            return failed;
+        }
+
+        self.read(object);
+        if !self.label.can_flow_to_with_privilege(&new_label, &self.privilege) {
+            return failed;
+        }
+
+        self.objects[object].0 = new_label;
+        DentResult { success: true, fd: Some(fd), data: None }
+    }
+
+    fn dent_read(&mut self, fd: u64) -> DentResult {
+        let data = self.dents.get(&fd).copied().and_then(|object| {
+            self.read(object);
+            match &self.objects[object].1 {
+                Object::File(data) => Some(data.clone()),
+                _ => None
+            }
+        });
+        DentResult { success: data.is_some(), fd: Some(fd), data }
+    }
+
+    fn dent_link(&mut self, dent_link: DentLink) -> DentResult {
+        let DentLink { dir_fd, name, target_fd } = dent_link;
+        let linked = match (self.dents.get(&dir_fd).copied(), self.dents.get(&target_fd).copied()) {
+            (Some(dir), Some(target)) if self.can_write(dir) => match self.entries(dir) {
+                Some(entries) if !entries.contains_key(&name) => entries.insert(name, target).is_none(),
+                _ => false
+            },
+            _ => false
+        };
+        DentResult { success: linked, fd: Some(dir_fd), data: None }
+    }
+
+    fn dent_unlink(&mut self, dent_unlink: DentUnlink) -> DentResult {
+        let DentUnlink { dir_fd, name } = dent_unlink;
+        let unlinked = match self.dents.get(&dir_fd).copied() {
+            Some(dir) if self.can_write(dir) => self.entries(dir).and_then(|entries| entries.remove(&name)).is_some(),
+            _ => false
+        };
+        DentResult { success: unlinked, fd: Some(dir_fd), data: None }
+    }
+
+    fn dent_list(&mut self, fd: u64) -> DentListResult {
+        let Some(dir) = self.dents.get(&fd).copied().filter(|dir| self.kind(*dir) == DentKind::DentDirectory) else {
+            return DentListResult { success: false, entries: Default::default() };
+        };
+
+        self.read(dir);
+        let entries: Vec<(String, usize)> = self.entries(dir)
+            .map(|entries| entries.iter().map(|(name, object)| (name.clone(), *object)).collect())
+            .unwrap_or_default();
+        DentListResult {
+            success: true,
+            entries: entries.into_iter().map(|(name, object)| (name, self.kind(object) as i32)).collect(),
+        }
+    }
+
+    fn dent_ls_faceted(&mut self, dent_ls_faceted: DentLsFaceted) -> DentLsFacetedResult {
+        let DentLsFaceted { fd, clearance } = dent_ls_faceted;
+        let clearance: Buckle = clearance.map(Into::into).unwrap_or(Buckle::public());
+
+        match self.dents.get(&fd).map(|fdir| &self.objects[*fdir].1) {
+            Some(Object::FacetedDirectory(facets)) => DentLsFacetedResult {
+                success: true,
+                facets: facets.iter()
+                    .filter(|(label, _)| label.can_flow_to(&clearance))
+                    .map(|(label, _)| label.clone().into())
+                    .collect(),
+            },
+            _ => DentLsFacetedResult { success: false, facets: Default::default() }
+        }
+    }
+
+    fn invoke_result(&mut self, handle: u64) -> InvokeHandleResult {
+        match self.invoke_results.remove(&handle) {
+            Some((result, label)) => {
+                self.taint(&label);
+                InvokeHandleResult { success: true, ready: true, result: Some(result) }
+            }
+            None => InvokeHandleResult { success: false, ready: false, result: None }
+        }
+    }
+
+    fn invoke_cancel(&mut self, handle: u64) -> DentResult {
+        DentResult { success: self.invoke_results.remove(&handle).is_some(), fd: None, data: None }
+    }
+
+    fn dent_get_blob(&mut self, fd: u64) -> BlobResult {
+        let contents = self.dents.get(&fd).copied().and_then(|object| {
+            self.read(object);
+            match &self.objects[object].1 {
+                Object::Blob(contents) => Some(contents.clone()),
+                _ => None
+            }
+        });
+        match contents {
+            Some(contents) => {
+                let len = contents.len() as u64;
+                blob_result(true, self.open_blob(contents), len, None)
+            }
+            None => blob_result(false, 0, 0, None)
+        }
+    }
+
+    fn blob_create(&mut self) -> BlobResult {
+        let fd = self.max_blob_id;
+        self.max_blob_id += 1;
+        self.create_blobs.insert(fd, Vec::new());
+        blob_result(true, fd, 0, None)
+    }
+
+    fn blob_write(&mut self, blob_write: BlobWrite) -> BlobResult {
+        let BlobWrite { fd, data } = blob_write;
+        match self.create_blobs.get_mut(&fd) {
+            Some(contents) => {
+                contents.extend_from_slice(&data);
+                let len = contents.len() as u64;
+                blob_result(true, fd, len, None)
+            }
+            None => blob_result(false, fd, 0, None)
+        }
+    }
+
+    fn blob_finalize(&mut self, blob_finalize: BlobFinalize) -> BlobResult {
+        let BlobFinalize { fd } = blob_finalize;
+        match self.create_blobs.remove(&fd) {
+            Some(contents) => {
+                let len = contents.len() as u64;
+                self.blobs.insert(fd, (Rc::new(contents), 0));
+                blob_result(true, fd, len, None)
+            }
+            None => blob_result(false, fd, 0, None)
+        }
+    }
+
+    fn blob_seek(&mut self, blob_seek: BlobSeek) -> BlobResult {
+        let BlobSeek { fd, offset, whence } = blob_seek;
+        let position = self.blobs.get_mut(&fd).and_then(|(contents, cursor)| {
+            let base = match SeekWhence::try_from(whence).ok()? {
+                SeekWhence::Start => 0,
+                SeekWhence::Current => *cursor,
+                SeekWhence::End => contents.len() as u64,
+            };
+            *cursor = base.checked_add_signed(offset)?;
+            Some(*cursor)
+        });
+        blob_result(position.is_some(), fd, position.unwrap_or(0), None)
+    }
+
+    // reads at most length bytes from the cursor on (to the end if None) and moves the cursor past them
+    fn blob_read_from_cursor(&mut self, fd: u64, length: Option<u64>) -> BlobResult {
+        let data = self.blobs.get_mut(&fd).map(|(contents, cursor)| {
+            let start = (*cursor as usize).min(contents.len());
+            let end = match length {
+                Some(length) => start.saturating_add(length as usize).min(contents.len()),
+                None => contents.len()
+            };
+            *cursor = end as u64;
+            contents[start..end].to_vec()
+        });
+        match data {
+            Some(data) => blob_result(true, fd, data.len() as u64, Some(data)),
+            None => blob_result(false, fd, 0, None)
+        }
+    }
+
+    fn file_to_blob(&mut self, fd: u64) -> BlobResult {
+        let DentResult { data, .. } = self.dent_read(fd);
+        match data {
+            Some(data) => {
+                let len = data.len() as u64;
+                blob_result(true, self.open_blob(Rc::new(data)), len, None)
+            }
+            None => blob_result(false, 0, 0, None)
+        }
+    }
+
+    fn blob_to_file(&mut self, blob_fd: u64, file_fd: u64) -> DentResult {
+        let contents = self.blobs.get(&blob_fd).map(|(contents, _)| contents.to_vec());
+        match contents {
+            Some(contents) => self.dent_update(DentUpdate { fd: file_fd, kind: Some(dent_update::Kind::File(contents)) }),
+            None => DentResult { success: false, fd: None, data: None }
+        }
+    }
+
+    // the runtime names blobs by their content hash. The mock uses a hash of its own, which differs from the blobstore's
+    fn blob_stat(&self, fd: u64) -> BlobStat {
+        if let Some((contents, _)) = self.blobs.get(&fd) {
+            return BlobStat { success: true, fd, name: Some(blob_name(contents)), len: contents.len() as u64, finalized: true };
+        }
+        match self.create_blobs.get(&fd) {
+            Some(contents) => BlobStat { success: true, fd, name: Some(blob_name(contents)), len: contents.len() as u64, finalized: false },
+            None => BlobStat { success: false, fd, name: None, len: 0, finalized: false }
+        }
+    }
+
+    fn blob_close(&mut self, fd: u64) -> BlobResult {
+        let closed = self.blobs.remove(&fd).is_some() || self.create_blobs.remove(&fd).is_some();
+        blob_result(closed, fd, 0, None)
+    }
+
+    fn blob_abort(&mut self, fd: u64) -> BlobResult {
+        let aborted = self.create_blobs.remove(&fd).is_some();
+        blob_result(aborted, fd, 0, None)
+    }
+}
+
+// a stand-in for the blobstore's content hash, which the mock doesn't need to match
+fn blob_name(contents: &[u8]) -> String {
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+fn blob_result(success: bool, fd: u64, len: u64, data: Option<Vec<u8>>) -> BlobResult {
+    BlobResult { success, fd, len, data }
+}
+
+// what an invocation targets, taken out of the host so handlers can make cloudcalls of their own
+enum Target {
+    Gate(GateHandler, Buckle),
+    Service(ServiceHandler, Buckle),
+}
+
+fn invoke(dent_invoke: DentInvoke) -> ServiceInvokeResult {
+    let DentInvoke { fd, sync, payload, toblob, parameters } = dent_invoke;
+    let failed = ServiceInvokeResult::default();
+
+    let target = with_host(|host| host.dents.get(&fd).and_then(|object| match &host.objects[*object].1 {
+        Object::Gate(handler, label) => Some(Target::Gate(handler.clone(), label.clone())),
+        Object::Service(handler, taint) => Some(Target::Service(handler.clone(), taint.clone())),
+        _ => None
+    }));
+
+    // services report their status and content length, same as the runtime. Mock services have no url
+    let (success, body, status, label) = match target {
+        Some(Target::Gate(handler, label)) => match handler(payload) {
+            Ok(body) => (true, body, None, label),
+            Err(e) => (false, e.into_bytes(), None, label)
+        },
+        Some(Target::Service(handler, taint)) => {
+            let (status, body) = handler(parameters, payload);
+            (true, body, Some(status.into()), taint)
+        }
+        None => return failed
+    };
+    let content_length = status.map(|_| body.len() as u64);
+
+    with_host(|host| {
+        let result = if success && toblob {
+            ServiceInvokeResult { success, fd: Some(host.open_blob(Rc::new(body))), status, content_length, ..Default::default() }
+        } else {
+            ServiceInvokeResult { success, data: Some(body), status, content_length, ..Default::default() }
+        };
+
+        if sync {
+            host.taint(&label);
+            return result;
+        }
+        let handle = host.max_invoke_id;
+        host.max_invoke_id += 1;
+        host.invoke_results.insert(handle, (result.into(), label));
+        ServiceInvokeResult { success: true, fd: Some(handle), ..Default::default() }
+    })
+}
+
+// blobs passed to a mock gate aren't delivered to it, but they must be open finalized blobs
+fn invoke_blobs(dent_invoke_blobs: DentInvokeBlobs) -> ServiceInvokeResult {
+    let DentInvokeBlobs { invoke: dent_invoke, payload_blob, blobs, .. } = dent_invoke_blobs;
+    let failed = ServiceInvokeResult::default();
+
+    let Some(mut dent_invoke) = dent_invoke else {
+        return failed;
+    };
+    let payload = with_host(|host| {
+        if !blobs.iter().all(|fd| host.blobs.contains_key(fd)) {
+            return Err(());
+        }
+        payload_blob.map(|fd| host.blobs.get(&fd).map(|(contents, _)| contents.to_vec()).ok_or(())).transpose()
+    });
+
+    match payload {
+        Ok(payload) => {
+            if let Some(payload) = payload {
+                dent_invoke.payload = payload;
+            }
+            invoke(dent_invoke)
+        }
+        Err(()) => failed
+    }
+}
+
+/*
+    The cloudcalls, with the same signatures as the runtime's imports so the SDK's handles
+    work unchanged.
+ */
+pub(crate) mod host {
+    use extism_pdk::Prost;
+    use faasten_interface_types::{BlobClose, BlobFinalize, BlobResult, BlobWrite, DentCreate, DentInvoke,
+        DentInvokeResult, DentLink, DentListResult, DentLsFaceted, DentLsFacetedResult, DentOpen, DentOpenResult,
+        DentResult, DentUnlink, DentUpdate};
+    use faasten_runtime_interface::{BlobReadNext, BlobSeek, BlobStat, DentInvokeBlobs, InputBlobs, InvokeAwait,
+        InvokeHandleResult, MaybeBuckle, ServiceInvokeResult};
+    use labeled::{buckle::{Buckle, Component}, HasPrivilege};
+
+    use super::{invoke, invoke_blobs, with_host, Object, DEFAULT_READ_LENGTH};
+
+    type Call<T> = Result<Prost<T>, extism_pdk::Error>;
+
+    fn ok<T>(value: T) -> Call<T> {
+        Ok(Prost(value))
+    }
+
+    pub unsafe fn get_current_label_pb() -> Call<Buckle> {
+        ok(with_host(|host| host.label.clone()))
+    }
+
+    pub unsafe fn buckle_parse_pb(input_str: &str) -> Call<MaybeBuckle> {
+        ok(MaybeBuckle { label: Buckle::parse(input_str).ok() })
+    }
+
+    pub unsafe fn taint_with_label_pb(input_label: Prost<Buckle>) -> Call<Buckle> {
+        ok(with_host(|host| {
+            host.taint(&input_label.0);
+            host.label.clone()
+        }))
+    }
+
+    /*
+        Same as the runtime, returns the declassified label, or the current label if the privilege
+        doesn't allow it. The current label itself is never changed.
+     */
+    pub unsafe fn declassify_pb(target_secrecy: Prost<Component>) -> Call<Buckle> {
+        ok(with_host(|host| {
+            let target = Buckle { secrecy: target_secrecy.0, integrity: host.label.integrity.clone() };
+            if host.label.can_flow_to_with_privilege(&target, &host.privilege) {
+                target
+            } else {
+                host.label.clone()
+            }
+        }))
+    }
+
+    pub unsafe fn dent_open_pb(dent_open: Prost<DentOpen>) -> Call<DentOpenResult> {
+        ok(with_host(|host| host.dent_open(dent_open.0)))
+    }
+
+    pub unsafe fn dent_create_pb(dent_create: Prost<DentCreate>) -> Call<DentResult> {
+        ok(with_host(|host| host.dent_create(dent_create.0)))
+    }
+
+    pub unsafe fn dent_close_pb(input_fd: u64) -> Call<DentResult> {
+        ok(with_host(|host| DentResult { success: host.dents.remove(&input_fd).is_some(), fd: None, data: None }))
+    }
+
+    pub unsafe fn dent_update_pb(dent_update: Prost<DentUpdate>) -> Call<DentResult> {
+        ok(with_host(|host| host.dent_update(dent_update.0)))
+    }
+
+    pub unsafe fn dent_relabel_pb(fd: u64, new_label: Prost<Buckle>) -> Call<DentResult> {
+        ok(with_host(|host| host.dent_relabel(fd, new_label.0)))
+    }
+
+    pub unsafe fn dent_read_pb(fd: u64) -> Call<DentResult> {
+        ok(with_host(|host| host.dent_read(fd)))
+    }
+
+    pub unsafe fn dent_link_pb(dent_link: Prost<DentLink>) -> Call<DentResult> {
+        ok(with_host(|host| host.dent_link(dent_link.0)))
+    }
+
+    pub unsafe fn dent_unlink_pb(dent_unlink: Prost<DentUnlink>) -> Call<DentResult> {
+        ok(with_host(|host| host.dent_unlink(dent_unlink.0)))
+    }
+
+    pub unsafe fn dent_list_pb(fd: u64) -> Call<DentListResult> {
+        ok(with_host(|host| host.dent_list(fd)))
+    }
+
+    pub unsafe fn dent_ls_faceted_pb(dent_ls_faceted: Prost<DentLsFaceted>) -> Call<DentLsFacetedResult> {
+        ok(with_host(|host| host.dent_ls_faceted(dent_ls_faceted.0)))
+    }
+
+    pub unsafe fn dent_invoke_pb(dent_invoke: Prost<DentInvoke>) -> Call<DentInvokeResult> {
+        ok(invoke(dent_invoke.0).into())
+    }
+
+    pub unsafe fn dent_invoke_blobs_pb(dent_invoke_blobs: Prost<DentInvokeBlobs>) -> Call<DentInvokeResult> {
+        ok(invoke_blobs(dent_invoke_blobs.0).into())
+    }
+
+    pub unsafe fn service_invoke_pb(dent_invoke_blobs: Prost<DentInvokeBlobs>) -> Call<ServiceInvokeResult> {
+        let fd = dent_invoke_blobs.0.invoke.as_ref().map(|invoke| invoke.fd);
+        let is_service = with_host(|host| fd.and_then(|fd| host.dents.get(&fd)).is_some_and(|object| matches!(host.objects[*object].1, Object::Service(..))));
+        if !is_service {
+            return ok(ServiceInvokeResult::default());
+        }
+        ok(invoke_blobs(dent_invoke_blobs.0))
+    }
+
+    // mock invocations finish right away, so results are always ready
+    pub unsafe fn invoke_poll_pb(handle: u64) -> Call<InvokeHandleResult> {
+        ok(with_host(|host| host.invoke_result(handle)))
+    }
+
+    pub unsafe fn invoke_await_pb(invoke_await: Prost<InvokeAwait>) -> Call<InvokeHandleResult> {
+        ok(with_host(|host| host.invoke_result(invoke_await.0.handle)))
+    }
+
+    pub unsafe fn invoke_cancel_pb(handle: u64) -> Call<DentResult> {
+        ok(with_host(|host| host.invoke_cancel(handle)))
+    }
+
+    pub unsafe fn input_blobs_pb() -> Call<InputBlobs> {
+        ok(with_host(|host| InputBlobs { fds: host.input_blobs.clone() }))
+    }
+
+    pub unsafe fn dent_get_blob_pb(fd: u64) -> Call<BlobResult> {
+        ok(with_host(|host| host.dent_get_blob(fd)))
+    }
+
+    pub unsafe fn blob_create_pb() -> Call<BlobResult> {
+        ok(with_host(|host| host.blob_create()))
+    }
+
+    pub unsafe fn blob_write_pb(blob_write: Prost<BlobWrite>) -> Call<BlobResult> {
+        ok(with_host(|host| host.blob_write(blob_write.0)))
+    }
+
+    pub unsafe fn blob_finalize_pb(blob_finalize: Prost<BlobFinalize>) -> Call<BlobResult> {
+        ok(with_host(|host| host.blob_finalize(blob_finalize.0)))
+    }
+
+    pub unsafe fn blob_seek_pb(blob_seek: Prost<BlobSeek>) -> Call<BlobResult> {
+        ok(with_host(|host| host.blob_seek(blob_seek.0)))
+    }
+
+    pub unsafe fn blob_read_next_pb(blob_read_next: Prost<BlobReadNext>) -> Call<BlobResult> {
+        let BlobReadNext { fd, length } = blob_read_next.0;
+        ok(with_host(|host| host.blob_read_from_cursor(fd, Some(length.unwrap_or(DEFAULT_READ_LENGTH)))))
+    }
+
+    pub unsafe fn blob_read_to_end_pb(fd: u64) -> Call<BlobResult> {
+        ok(with_host(|host| host.blob_read_from_cursor(fd, None)))
+    }
+
+    pub unsafe fn file_to_blob_pb(fd: u64) -> Call<BlobResult> {
+        ok(with_host(|host| host.file_to_blob(fd)))
+    }
+
+    pub unsafe fn blob_to_file_pb(blob_fd: u64, file_fd: u64) -> Call<DentResult> {
+        ok(with_host(|host| host.blob_to_file(blob_fd, file_fd)))
+    }
+
+    pub unsafe fn blob_stat_pb(fd: u64) -> Call<BlobStat> {
+        ok(with_host(|host| host.blob_stat(fd)))
+    }
+
+    pub unsafe fn blob_close_pb(blob_close: Prost<BlobClose>) -> Call<BlobResult> {
+        ok(with_host(|host| host.blob_close(blob_close.0.fd)))
+    }
+
+    pub unsafe fn blob_abort_pb(fd: u64) -> Call<BlobResult> {
+        ok(with_host(|host| host.blob_abort(fd)))
+    }
+
+    pub fn blob_read_at(fd: u64, offset: u64, buf: &mut [u8]) -> Result<Option<usize>, extism_pdk::Error> {
+        Ok(with_host(|host| {
+            let (contents, _) = host.blobs.get(&fd)?;
+            let start = usize::try_from(offset).unwrap_or(usize::MAX).min(contents.len());
+            let n = buf.len().min(contents.len() - start);
+            buf[..n].copy_from_slice(&contents[start..start + n]);
+            Some(n)
+        }))
+    }
+
+    pub fn input() -> Result<Vec<u8>, extism_pdk::Error> {
+        Ok(with_host(|host| host.input.clone()))
+    }
+
+    pub fn output(data: Vec<u8>) -> Result<(), extism_pdk::Error> {
+        with_host(|host| host.output = Some(data));
+        Ok(())
+    }
+
+    pub fn set_error(error: &str) {
+        with_host(|host| host.error = Some(error.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Seek, SeekFrom, Write};
+    use crate::{label, BlobEntry, Dent, Dir, Error, FacetedDir, File, NewBlob};
+
+    // an example function, greeting the user named in the file at its input path through the greeter gate
+    #[faasten::function]
+    fn greet(path: String) -> crate::Result<String> {
+        let name = Dir::root().open_dir("users")?.open_file(&path)?.read()?;
+        let greeting = Dir::root().open("greeter")?.into_gate()?.invoke(name)?;
+        Ok(String::from_utf8_lossy(&greeting.data).into_owned())
+    }
+
+    fn alice() -> Buckle {
+        Buckle::parse("alice,true").unwrap()
+    }
+
+    fn set_up() {
+        reset();
+        write_file("users/alice", alice(), "Alice");
+        link_gate("greeter", Buckle::public(), |name| Ok([b"hello ".as_slice(), &name].concat()));
+    }
+
+    #[test]
+    fn functions_run_against_the_mock() {
+        set_up();
+        set_input(r#""alice""#);
+
+        assert_eq!(__faasten_run(), 0);
+        assert_eq!(output(), Some(br#""hello Alice""#.to_vec()));
+        assert_eq!(error(), None);
+        // reading alice's file tainted the function
+        assert_eq!(label::current().unwrap(), alice());
+    }
+
+    #[test]
+    fn failed_functions_set_their_error() {
+        set_up();
+        set_input(r#""bob""#);
+
+        assert_eq!(__faasten_run(), crate::entry::FAILURE);
+        assert_eq!(output(), None);
+        assert!(error().is_some());
+    }
+
+    #[test]
+    fn declassify_leaves_the_current_label() {
+        reset();
+        set_label(alice());
+
+        // no privilege by default, so nothing can be declassified
+        assert_eq!(label::declassify(Component::dc_true()).unwrap(), alice());
+        set_privilege(Component::dc_false());
+        assert_eq!(label::declassify(Component::dc_true()).unwrap(), Buckle::public());
+        assert_eq!(label::current().unwrap(), alice());
+    }
+
+    #[test]
+    fn created_objects_must_be_writable_at_the_current_label() {
+        reset();
+        set_label(alice());
+
+        assert!(File::create(Buckle::public()).is_err());
+        assert!(File::create(alice()).is_ok());
+    }
+
+    #[test]
+    fn relabeling_raises_freely_and_lowers_with_privilege() {
+        reset();
+        write_file("notes", Buckle::public(), "notes");
+
+        let notes = Dir::root().open_file("notes").unwrap();
+        assert!(notes.relabel(alice()).is_ok());
+        assert_eq!(label::current().unwrap(), Buckle::public());
+        // reading the relabeled object taints with its new label
+        notes.read().unwrap();
+        assert_eq!(label::current().unwrap(), alice());
+
+        reset();
+        write_file("secret", alice(), "secret");
+        let secret = Dir::root().open_file("secret").unwrap();
+        assert!(secret.relabel(Buckle::public()).is_err());
+        set_privilege(Component::dc_false());
+        assert!(secret.relabel(Buckle::public()).is_ok());
+        // relabeling read the object, and lowering it didn't lower the current label
+        assert_eq!(label::current().unwrap(), alice());
+
+        set_label(Buckle::public());
+        assert_eq!(secret.read().unwrap(), b"secret");
+        assert_eq!(label::current().unwrap(), Buckle::public());
+    }
+
+    #[test]
+    fn faceted_directories_have_a_directory_per_label() {
+        reset();
+        let fdir = FacetedDir::create().unwrap();
+        let facet = fdir.open(alice()).unwrap();
+        let file = File::create(alice()).unwrap();
+        facet.link("note", &file).unwrap();
+
+        // only facets that can flow to the clearance are listed, and listing them doesn't taint
+        assert!(fdir.list(None).unwrap().is_empty());
+        assert_eq!(fdir.list(Some(alice())).unwrap(), vec![alice()]);
+        assert_eq!(label::current().unwrap(), Buckle::public());
+
+        // listing a facet taints with its label, after which public facets can't be written
+        assert!(facet.list().unwrap().contains_key("note"));
+        assert_eq!(label::current().unwrap(), alice());
+        let public_facet = fdir.open(Buckle::public()).unwrap();
+        assert!(public_facet.link("note", &file).is_err());
+
+        // faceted directories can't be relabeled
+        assert!(fdir.relabel(alice()).is_err());
+    }
+
+    #[test]
+    fn blobs_taint_when_opened_from_the_file_system() {
+        reset();
+        let mut new_blob = NewBlob::create().unwrap();
+        new_blob.write_all(b"hello world").unwrap();
+        let mut blob = new_blob.finalize().unwrap();
+
+        let stat = blob.stat().unwrap();
+        assert!(stat.finalized);
+        assert_eq!(stat.len, 11);
+        let mut buf = [0; 5];
+        assert_eq!(blob.read_at(6, &mut buf).unwrap(), 5);
+        assert_eq!(&buf, b"world");
+        blob.seek(SeekFrom::Start(6)).unwrap();
+        let mut rest = String::new();
+        blob.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "world");
+
+        let file = File::create(Buckle::public()).unwrap();
+        let entry = BlobEntry::create(&blob, alice()).unwrap();
+        assert_eq!(label::current().unwrap(), Buckle::public());
+        let mut opened = entry.open().unwrap();
+        assert_eq!(opened.contents().unwrap(), b"hello world");
+        assert_eq!(label::current().unwrap(), alice());
+
+        // at alice's label, the blob can't be copied into a public file
+        assert!(opened.to_file(&file).is_err());
+    }
+
+    #[test]
+    fn async_invocations_taint_when_collected() {
+        reset();
+        link_gate("echo", alice(), |payload| Ok(payload));
+        let gate = Dir::root().open("echo").unwrap().into_gate().unwrap();
+
+        let mut pending = gate.spawn("ping").unwrap();
+        assert_eq!(label::current().unwrap(), Buckle::public());
+        let response = pending.wait(None).unwrap().unwrap();
+        assert_eq!(response.data, b"ping");
+        assert_eq!(label::current().unwrap(), alice());
+        // a result can only be collected once
+        assert!(pending.poll().is_err());
+
+        // cancelled invocations never taint
+        set_label(Buckle::public());
+        drop(gate.spawn("ping").unwrap());
+        assert_eq!(label::current().unwrap(), Buckle::public());
+    }
+
+    #[test]
+    fn services_taint_and_report_their_status() {
+        reset();
+        link_service("api", alice(), |parameters, _| (404, parameters["id"].clone().into_bytes()));
+        let service = Dir::root().open("api").unwrap().into_service().unwrap();
+
+        let response = service.invoke("", HashMap::from([("id".to_string(), "7".to_string())])).unwrap();
+        assert_eq!(response.data, b"7");
+        assert_eq!(response.status(), Some(404));
+        assert_eq!(response.content_length(), Some(1));
+        assert_eq!(label::current().unwrap(), alice());
+    }
+
+    #[test]
+    fn failures_are_returned_as_errors() {
+        reset();
+        link_gate("failing", Buckle::public(), |_| Err("no such user".to_string()));
+        let gate = Dir::root().open("failing").unwrap().into_gate().unwrap();
+
+        assert!(matches!(gate.invoke("bob"), Err(Error::Invoke(message)) if message == "no such user"));
+        assert!(Dir::root().open("missing").is_err());
+        assert!(matches!(Dir::root().open_dir("failing"), Err(Error::WrongKind)));
+
+        // names can't be linked twice
+        let file = File::create(Buckle::public()).unwrap();
+        assert!(Dir::root().link("failing", &file).is_err());
+
+        // at a raised label, public objects can't be written
+        set_label(alice());
+        assert!(file.write("leak").is_err());
+        assert!(Dir::root().unlink("failing").is_err());
+    }
+}
@@ -0,0 +1,164 @@
+use faasten_interface_types::{DentKind, DentListResult, DentOpenResult, DentResult};
+use faasten_runtime_interface::{batch_op::Op, Batch, BatchOp, BatchOpResult, BatchResult};
+
+use crate::SyscallProcessor;
+
+impl SyscallProcessor {
+    /*
+        Executes the operations of a batch in order. Every operation that refers to an earlier
+        operation's result uses the file descriptor that operation produced (open and create produce
+        file descriptors, the other operations don't). If the referenced operation failed, produced
+        no file descriptor, or isn't an earlier operation, the operation fails without being executed.
+     */
+    pub fn batch(&mut self, batch: Batch) -> BatchResult {
+        let Batch { ops, stop_on_failure } = batch;
+
+        let mut results: Vec<BatchOpResult> = Vec::with_capacity(ops.len());
+        // file descriptor produced by each executed operation, if any
+        let mut produced_fds: Vec<Option<u64>> = Vec::with_capacity(ops.len());
+        let mut success = true;
+
+        for BatchOp { op, fd_ref, target_ref } in ops {
+            let fd = resolve_ref(&produced_fds, fd_ref);
+            let target_fd = resolve_ref(&produced_fds, target_ref);
+
+            let (result, produced_fd): (BatchOpResult, Option<u64>) = match (op, fd, target_fd) {
+                (None, _, _) => {
+                    log::info!("Batch operation {} failed. No operation provided", results.len());
+                    (failed_batch_op(None), None)
+                }
+                (Some(op), Some(None), _) | (Some(op), _, Some(None)) => {
+                    log::info!("Batch operation {} failed. Referenced operation has no file descriptor", results.len());
+                    (failed_batch_op(Some(&op)), None)
+                }
+                (Some(op), fd, target_fd) => self.execute_batch_op(with_refs(op, fd.flatten(), target_fd.flatten()))
+            };
+
+            let op_success = result.success();
+            success &= op_success;
+            results.push(result);
+            produced_fds.push(produced_fd);
+
+            if !op_success && stop_on_failure {
+                break;
+            }
+        }
+
+        BatchResult { success, results }
+    }
+
+    // executes one operation, returning its result and the file descriptor it produced, if any
+    fn execute_batch_op(&mut self, op: Op) -> (BatchOpResult, Option<u64>) {
+        match op {
+            Op::Open(dent_open) => {
+                let res = self.dent_open(dent_open);
+                let produced_fd = res.success.then_some(res.fd);
+                (res.into(), produced_fd)
+            }
+            Op::Create(dent_create) => {
+                let res = self.dent_create(dent_create);
+                let produced_fd = if res.success { res.fd } else { None };
+                (res.into(), produced_fd)
+            }
+            Op::Link(dent_link) => (self.dent_link(dent_link).into(), None),
+            Op::Unlink(dent_unlink) => (self.dent_unlink(dent_unlink).into(), None),
+            Op::Update(dent_update) => (self.dent_update(dent_update).into(), None),
+            Op::Read(fd) => (self.dent_read(fd).into(), None),
+            Op::List(fd) => (self.dent_list(fd).into(), None),
+            Op::Close(fd) => (self.dent_close(fd).into(), None),
+        }
+    }
+}
+
+/*
+    Looks up the file descriptor produced by the operation at index op_ref. Returns None if there is no
+    reference, and Some(None) if the referenced operation produced no file descriptor or wasn't executed yet.
+ */
+fn resolve_ref(produced_fds: &[Option<u64>], op_ref: Option<u32>) -> Option<Option<u64>> {
+    op_ref.map(|i| produced_fds.get(i as usize).copied().flatten())
+}
+
+// replaces the operation's own fd, and DentLink's target_fd, with the referenced file descriptors, if any
+fn with_refs(op: Op, fd: Option<u64>, target_fd: Option<u64>) -> Op {
+    match op {
+        Op::Open(mut dent_open) => {
+            dent_open.fd = fd.unwrap_or(dent_open.fd);
+            Op::Open(dent_open)
+        }
+        Op::Create(dent_create) => Op::Create(dent_create),
+        Op::Link(mut dent_link) => {
+            dent_link.dir_fd = fd.unwrap_or(dent_link.dir_fd);
+            dent_link.target_fd = target_fd.unwrap_or(dent_link.target_fd);
+            Op::Link(dent_link)
+        }
+        Op::Unlink(mut dent_unlink) => {
+            dent_unlink.dir_fd = fd.unwrap_or(dent_unlink.dir_fd);
+            Op::Unlink(dent_unlink)
+        }
+        Op::Update(mut dent_update) => {
+            dent_update.fd = fd.unwrap_or(dent_update.fd);
+            Op::Update(dent_update)
+        }
+        Op::Read(read_fd) => Op::Read(fd.unwrap_or(read_fd)),
+        Op::List(list_fd) => Op::List(fd.unwrap_or(list_fd)),
+        Op::Close(close_fd) => Op::Close(fd.unwrap_or(close_fd)),
+    }
+}
+
+// result of an operation that could not be executed, matching the operation's result type
+fn failed_batch_op(op: Option<&Op>) -> BatchOpResult {
+    match op {
+        Some(Op::Open(_)) => DentOpenResult { success: false, fd: 0, kind: DentKind::DentDirectory.into() }.into(),
+        Some(Op::List(_)) => DentListResult { success: false, entries: Default::default() }.into(),
+        _ => DentResult { success: false, fd: None, data: None }.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use faasten_interface_types::{dent_open, DentLink, DentOpen};
+    use faasten_runtime_interface::batch_op_result::Result;
+
+    #[test]
+    fn resolve_ref_looks_up_earlier_operations() {
+        let produced_fds = [Some(3), None];
+
+        assert_eq!(resolve_ref(&produced_fds, None), None);
+        assert_eq!(resolve_ref(&produced_fds, Some(0)), Some(Some(3)));
+        // the referenced operation produced no file descriptor
+        assert_eq!(resolve_ref(&produced_fds, Some(1)), Some(None));
+        // the operation itself, or a later one
+        assert_eq!(resolve_ref(&produced_fds, Some(2)), Some(None));
+        assert_eq!(resolve_ref(&produced_fds, Some(u32::MAX)), Some(None));
+    }
+
+    #[test]
+    fn with_refs_replaces_fds() {
+        let link = Op::Link(DentLink { dir_fd: 1, name: "a".to_string(), target_fd: 2 });
+        assert_eq!(
+            with_refs(link.clone(), Some(5), Some(6)),
+            Op::Link(DentLink { dir_fd: 5, name: "a".to_string(), target_fd: 6 })
+        );
+        assert_eq!(
+            with_refs(link.clone(), None, Some(6)),
+            Op::Link(DentLink { dir_fd: 1, name: "a".to_string(), target_fd: 6 })
+        );
+        assert_eq!(with_refs(link.clone(), None, None), link);
+
+        let open = DentOpen { fd: 0, entry: Some(dent_open::Entry::Name("a".to_string())) };
+        assert_eq!(with_refs(Op::Open(open.clone()), Some(4), None), Op::Open(DentOpen { fd: 4, ..open }));
+        assert_eq!(with_refs(Op::Read(1), Some(4), None), Op::Read(4));
+        // only links have a target
+        assert_eq!(with_refs(Op::Close(1), None, Some(4)), Op::Close(1));
+    }
+
+    #[test]
+    fn failed_ops_match_result_type() {
+        let open = Op::Open(DentOpen { fd: 0, entry: None });
+        assert!(matches!(failed_batch_op(Some(&open)).result, Some(Result::Open(DentOpenResult { success: false, .. }))));
+        assert!(matches!(failed_batch_op(Some(&Op::List(1))).result, Some(Result::List(DentListResult { success: false, .. }))));
+        assert!(matches!(failed_batch_op(Some(&Op::Read(1))).result, Some(Result::Dent(DentResult { success: false, .. }))));
+        assert!(!failed_batch_op(None).success());
+    }
+}
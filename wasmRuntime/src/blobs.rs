@@ -0,0 +1,663 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use faasten_interface_types::{BlobClose, BlobFinalize, BlobRead, BlobResult, BlobWrite, DentResult};
+use faasten_core::blobstore::{Blob, Blobstore};
+use faasten_core::fs::DirEntry;
+use faasten_runtime_interface::{BlobReadNext, BlobSeek, BlobStat, InputBlobs, SeekWhence};
+use sha2::{Digest, Sha256};
+
+use crate::SyscallProcessor;
+
+/*
+    Blob operations of the syscall processor. Finalized blobs live in the blobs table, blobs
+    that are still being written live in the create_blobs table, along with a running hash of their
+    contents (create_blob_hashers). Both tables share file descriptors.
+    Finalized blobs also have a cursor (blob_cursors, 0 when missing) used by the streaming reads.
+    Each blob that is still being written has a tmp directory of its own inside the processor's, so
+    its temporary file can be removed as soon as the blob is aborted.
+ */
+impl SyscallProcessor {
+    pub fn dent_get_blob(&mut self, fd: u64) -> BlobResult {
+        match self.dents.get(&fd) {
+            Some(DirEntry::Blob(blob_objref)) => {
+                // get the blob from the blobstore using it's name in the fs
+                let blob = self
+                    .env.blobstore
+                    .open(blob_objref.read(&self.env.fs))
+                .expect("Get blob failed. Couldn't open blob");
+                
+                // insert the fetched blob in the table of open blobs
+                let blobfd = self.max_blob_id;
+                self.max_blob_id += 1;
+                let len = blob.len().expect("Get blob failed. Couldn't find blob length");
+                self.blobs.insert(blobfd, blob);
+
+                BlobResult {success: true, fd: blobfd, len, data: None}
+            }
+            _ => BlobResult {success: false, fd: 0, len: 0, data: None}
+        }
+    }
+
+    pub fn blob_create(&mut self) -> BlobResult {
+        let blobfd = self.max_blob_id;
+        let created = std::fs::create_dir_all(self.create_blob_dir(blobfd))
+            .and_then(|_| self.create_blobstore(blobfd).create());
+
+        match created {
+            Ok(newblob) => {
+                self.max_blob_id += 1;
+                self.create_blobs.insert(blobfd, newblob);
+                self.create_blob_hashers.insert(blobfd, Sha256::new());
+
+                BlobResult {success: true, fd: blobfd, len: 0, data: None}
+            },
+            Err(e) => BlobResult{success: false, fd: 0, len: 0, data: Some(e.to_string().into())}
+        }
+    }
+
+    pub fn blob_write(&mut self, blob_write: BlobWrite) -> BlobResult {
+        let BlobWrite { fd, data } = blob_write;
+
+        if let Some(newblob) = self.create_blobs.get_mut(&fd) {
+            match newblob.write(&data) {
+                Ok(len) => {
+                    if let Some(hasher) = self.create_blob_hashers.get_mut(&fd) {
+                        hasher.update(&data[..len]);
+                    }
+                    BlobResult { success: true, fd, len: len as u64, data: None }
+                }
+                Err(e) => BlobResult { success: false, fd, len: 0, data: Some(e.to_string().into()) }
+            }
+        } else {
+            BlobResult {success: false, fd: 0, len: 0, data: None }
+        }
+    }
+
+    pub fn blob_finalize(&mut self, blob_finalize: BlobFinalize) -> BlobResult {
+        let BlobFinalize { fd } = blob_finalize;
+
+        if let Some(newblob) = self.create_blobs.remove(&fd) {
+            let len = newblob.len() as u64;
+            let hasher = self.create_blob_hashers.remove(&fd);
+            
+            // failing to remove the blob's tmp directory is logged. It is removed with the processor's at the latest
+            let saved = self.create_blobstore(fd).save(newblob);
+            let _ = self.remove_create_blob_dir(fd);
+
+            match saved {
+                Ok(finalized_blob) => {
+                    // blob_stat reported the running hash as the blob's name while it was written
+                    if let Some(hash) = hasher.as_ref().map(content_hash) {
+                        if hash != finalized_blob.name {
+                            log::warn!("Content hash {} of blob {} differs from its name {}", hash, fd, finalized_blob.name);
+                        }
+                    }
+                    self.blobs.insert(fd, finalized_blob);
+                    BlobResult { success: true, fd,  len, data: None}
+                }
+                Err(e) => BlobResult { success: false, fd, len, data: Some(e.to_string().into()) }
+            }
+        } else {
+            BlobResult { success: false, fd, len: 0, data: None }
+        }
+    }
+
+    pub fn blob_read(&mut self, blob_read: BlobRead) -> BlobResult {
+        let BlobRead { fd, offset: maybe_offset, length: maybe_length } = blob_read;
+
+        // offset defaults to 0 and length defaults to 4096 bytes (4KB)
+        let offset = maybe_offset.unwrap_or(0);
+        let length = maybe_length.unwrap_or(4096);
+
+        if let Some(blob) = self.blobs.get(&fd) {
+            let mut buf = vec![0; length as usize];
+
+            match blob.read_at(&mut buf, offset) {
+                Ok(num_bytes_read) => {
+                    buf.resize(num_bytes_read, 0);
+                    BlobResult { success: true, fd, len: num_bytes_read as u64, data: Some(buf) }
+                },
+                Err(e) => BlobResult { success: false, fd, len: 0, data: Some(e.to_string().into()) }
+            }
+        } else {
+            BlobResult { success: false, fd, len: 0, data: None }
+        }
+    }
+
+    pub fn blob_close(&mut self, blob_close: BlobClose) -> BlobResult {
+        let BlobClose { fd } = blob_close;
+
+        // the fd may refer to a finalized blob or to one that is still being written
+        self.blob_cursors.remove(&fd);
+        if self.blobs.remove(&fd).is_some() {
+            BlobResult { success: true, fd, len: 0, data: None }
+        } else if self.create_blobs.contains_key(&fd) {
+            BlobResult { len: 0, ..self.blob_abort(fd) }
+        } else {
+            BlobResult { success: false, fd, len: 0, data: None }
+        }
+    }
+
+    pub fn blob_seek(&mut self, blob_seek: BlobSeek) -> BlobResult {
+        let BlobSeek { fd, offset, whence } = blob_seek;
+
+        let Some(blob) = self.blobs.get(&fd) else {
+            return BlobResult { success: false, fd, len: 0, data: None };
+        };
+
+        let cursor = self.blob_cursors.get(&fd).copied().unwrap_or(0);
+        match seek_position(whence, offset, cursor, || blob.len()) {
+            Ok(position) => {
+                self.blob_cursors.insert(fd, position);
+                BlobResult { success: true, fd, len: position, data: None }
+            }
+            Err(e) => BlobResult { success: false, fd, len: 0, data: Some(e.into()) }
+        }
+    }
+
+    pub fn blob_tell(&mut self, fd: u64) -> BlobResult {
+        if self.blobs.contains_key(&fd) {
+            let position = self.blob_cursors.get(&fd).copied().unwrap_or(0);
+            BlobResult { success: true, fd, len: position, data: None }
+        } else if let Some(newblob) = self.create_blobs.get(&fd) {
+            // writes always append, so the cursor of a blob being written is at its end
+            BlobResult { success: true, fd, len: newblob.len() as u64, data: None }
+        } else {
+            BlobResult { success: false, fd, len: 0, data: None }
+        }
+    }
+
+    pub fn blob_read_next(&mut self, blob_read_next: BlobReadNext) -> BlobResult {
+        let BlobReadNext { fd, length } = blob_read_next;
+        let offset = self.blob_cursors.get(&fd).copied().unwrap_or(0);
+
+        let res = self.blob_read(BlobRead { fd, offset: Some(offset), length });
+        if res.success {
+            self.blob_cursors.insert(fd, offset + res.len);
+        }
+        res
+    }
+
+    pub fn blob_read_to_end(&mut self, fd: u64) -> BlobResult {
+        let offset = self.blob_cursors.get(&fd).copied().unwrap_or(0);
+        let max_len = self.env.config.max_blob_read_bytes();
+
+        match self.blobs.get(&fd).map(|blob| read_blob_from(blob, offset, max_len)) {
+            Some(Ok(buf)) => {
+                self.blob_cursors.insert(fd, offset + buf.len() as u64);
+                BlobResult { success: true, fd, len: buf.len() as u64, data: Some(buf) }
+            }
+            Some(Err(e)) => {
+                log::info!("Read to end failed. {}", e);
+                BlobResult { success: false, fd, len: 0, data: Some(e.to_string().into()) }
+            }
+            None => BlobResult { success: false, fd, len: 0, data: None }
+        }
+    }
+
+    /*
+        Opens the blobs passed to this function by its invoker into the blobs table. blobs maps
+        each blob's position in the invoker's list to its name in the blobstore.
+     */
+    pub fn open_input_blobs(&mut self, blobs: HashMap<String, String>) -> Result<(), std::io::Error> {
+        let mut blobs: Vec<(usize, String)> = blobs.into_iter()
+            .filter_map(|(i, name)| i.parse().ok().map(|i| (i, name)))
+            .collect();
+        blobs.sort();
+
+        for (_, name) in blobs {
+            let blob = self.env.blobstore.open(name)?;
+            let blobfd = self.max_blob_id;
+            self.max_blob_id += 1;
+            self.blobs.insert(blobfd, blob);
+            self.input_blobs.push(blobfd);
+        }
+        Ok(())
+    }
+
+    pub fn input_blobs(&self) -> InputBlobs {
+        InputBlobs { fds: self.input_blobs.clone() }
+    }
+
+    /*
+        Names of the finalized blobs at blobfds, keyed by their position in the list, as the callee of an
+        invocation opens them with open_input_blobs. Fails with the first fd that isn't a finalized blob.
+     */
+    pub(crate) fn invoked_blobs(&self, blobfds: Vec<u64>) -> Result<HashMap<String, String>, u64> {
+        blobfds.into_iter()
+            .enumerate()
+            .map(|(i, blobfd)| match self.blobs.get(&blobfd) {
+                Some(blob) => Ok((i.to_string(), blob.name.clone())),
+                None => Err(blobfd)
+            })
+            .collect()
+    }
+
+    // contents of a finalized blob, if the fd refers to one and it isn't larger than max_blob_read_bytes
+    pub(crate) fn blob_contents(&self, fd: u64) -> Option<Vec<u8>> {
+        let max_len = self.env.config.max_blob_read_bytes();
+        match read_blob_from(self.blobs.get(&fd)?, 0, max_len) {
+            Ok(contents) => Some(contents),
+            Err(e) => {
+                log::info!("Couldn't read blob {}. {}", fd, e);
+                None
+            }
+        }
+    }
+
+    // reader over a finalized blob, with a blob object of its own so it can outlive the blobs table entry
+    pub(crate) fn blob_reader(&self, fd: u64) -> Option<BlobReader> {
+        let name = self.blobs.get(&fd)?.name.clone();
+        let blob = self.env.blobstore.open(name).ok()?;
+        let len = blob.len().ok()?;
+        Some(BlobReader { blob, position: 0, len })
+    }
+
+    pub fn file_to_blob(&mut self, fd: u64) -> BlobResult {
+        let Some(DirEntry::File(file_objref)) = self.dents.get(&fd) else {
+            log::info!("File to blob failed. Not a file.");
+            return BlobResult { success: false, fd: 0, len: 0, data: None };
+        };
+
+        // reading the file raises the current label to the file's label
+        let data = file_objref.read(&self.env.fs);
+
+        let saved = self.env.blobstore.create().and_then(|mut newblob| {
+            newblob.write_all(&data)?;
+            self.env.blobstore.save(newblob)
+        });
+
+        match saved {
+            Ok(blob) => {
+                let blobfd = self.max_blob_id;
+                self.max_blob_id += 1;
+                self.blobs.insert(blobfd, blob);
+
+                BlobResult { success: true, fd: blobfd, len: data.len() as u64, data: None }
+            }
+            Err(e) => BlobResult { success: false, fd: 0, len: 0, data: Some(e.to_string().into()) }
+        }
+    }
+
+    pub fn blob_to_file(&mut self, blob_fd: u64, file_fd: u64) -> DentResult {
+        let (Some(blob), Some(DirEntry::File(file_objref))) = (self.blobs.get(&blob_fd), self.dents.get(&file_fd)) else {
+            log::info!("Blob to file failed. Not a blob and a file.");
+            return DentResult { success: false, fd: Some(file_fd), data: None };
+        };
+
+        /*
+            Files are written whole, so the blob is read into memory first. Blobs larger than the configured
+            limit are refused before reading them, and reading stops at the limit in case the length was wrong.
+         */
+        let data = match read_blob_from(blob, 0, self.env.config.max_file_bytes()) {
+            Ok(data) => data,
+            Err(e) => {
+                log::info!("Blob to file failed. {}", e);
+                return DentResult { success: false, fd: Some(file_fd), data: Some(e.to_string().into()) };
+            }
+        };
+
+        // writing checks that the current label can flow to the file's label
+        let result = file_objref.write(data, &self.env.fs);
+        DentResult { success: result.is_ok(), fd: Some(file_fd), data: None }
+    }
+
+    pub fn blob_stat(&mut self, fd: u64) -> BlobStat {
+        if let Some(blob) = self.blobs.get(&fd) {
+            match blob.len() {
+                Ok(len) => BlobStat { success: true, fd, name: Some(blob.name.clone()), len, finalized: true },
+                Err(e) => {
+                    log::info!("Stat failed. Couldn't find blob length. {}", e);
+                    BlobStat { success: false, fd, name: None, len: 0, finalized: true }
+                }
+            }
+        } else if let Some(newblob) = self.create_blobs.get(&fd) {
+            let name = self.create_blob_hashers.get(&fd).map(content_hash);
+            BlobStat { success: true, fd, name, len: newblob.len() as u64, finalized: false }
+        } else {
+            BlobStat { success: false, fd, name: None, len: 0, finalized: false }
+        }
+    }
+
+    pub fn blob_abort(&mut self, fd: u64) -> BlobResult {
+        self.create_blob_hashers.remove(&fd);
+        match self.create_blobs.remove(&fd) {
+            Some(newblob) => {
+                let len = newblob.len() as u64;
+                drop(newblob);
+                match self.remove_create_blob_dir(fd) {
+                    Ok(()) => BlobResult { success: true, fd, len, data: None },
+                    Err(e) => BlobResult { success: false, fd, len, data: Some(e.to_string().into()) }
+                }
+            }
+            None => BlobResult { success: false, fd, len: 0, data: None }
+        }
+    }
+
+    // tmp directory of the blob being written with file descriptor fd
+    fn create_blob_dir(&self, fd: u64) -> PathBuf {
+        self.env.blobstore_tmp_dir.join(fd.to_string())
+    }
+
+    // blobstore writing new blobs to the tmp directory of the blob with file descriptor fd
+    fn create_blobstore(&self, fd: u64) -> Blobstore {
+        Blobstore::new(self.env.blobstore_base_dir.clone(), self.create_blob_dir(fd))
+    }
+
+    // removes the tmp directory of a blob that was finalized or aborted, along with its temporary file
+    fn remove_create_blob_dir(&self, fd: u64) -> std::io::Result<()> {
+        match std::fs::remove_dir_all(self.create_blob_dir(fd)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                log::warn!("Failed to remove tmp directory of blob {}. {}", fd, e);
+                Err(e)
+            }
+            _ => Ok(())
+        }
+    }
+}
+
+/*
+    Removes the tmp directories that runtime processes which are no longer running left behind in
+    tmp_path, e.g because they were killed before removing theirs. The directories are named by process
+    id, and is_running tells whether the process with a given id is still running.
+ */
+pub(crate) fn remove_stale_tmp_dirs(tmp_path: &Path, is_running: impl Fn(u32) -> bool) {
+    let Ok(entries) = std::fs::read_dir(tmp_path) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok()) else {
+            continue;
+        };
+        if is_running(pid) {
+            continue;
+        }
+        match std::fs::remove_dir_all(entry.path()) {
+            Ok(()) => log::info!("Removed stale blobstore tmp directory {:?}", entry.path()),
+            Err(e) => log::warn!("Failed to remove stale blobstore tmp directory {:?}. {}", entry.path(), e)
+        }
+    }
+}
+
+/*
+    The cursor position after seeking by offset relative to whence, given the current cursor position and
+    the blob's length (only looked up when seeking from the end). Seeking before the start of the blob is
+    an error, seeking past the end is not.
+ */
+fn seek_position(
+    whence: i32,
+    offset: i64,
+    cursor: u64,
+    len: impl FnOnce() -> std::io::Result<u64>
+) -> Result<u64, String> {
+    let base = match SeekWhence::try_from(whence) {
+        Ok(SeekWhence::Start) => 0,
+        Ok(SeekWhence::Current) => cursor,
+        Ok(SeekWhence::End) => len().map_err(|e| e.to_string())?,
+        Err(_) => return Err(String::from("invalid whence"))
+    };
+    base.checked_add_signed(offset).ok_or_else(|| String::from("invalid seek to a negative position"))
+}
+
+// lowercase hex SHA-256 of the bytes hashed so far, the way the blobstore names blobs
+fn content_hash(hasher: &Sha256) -> String {
+    hasher.clone().finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/*
+    Reads a finalized blob from offset to its end, 4KB at a time. Fails if that is more than max_len
+    bytes, before reading anything if the blob's length already tells, and otherwise once more than
+    max_len bytes were read, in case the length was wrong.
+ */
+fn read_blob_from(blob: &Blob, offset: u64, max_len: u64) -> std::io::Result<Vec<u8>> {
+    if blob.len()?.saturating_sub(offset) > max_len {
+        return Err(std::io::Error::other(format!("blob is larger than {} bytes", max_len)));
+    }
+    read_chunks(|chunk, position| blob.read_at(chunk, position), offset, Some(max_len))
+}
+
+/*
+    Reads from offset to the end in 4KB chunks, using read_at to read a chunk at a position. Fails
+    without reading further once more than max_len bytes were read, if set.
+ */
+fn read_chunks(
+    mut read_at: impl FnMut(&mut [u8], u64) -> std::io::Result<usize>,
+    offset: u64,
+    max_len: Option<u64>
+) -> std::io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    let mut chunk = vec![0; 4096];
+    let mut position = offset;
+
+    loop {
+        match read_at(&mut chunk, position)? {
+            0 => return Ok(buf),
+            num_bytes_read => {
+                buf.extend_from_slice(&chunk[..num_bytes_read]);
+                position += num_bytes_read as u64;
+                if max_len.is_some_and(|max_len| buf.len() as u64 > max_len) {
+                    return Err(std::io::Error::other(format!("blob is larger than {} bytes", max_len.unwrap_or(0))));
+                }
+            }
+        }
+    }
+}
+
+/*
+    Reads a finalized blob from start to end, e.g to stream it as the body of a request.
+ */
+pub(crate) struct BlobReader {
+    blob: Blob,
+    position: u64,
+    len: u64,
+}
+
+impl BlobReader {
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+}
+
+impl Read for BlobReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let num_bytes_read = self.blob.read_at(buf, self.position)?;
+        self.position += num_bytes_read as u64;
+        Ok(num_bytes_read)
+    }
+}
+
+/*
+    Blobs that were never finalized leave temporary files behind in the blobstore's tmp
+    directory. Each processor has a tmp directory of its own, so it is removed together
+    with whatever is left in it.
+ */
+impl Drop for SyscallProcessor {
+    fn drop(&mut self) {
+        self.create_blobs.clear();
+        self.create_blob_hashers.clear();
+
+        if let Err(e) = std::fs::remove_dir_all(&self.env.blobstore_tmp_dir) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                log::warn!("Failed to remove blobstore tmp directory {:?}. {}", self.env.blobstore_tmp_dir, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use labeled::buckle::{Buckle, Component};
+
+    fn seek(whence: SeekWhence, offset: i64, cursor: u64) -> Result<u64, String> {
+        seek_position(whence.into(), offset, cursor, || Ok(10))
+    }
+
+    #[test]
+    fn seek_positions() {
+        assert_eq!(seek(SeekWhence::Start, 4, 7), Ok(4));
+        assert_eq!(seek(SeekWhence::Current, 2, 7), Ok(9));
+        assert_eq!(seek(SeekWhence::Current, -7, 7), Ok(0));
+        assert_eq!(seek(SeekWhence::End, -3, 7), Ok(7));
+        // past the end is allowed, reads from there return no bytes
+        assert_eq!(seek(SeekWhence::End, 5, 0), Ok(15));
+        assert_eq!(seek(SeekWhence::Start, i64::MAX, 0), Ok(i64::MAX as u64));
+    }
+
+    #[test]
+    fn invalid_seeks_fail() {
+        assert!(seek(SeekWhence::Start, -1, 7).is_err());
+        assert!(seek(SeekWhence::Current, -8, 7).is_err());
+        assert!(seek(SeekWhence::End, -11, 0).is_err());
+        assert!(seek(SeekWhence::Current, 1, u64::MAX).is_err());
+        assert!(seek_position(7, 0, 0, || Ok(10)).is_err());
+    }
+
+    #[test]
+    fn length_is_only_needed_from_the_end() {
+        let no_len = || Err(std::io::Error::other("no length"));
+        assert_eq!(seek_position(SeekWhence::Current.into(), 1, 2, no_len), Ok(3));
+        assert!(seek_position(SeekWhence::End.into(), 0, 2, no_len).is_err());
+    }
+
+    // reads from contents the way Blob::read_at does
+    fn read_at(contents: &[u8]) -> impl FnMut(&mut [u8], u64) -> std::io::Result<usize> + '_ {
+        |chunk, position| {
+            let start = (position as usize).min(contents.len());
+            let n = chunk.len().min(contents.len() - start);
+            chunk[..n].copy_from_slice(&contents[start..start + n]);
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn read_chunks_reads_to_the_end() {
+        let contents: Vec<u8> = (0..10_000).map(|i| i as u8).collect();
+
+        assert_eq!(read_chunks(read_at(&contents), 0, None).unwrap(), contents);
+        assert_eq!(read_chunks(read_at(&contents), 5000, None).unwrap(), &contents[5000..]);
+        assert!(read_chunks(read_at(&contents), 20_000, None).unwrap().is_empty());
+        assert_eq!(read_chunks(read_at(&contents), 0, Some(10_000)).unwrap(), contents);
+    }
+
+    #[test]
+    fn read_chunks_stops_at_max_len() {
+        let contents = vec![0; 10_000];
+        let mut num_reads = 0;
+        let counting_read_at = |chunk: &mut [u8], position| {
+            num_reads += 1;
+            read_at(&contents)(chunk, position)
+        };
+
+        assert!(read_chunks(counting_read_at, 0, Some(4097)).is_err());
+        // the third chunk isn't read
+        assert_eq!(num_reads, 2);
+    }
+
+    #[test]
+    fn read_to_end_is_capped() {
+        let (_dir, mut processor) = SyscallProcessor::for_test(Buckle::public(), Component::dc_true());
+        processor.env.config.max_blob_read_bytes = Some(10);
+        let contents: Vec<u8> = (0..20).collect();
+        let fd = processor.blob_for_test(&contents);
+
+        let result = processor.blob_read_to_end(fd);
+        assert!(!result.success);
+        assert_eq!(processor.blob_tell(fd).len, 0);
+
+        // the rest of the blob fits once part of it was read
+        assert!(processor.blob_read_next(BlobReadNext { fd, length: Some(12) }).success);
+        let result = processor.blob_read_to_end(fd);
+        assert!(result.success);
+        assert_eq!(result.data, Some(contents[12..].to_vec()));
+    }
+
+    #[test]
+    fn callee_reads_blobs_passed_by_caller() {
+        let (_caller_dir, mut caller) = SyscallProcessor::for_test(Buckle::public(), Component::dc_true());
+        let first = caller.blob_for_test(b"first");
+        let second = caller.blob_for_test(b"second");
+        let blobs = caller.invoked_blobs(vec![second, first]).unwrap();
+        assert_eq!(caller.invoked_blobs(vec![first, 100]), Err(100));
+
+        // the callee runs over the same blobstore
+        let (_callee_dir, mut callee) = SyscallProcessor::for_test(Buckle::public(), Component::dc_true());
+        callee.env.blobstore = Blobstore::new(caller.env.blobstore_base_dir.clone(), callee.env.blobstore_tmp_dir.clone());
+        callee.open_input_blobs(blobs).unwrap();
+
+        let contents: Vec<_> = callee.input_blobs().fds.into_iter()
+            .map(|fd| callee.blob_read_to_end(fd).data.unwrap())
+            .collect();
+        assert_eq!(contents, [b"second".to_vec(), b"first".to_vec()]);
+    }
+
+    #[test]
+    fn blob_contents_are_capped() {
+        let (_dir, mut processor) = SyscallProcessor::for_test(Buckle::public(), Component::dc_true());
+        processor.env.config.max_blob_read_bytes = Some(4);
+        let small = processor.blob_for_test(b"data");
+        let large = processor.blob_for_test(b"more data");
+
+        assert_eq!(processor.blob_contents(small), Some(b"data".to_vec()));
+        assert_eq!(processor.blob_contents(large), None);
+    }
+
+    #[test]
+    fn aborting_removes_temporary_file() {
+        let (_dir, mut processor) = SyscallProcessor::for_test(Buckle::public(), Component::dc_true());
+        let fd = processor.blob_create().fd;
+        assert!(processor.blob_write(BlobWrite { fd, data: b"data".to_vec() }).success);
+        assert!(processor.create_blob_dir(fd).exists());
+
+        let result = processor.blob_abort(fd);
+        assert!(result.success);
+        assert_eq!(result.len, 4);
+        assert!(!processor.create_blob_dir(fd).exists());
+    }
+
+    #[test]
+    fn closing_unfinalized_blob_removes_temporary_file() {
+        let (_dir, mut processor) = SyscallProcessor::for_test(Buckle::public(), Component::dc_true());
+        let fd = processor.blob_create().fd;
+
+        assert!(processor.blob_close(BlobClose { fd }).success);
+        assert!(!processor.create_blob_dir(fd).exists());
+    }
+
+    #[test]
+    fn finalizing_removes_tmp_directory() {
+        let (_dir, mut processor) = SyscallProcessor::for_test(Buckle::public(), Component::dc_true());
+        let fd = processor.blob_for_test(b"data");
+
+        assert!(!processor.create_blob_dir(fd).exists());
+        assert_eq!(processor.blob_contents(fd), Some(b"data".to_vec()));
+    }
+
+    #[test]
+    fn only_tmp_dirs_of_stopped_processes_are_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["1", "2", "blobs"] {
+            std::fs::create_dir_all(dir.path().join(name).join("3")).unwrap();
+        }
+
+        remove_stale_tmp_dirs(dir.path(), |pid| pid == 2);
+
+        assert!(!dir.path().join("1").exists());
+        assert!(dir.path().join("2").exists());
+        assert!(dir.path().join("blobs").exists());
+    }
+
+    #[test]
+    fn content_hash_covers_all_writes() {
+        let mut hasher = Sha256::new();
+        assert_eq!(content_hash(&hasher), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+
+        hasher.update(b"ab");
+        hasher.update(b"c");
+        assert_eq!(content_hash(&hasher), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        // hashing doesn't consume the running hash
+        assert_eq!(content_hash(&hasher), content_hash(&hasher));
+    }
+}
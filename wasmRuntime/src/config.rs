@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use labeled::{buckle::Buckle, Label};
+use serde::{Deserialize, Deserializer};
+
+// environment variable holding the path of the runtime's configuration file
+pub const CONFIG_PATH_VAR: &str = "FAASTEN_CONFIG";
+
+const DEFAULT_MAX_FILE_BYTES: u64 = 64 * 1024 * 1024;
+const DEFAULT_MAX_BLOB_READ_BYTES: u64 = 64 * 1024 * 1024;
+
+/*
+    Runtime configuration, read from the JSON file named by FAASTEN_CONFIG. Every setting is
+    optional and the runtime runs with the defaults if the variable isn't set.
+ */
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RuntimeConfig {
+    // address of the scheduler gates are invoked through. Gates can't be invoked if unset
+    pub scheduler_addr: Option<SocketAddr>,
+    // how long dent_invoke waits for a gate's result or a service's response, unless the call sets its own timeout
+    pub invoke_timeout_ms: Option<u64>,
+    // settings of all services, unless overridden in services
+    pub service_defaults: ServiceConfig,
+    // settings of individual services, keyed by the service's url template
+    pub services: HashMap<String, ServiceConfig>,
+    // where services may send requests to. A request must match one of the rules. Unrestricted if unset
+    pub egress: Option<Vec<EgressRule>>,
+    // serve requests to services from a fixture file, or record them to one, instead of only using the network
+    pub service_fixtures: Option<FixtureConfig>,
+    // largest blob blob_to_file copies into a file, which is held in memory whole (default: 64MB)
+    pub max_file_bytes: Option<u64>,
+    // most bytes read from a blob into memory at once, by blob_read_to_end or for a gate's payload blob (default: 64MB)
+    pub max_blob_read_bytes: Option<u64>,
+}
+
+/*
+    Settings of a service. Settings that aren't set fall back to service_defaults, then to
+    the defaults noted below.
+ */
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ServiceConfig {
+    // how long to wait for a connection to the service (default: reqwest's default)
+    pub connect_timeout_ms: Option<u64>,
+    // how long the whole request may take, including reading the response (default: invoke_timeout_ms)
+    pub timeout_ms: Option<u64>,
+    // how many times to retry failed requests with idempotent verbs, within invoke_timeout_ms (default: 0)
+    pub retries: Option<u32>,
+    // wait before the first retry, doubled for each retry after it, up to 30s (default: 100ms)
+    pub retry_backoff_ms: Option<u64>,
+    // largest response body returned to the function as data (default: no limit)
+    pub max_response_bytes: Option<u64>,
+    // whether larger responses are written to a blob instead of failing the invocation (default: false)
+    pub oversized_to_blob: Option<bool>,
+}
+
+/*
+    Destinations allowed by the egress policy. A url matches a rule if its scheme, host and
+    port are all allowed by it.
+ */
+#[derive(Clone, Debug, Deserialize)]
+pub struct EgressRule {
+    // allowed schemes (default: https)
+    #[serde(default = "default_egress_schemes")]
+    pub schemes: Vec<String>,
+    // allowed hosts. "*.example.com" allows the subdomains of example.com. Lowercased, like the hosts of urls
+    #[serde(deserialize_with = "lowercase_hosts")]
+    pub hosts: Vec<String>,
+    // allowed ports (default: any)
+    #[serde(default)]
+    pub ports: Vec<u16>,
+    // only applies to requests sent at labels that can flow to this label, e.g "alice,true" (default: any label)
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct FixtureConfig {
+    // path of the fixture file, a JSON list of requests and their responses
+    pub path: String,
+    // "replay" serves responses from the file, "record" sends requests and writes them to the file
+    pub mode: FixtureMode,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FixtureMode {
+    Replay,
+    Record,
+}
+
+fn default_egress_schemes() -> Vec<String> {
+    vec![String::from("https")]
+}
+
+fn lowercase_hosts<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    let hosts: Vec<String> = Vec::deserialize(deserializer)?;
+    Ok(hosts.into_iter().map(|host| host.to_ascii_lowercase()).collect())
+}
+
+/*
+    How a request is retried. Every attempt may take at most timeout, and all attempts, including the
+    waits between them, must be done by the deadline.
+ */
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    pub backoff: Duration,
+    pub timeout: Option<Duration>,
+    pub deadline: Option<Instant>,
+}
+
+impl RetryPolicy {
+    // time left until the deadline, if there is one
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    // the timeout of the next attempt, shortened to the time left until the deadline
+    pub fn attempt_timeout(&self) -> Option<Duration> {
+        match (self.timeout, self.remaining()) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b)
+        }
+    }
+}
+
+impl RuntimeConfig {
+    pub fn load() -> Result<Self, String> {
+        let Ok(path) = std::env::var(CONFIG_PATH_VAR) else {
+            return Ok(Self::default());
+        };
+
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Couldn't read config file {}. {}", path, e))?;
+        let config: Self = serde_json::from_str(&contents)
+            .map_err(|e| format!("Couldn't parse config file {}. {}", path, e))?;
+
+        // labels of egress rules are parsed when requests are checked, so make sure they parse
+        for rule in config.egress.iter().flatten() {
+            if let Some(label) = &rule.label {
+                Buckle::parse(label).map_err(|_| format!("Invalid egress rule label {} in {}", label, path))?;
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn invoke_timeout(&self) -> Option<Duration> {
+        self.invoke_timeout_ms.map(Duration::from_millis)
+    }
+
+    pub fn max_file_bytes(&self) -> u64 {
+        self.max_file_bytes.unwrap_or(DEFAULT_MAX_FILE_BYTES)
+    }
+
+    pub fn max_blob_read_bytes(&self) -> u64 {
+        self.max_blob_read_bytes.unwrap_or(DEFAULT_MAX_BLOB_READ_BYTES)
+    }
+
+    pub fn service(&self, url: &str) -> ServiceConfig {
+        let defaults = &self.service_defaults;
+        match self.services.get(url) {
+            Some(service) => ServiceConfig {
+                connect_timeout_ms: service.connect_timeout_ms.or(defaults.connect_timeout_ms),
+                timeout_ms: service.timeout_ms.or(defaults.timeout_ms),
+                retries: service.retries.or(defaults.retries),
+                retry_backoff_ms: service.retry_backoff_ms.or(defaults.retry_backoff_ms),
+                max_response_bytes: service.max_response_bytes.or(defaults.max_response_bytes),
+                oversized_to_blob: service.oversized_to_blob.or(defaults.oversized_to_blob),
+            },
+            None => defaults.clone()
+        }
+    }
+
+    // whether the egress policy allows sending a request to url at the given label
+    pub fn egress_allowed(&self, url: &reqwest::Url, label: &Buckle) -> bool {
+        egress_allowed(self.egress.as_deref(), url, label)
+    }
+
+    // connect timeouts of all configured services, each needs an http client of its own
+    pub fn connect_timeouts(&self) -> impl Iterator<Item = u64> + '_ {
+        std::iter::once(&self.service_defaults)
+            .chain(self.services.values())
+            .filter_map(|service| service.connect_timeout_ms)
+    }
+}
+
+impl ServiceConfig {
+    pub fn oversized_to_blob(&self) -> bool {
+        self.oversized_to_blob.unwrap_or(false)
+    }
+
+    // the service's timeout, or invoke_timeout if shorter
+    pub fn timeout(&self, invoke_timeout: Option<Duration>) -> Option<Duration> {
+        match (self.timeout_ms.map(Duration::from_millis), invoke_timeout) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b)
+        }
+    }
+
+    /*
+        Only requests that can safely be sent twice are retried. Every attempt times out like a single
+        request would (see timeout), and all of them must be done within invoke_timeout from now.
+     */
+    pub fn retry_policy(&self, method: &reqwest::Method, invoke_timeout: Option<Duration>) -> RetryPolicy {
+        RetryPolicy {
+            retries: if method.is_idempotent() { self.retries.unwrap_or(0) } else { 0 },
+            backoff: Duration::from_millis(self.retry_backoff_ms.unwrap_or(100)),
+            timeout: self.timeout(invoke_timeout),
+            deadline: invoke_timeout.map(|timeout| Instant::now() + timeout),
+        }
+    }
+}
+
+// whether the egress rules, if any, allow sending a request to url at the given label
+pub fn egress_allowed(rules: Option<&[EgressRule]>, url: &reqwest::Url, label: &Buckle) -> bool {
+    match rules {
+        Some(rules) => rules.iter().any(|rule| rule.allows(url, label)),
+        None => true
+    }
+}
+
+impl EgressRule {
+    pub fn allows(&self, url: &reqwest::Url, label: &Buckle) -> bool {
+        let applies = match &self.label {
+            Some(rule_label) => Buckle::parse(rule_label).map_or(false, |rule_label| label.can_flow_to(&rule_label)),
+            None => true
+        };
+        let scheme_allowed = self.schemes.iter().any(|scheme| scheme == url.scheme());
+        let host_allowed = url.host_str().map_or(false, |host| {
+            self.hosts.iter().any(|allowed| match allowed.strip_prefix("*.") {
+                Some(domain) => host.strip_suffix(domain).map_or(false, |sub| sub.ends_with('.')),
+                None => allowed == host
+            })
+        });
+        let port_allowed = self.ports.is_empty()
+            || url.port_or_known_default().map_or(false, |port| self.ports.contains(&port));
+
+        applies && scheme_allowed && host_allowed && port_allowed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(json: &str) -> EgressRule {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn url(url: &str) -> reqwest::Url {
+        reqwest::Url::parse(url).unwrap()
+    }
+
+    #[test]
+    fn rules_match_hosts() {
+        let rule = rule(r#"{"hosts": ["api.example.com", "*.example.org"]}"#);
+        let public = Buckle::public();
+
+        assert!(rule.allows(&url("https://api.example.com/v1"), &public));
+        assert!(rule.allows(&url("https://a.b.example.org"), &public));
+        assert!(!rule.allows(&url("https://example.com"), &public));
+        // wildcards only match subdomains
+        assert!(!rule.allows(&url("https://example.org"), &public));
+        assert!(!rule.allows(&url("https://badexample.org"), &public));
+    }
+
+    #[test]
+    fn hosts_are_case_insensitive() {
+        let rule = rule(r#"{"hosts": ["API.Example.com", "*.Example.ORG"]}"#);
+        assert_eq!(rule.hosts, vec!["api.example.com", "*.example.org"]);
+
+        assert!(rule.allows(&url("https://api.EXAMPLE.com"), &Buckle::public()));
+        assert!(rule.allows(&url("https://WWW.example.org"), &Buckle::public()));
+    }
+
+    #[test]
+    fn rules_match_schemes_and_ports() {
+        let public = Buckle::public();
+
+        // only https by default
+        let https_only = rule(r#"{"hosts": ["a.com"]}"#);
+        assert!(https_only.allows(&url("https://a.com"), &public));
+        assert!(!https_only.allows(&url("http://a.com"), &public));
+
+        let ports = rule(r#"{"hosts": ["a.com"], "schemes": ["http", "https"], "ports": [443, 8080]}"#);
+        assert!(ports.allows(&url("https://a.com"), &public));
+        assert!(ports.allows(&url("http://a.com:8080"), &public));
+        assert!(!ports.allows(&url("http://a.com"), &public));
+        assert!(!ports.allows(&url("https://a.com:8443"), &public));
+    }
+
+    #[test]
+    fn labeled_rules_apply_to_labels_that_flow_to_them() {
+        let rule = rule(r#"{"hosts": ["a.com"], "label": "alice,true"}"#);
+        let url = url("https://a.com");
+
+        assert!(rule.allows(&url, &Buckle::public()));
+        assert!(rule.allows(&url, &Buckle::parse("alice,true").unwrap()));
+        assert!(!rule.allows(&url, &Buckle::parse("bob,true").unwrap()));
+        // redirects are checked at the top label, which only rules without a label allow
+        assert!(!rule.allows(&url, &Buckle::top()));
+    }
+
+    #[test]
+    fn requests_must_match_a_rule() {
+        let url = url("https://a.com");
+
+        assert!(egress_allowed(None, &url, &Buckle::top()));
+        assert!(!egress_allowed(Some(&[]), &url, &Buckle::public()));
+        let rules = [rule(r#"{"hosts": ["b.com"]}"#), rule(r#"{"hosts": ["a.com"]}"#)];
+        assert!(egress_allowed(Some(&rules), &url, &Buckle::top()));
+    }
+}
@@ -0,0 +1,1150 @@
+use std::collections::HashMap;
+use faasten_interface_types::{dent_create, dent_open, dent_update, gate,
+    DentCreate, DentKind, DentLink, DentListResult, DentLsFaceted, DentLsFacetedResult, DentLsGateResult,
+    DentOpen, DentOpenResult, DentResult, DentUnlink, DentUpdate, Service};
+use faasten_core::fs::{self, DirEntry, DirectGate, Gate, HttpVerb, RedirectGate, CURRENT_LABEL};
+use labeled::{buckle::{Buckle, Component}, HasPrivilege, Label};
+use faasten_runtime_interface::ServiceUpdate;
+
+use crate::{DentKindWrap, SyscallProcessor};
+
+/*
+    Directory entry operations of the syscall processor. The dent_* cloudcalls
+    and the batch cloudcall are thin wrappers around these.
+ */
+impl SyscallProcessor {
+    pub fn dent_open(&mut self, dent_open: DentOpen) -> DentOpenResult {
+        let DentOpen{fd: dir_fd, entry} = dent_open;
+
+        // if entry is none, return failure
+        let Some(entry) = entry else {
+            return DentOpenResult{success: false, fd: 0, kind: DentKind::DentDirectory.into()};
+        };
+
+        let result: Option<(u64, DentKindWrap)> = self.dents
+            .get(&dir_fd)
+            .cloned()
+            .and_then(|base| match (base, entry) {
+                ( // Case #1: base = a Directory object. entry = a Name enum. Looking to open an object by it's name 
+                    DirEntry::Directory(base_dir),
+                    dent_open::Entry::Name(name)
+                ) => {
+                    base_dir.list(&self.env.fs).get(&name).map(|dent| {
+                        let res_id = self.max_dent_id;
+                        let _ = self.dents.insert(res_id, dent.clone());
+                        // remember where the object was linked so it can be relabeled in place later
+                        let _ = self.dent_origins.insert(res_id, (DirEntry::Directory(base_dir), name.clone()));
+                        self.max_dent_id += 1;
+                        (res_id, dent.into())
+                    })
+                }
+                (
+                    // Case #2: base = a FacetedDirectory object. entry = a Facet enum. Looking to open an object by it's label
+                    DirEntry::FacetedDirectory(base_dir),
+                    dent_open::Entry::Facet(label)
+                ) => {
+                    let dent = DirEntry::Directory(base_dir.open(&label.into(), &self.env.fs));
+                    let res_id = self.max_dent_id;
+                    let _ = self.dents.insert(res_id, dent.clone());
+                    self.max_dent_id += 1;
+                    Some((res_id, (&dent).into()))
+                }
+                (
+                    // Case #3: base = a FacetedDirectory object. entry = a Name enum. Name contains a string which represent a label
+                    DirEntry::FacetedDirectory(base_dir),
+                    dent_open::Entry::Name(label_name)
+                ) => {
+                    if let Ok(label) = Buckle::parse(label_name.as_str()) {
+                        let dent = DirEntry::Directory(base_dir.open(&label, &self.env.fs));
+                        let res_id = self.max_dent_id;
+                        let _ = self.dents.insert(res_id, dent.clone());
+                        self.max_dent_id += 1;
+                        Some((res_id, (&dent).into()))
+                    } else {
+                        None
+                    }
+                }
+                _ => None
+            });
+        
+        // return a DentOpenResult indicating success or failure
+        if let Some(result) = result {
+            DentOpenResult{
+                    success: true,
+                    fd: result.0,
+                    kind: result.1.kind.into()
+            }
+        } else {
+            DentOpenResult{
+                success: false,
+                fd: 0,
+                kind: DentKind::DentDirectory.into()
+            }
+        }
+    }
+
+    pub fn dent_close(&mut self, input_fd: u64) -> DentResult {
+        let _ = self.dent_origins.remove(&input_fd);
+
+        DentResult{
+            success: self.dents.remove(&input_fd).is_some(),
+            fd: None,
+            data: None
+        }
+    }
+
+    pub fn dent_create(&mut self, dent_create: DentCreate) -> DentResult {
+        let DentCreate{label, kind} = dent_create;
+
+        // if kind is none, return failure
+        if kind.is_none() {
+            return DentResult{success: false, fd: None, data: None};
+        }
+
+        // get the actual label and kind passed into dent create. label defaults to public
+        let kind = kind.unwrap();
+        let label = label.unwrap_or(Buckle::public());
+
+
+        let maybe_entry: Option<DirEntry> = match kind {
+            dent_create::Kind::Directory => Some(self.env.fs.create_directory(label)),
+            dent_create::Kind::File => Some(self.env.fs.create_file(label)),
+            dent_create::Kind::FacetedDirectory => Some(self.env.fs.create_faceted_directory()),
+            dent_create::Kind::Blob(blobfd) => {
+                // get blob from blobs table. create a blob entry in dents table which wraps around the blob name
+                let blob = self.blobs.get(&blobfd);
+                
+                if blob.is_some() { self.env.fs.create_blob(label, blob.unwrap().name.clone()).ok() }
+                else {None}
+            }
+            dent_create::Kind::Gate(faasten_interface_types::Gate {kind: gate_kind}) => {
+                if gate_kind.is_none() {
+                    None
+                } 
+                else {
+                    let gate_kind = gate_kind.unwrap();
+                    match gate_kind {
+                        // Case #1: creating a direct gate
+                        gate::Kind::Direct(dg) => {
+                            let Some(function) = dg.function
+                            else {
+                                log::info!("No function provided when creating gate");
+                                return DentResult{success: false, fd: None, data: None};
+                            };
+                            
+                            // get the function's app image
+                            let Some(DirEntry::Blob(app_image)) = self.dents.get(&function.app_image)
+                            else {
+                                log::info!("Failed to read function's app image when creating gate");
+                                return DentResult{success: false, fd: None, data: None};
+                            };
+
+                            // get the function's runtime image
+                            let Some(DirEntry::Blob(runtime_image)) = self.dents.get(&function.runtime_image)
+                            else {
+                                log::info!("Failed to read function's runtime image when creating gate");
+                                return DentResult{success: false, fd: None, data: None};
+                            };
+
+                            // create an internal version of the function
+                            let new_func = fs::Function {
+                                memory: function.memory as usize,
+                                app_image: app_image.get(&self.env.fs).unwrap().unlabel().clone(),
+                                runtime_image: runtime_image.get(&self.env.fs).unwrap().unlabel().clone(),
+                                kernel: "Kernel Not Used".to_string()
+                            };
+
+                            let new_gate = DirectGate {
+                                privilege: dg.privilege.unwrap().into(),
+                                invoker_integrity_clearance: dg.invoker_integrity_clearance.unwrap().into(),
+                                declassify: dg.declassify.map(|d| d.into()).unwrap_or(Component::dc_true()),
+                                function: new_func
+                            };
+
+                            // a gate can only be given privilege the current privilege owns
+                            if !owns(&new_gate.privilege) || !owns(&new_gate.declassify) {
+                                log::info!("Failed to create gate. Insufficient privilege");
+                                return DentResult{success: false, fd: None, data: None};
+                            }
+
+                            // create the direct gate
+                            self.env.fs.create_direct_gate(label, new_gate).ok()
+                        }
+                        // Case #2: creating a redirect gate
+                        gate::Kind::Redirect(rdg) => {
+                            let maybe_inner_gate = self.dents.get(&rdg.gate);
+                            
+                            if let Some(DirEntry::Gate(gate_objref)) = maybe_inner_gate {
+                                let new_gate = RedirectGate {
+                                    privilege: rdg.privilege.unwrap().into(),
+                                    invoker_integrity_clearance: rdg.invoker_integrity_clearance.unwrap().into(),
+                                    declassify: rdg.declassify.map(|d| d.into()).unwrap_or(Component::dc_true()),
+                                    gate: *gate_objref
+                                };
+
+                                // a gate can only be given privilege the current privilege owns
+                                if !owns(&new_gate.privilege) || !owns(&new_gate.declassify) {
+                                    log::info!("Failed to create gate. Insufficient privilege");
+                                    return DentResult{success: false, fd: None, data: None};
+                                }
+
+                                self.env.fs.create_redirect_gate(label, new_gate).ok()
+                            } else {
+                                log::info!("Failed to get inner gate when creating redirect gate");
+                                return DentResult{success: false, fd: None, data: None};
+                            }
+                        }
+                    }
+                }
+            },
+            dent_create::Kind::Service(Service {
+                taint,
+                privilege,
+                invoker_integrity_clearance,
+                url,
+                verb,
+                mut headers
+            }) => {
+                let verb = HttpVerb::from_i32(verb).unwrap_or(HttpVerb::HEAD);
+                let headers: std::collections::BTreeMap<String, String> = headers.drain().collect();
+                let new_service = fs::Service {
+                    taint: taint.unwrap().into(),
+                    privilege: privilege.unwrap().into(),
+                    invoker_integrity_clearance: invoker_integrity_clearance.unwrap().into(),
+                    url,
+                    verb,
+                    headers
+                };
+
+                // a service can only be given privilege the current privilege owns
+                if !owns(&new_service.privilege) {
+                    log::info!("Failed to create service. Insufficient privilege");
+                    return DentResult{success: false, fd: None, data: None};
+                }
+
+                self.env.fs.create_service(label, new_service).ok()
+            }
+        };
+
+        // if entry is valid, insert into dents table
+        if let Some(entry) = maybe_entry {
+            let res_id = self.max_dent_id;
+            let _ = self.dents.insert(res_id, entry);
+            self.max_dent_id += 1;
+
+            DentResult{
+                success: true,
+                fd: Some(res_id),
+                data: None
+            }
+        } else {
+            DentResult{
+                success: false,
+                fd: None,
+                data: None
+            }
+        }
+    }
+
+    pub fn dent_update(&mut self, dent_update: DentUpdate) -> DentResult {
+        let DentUpdate{fd, kind} = dent_update;
+
+        // if kind is none, return failure
+        if kind.is_none() {
+            return DentResult{success: false, fd: None, data: None}
+        }
+
+        let kind = kind.unwrap();
+
+        let result = match kind {
+            dent_update::Kind::File(data) => {
+                if let Some(DirEntry::File(file_objref)) = self.dents.get(&fd) {
+                    file_objref.write(data, &self.env.fs).ok()
+                } else { None }
+            }
+            dent_update::Kind::Blob(blobfd) => {
+                let new_blob = self.blobs.get(&blobfd);
+                if new_blob.is_none() {
+                    log::info!("Failed to update object with fd {}", fd);
+                    return DentResult{success: false, fd: None, data: None};
+                }
+                let new_blob = new_blob.unwrap();
+
+                if let Some(DirEntry::Blob(blob_objref)) = self.dents.get(&fd) {
+                    blob_objref.replace(new_blob.name.clone(), &self.env.fs).ok()
+                } else { None }
+            }
+            dent_update::Kind::Gate(faasten_interface_types::Gate { kind }) => {
+                if let Some(DirEntry::Gate(gate_objref)) = self.dents.get(&fd) {
+                    if let Some( kind ) = kind {
+                        match kind {
+                            // Case #1: replacing a direct gate
+                            gate::Kind::Direct(dg_intf) => {
+                                // will use this to replace the old gate in the backing store
+                                let mut new_gate;
+                                
+                                // get the old gate from the backing store
+                                if let Some(Gate::Direct(dg_core)) = gate_objref.get(&self.env.fs).map(|g| g.unlabel().clone()) {
+                                    new_gate = dg_core;
+                                } else {
+                                    log::info!("Failed to update object with fd {}", fd);
+                                    return DentResult{success: false, fd: None, data: None};
+                                }
+                                let old_privilege = new_gate.privilege.clone();
+
+                                // replace gate's function field, if user provided one
+                                if let Some(function) = dg_intf.function {
+                                    // replace function's fields if user provided a valid fd for them
+
+                                    // replacing app image
+                                    if function.app_image > 0 {
+                                        let Some(DirEntry::Blob(app_image)) = self.dents.get(&function.app_image) 
+                                        else {
+                                            log::info!("Failed to update object with fd {}", fd);
+                                            return DentResult{success: false, fd: None, data: None};
+                                        };
+
+                                        new_gate.function.app_image = app_image.get(&self.env.fs).unwrap().unlabel().clone();
+                                    }
+
+                                    // replacing runtime image
+                                    if function.runtime_image > 0 {
+                                        let Some(DirEntry::Blob(runtime_image)) = self.dents.get(&function.runtime_image)
+                                        else {
+                                            log::info!("Failed to update object with fd {}", fd);
+                                            return DentResult{success: false, fd: None, data: None};
+                                        };
+
+                                        new_gate.function.runtime_image = runtime_image.get(&self.env.fs).unwrap().unlabel().clone();
+                                    }
+
+                                    // kernel not used, so not replaced
+                                    
+                                    // replacing memory
+                                    if function.memory > 0 {
+                                        new_gate.function.memory = function.memory as usize;
+                                    }
+                                }
+
+                                // replace gate's privilege, if user provided one
+                                if let Some(privilege) = dg_intf.privilege {
+                                    new_gate.privilege = privilege.into();
+                                }
+
+                                // replace gate's invoker integrity clearance, if user provided one
+                                if let Some(invoker_integrity_clearance) = dg_intf.invoker_integrity_clearance {
+                                    new_gate.invoker_integrity_clearance = invoker_integrity_clearance.into();
+                                }
+
+                                // replace gate's declassify, if user provided one and current privilege allows it
+                                if let Some(declassify) = dg_intf.declassify {
+                                    if !fs::utils::get_privilege().implies(&declassify) {
+                                        log::info!("Failed to update object with fd {}. Insufficient privilege to declassify", fd);
+                                        return DentResult{success: false, fd: None, data: None};
+                                    }
+                                    new_gate.declassify = declassify.into();
+                                }
+
+                                /*
+                                    changing any field of the gate changes what its privilege is used for, so
+                                    the current privilege must own both the old and the new privilege
+                                 */
+                                if !owns(&old_privilege) || !owns(&new_gate.privilege) {
+                                    log::info!("Failed to update object with fd {}. Insufficient privilege", fd);
+                                    return DentResult{success: false, fd: None, data: None};
+                                }
+
+                                gate_objref.replace(Gate::Direct(new_gate), &self.env.fs).ok()
+                            }
+                            // Case #2 replacing an indirect gate
+                            gate::Kind::Redirect(rdg_intf) => {
+                                // will use this to replace the old gate in the backing store
+                                let mut new_gate;
+
+                                // get the old gate from the backing store
+                                if let Some(Gate::Redirect(rdg_core)) = gate_objref.get(&self.env.fs).map(|g| g.unlabel().clone()) {
+                                    new_gate = rdg_core;
+                                } else {
+                                    log::info!("Failed to update object with fd {}", fd);
+                                    return DentResult{success: false, fd: None, data: None};
+                                }
+                                let old_privilege = new_gate.privilege.clone();
+
+                                // replace inner gate, if user provided a valid fd for it
+                                if rdg_intf.gate > 0 {
+                                    if let Some(DirEntry::Gate(inner_gate_objref)) = self.dents.get(&rdg_intf.gate) {
+                                        new_gate.gate = *inner_gate_objref;
+                                    } else{
+                                        log::info!("Failed to update object with fd {}", fd);
+                                        return DentResult{success: false, fd: None, data: None};
+                                    }
+                                }
+
+                                // replace gate's privilege, if user provided one
+                                if let Some(privilege) = rdg_intf.privilege {
+                                    new_gate.privilege = privilege.into();
+                                }
+
+                                // replace gate's invoker integrity clearance, if user provided one
+                                if let Some(invoker_integrity_clearance) = rdg_intf.invoker_integrity_clearance {
+                                    new_gate.invoker_integrity_clearance = invoker_integrity_clearance;
+                                }
+
+                                // replace gate's declassify, if user provided one and current privilege allows it
+                                if let Some(declassify) = rdg_intf.declassify {
+                                    if !fs::utils::get_privilege().implies(&declassify) {
+                                        log::info!("Failed to update object with fd {}. Insufficient privilege to declassify", fd);
+                                        return DentResult{success: false, fd: None, data: None};
+                                    }
+                                    new_gate.declassify = declassify.into();
+                                }
+
+                                /*
+                                    changing any field of the gate changes what its privilege is used for, so
+                                    the current privilege must own both the old and the new privilege
+                                 */
+                                if !owns(&old_privilege) || !owns(&new_gate.privilege) {
+                                    log::info!("Failed to update object with fd {}. Insufficient privilege", fd);
+                                    return DentResult{success: false, fd: None, data: None};
+                                }
+
+                                gate_objref.replace(Gate::Redirect(new_gate), &self.env.fs).ok()
+                            }
+                        }
+                    } else { None }
+                } else { None }
+            },
+            dent_update::Kind::Service(service) => {
+                // the default url and verb can't be told apart from unset ones here, so they count as not provided
+                let url = (!service.url.is_empty()).then(|| service.url.clone());
+                let verb = (service.verb > 0).then_some(service.verb);
+                self.update_service(fd, service, url, verb)
+            }
+        };
+
+        if result.is_some() {
+            DentResult{
+                success: true,
+                fd: None,
+                data: None
+            }
+        } else {
+            log::info!("Failed to update object with fd {}", fd);
+            return DentResult{success: false, fd: None, data: None};
+        }
+    }
+
+    pub fn dent_update_service(&mut self, service_update: ServiceUpdate) -> DentResult {
+        let ServiceUpdate{fd, service, url, verb} = service_update;
+
+        let result = self.update_service(fd, service.unwrap_or_default(), url, verb);
+        if result.is_none() {
+            log::info!("Failed to update object with fd {}", fd);
+        }
+        DentResult{success: result.is_some(), fd: None, data: None}
+    }
+
+    /*
+        Replaces the provided fields of the service at fd. The url and verb of the given service are
+        ignored in favour of the explicitly provided ones. Headers are merged into the existing ones,
+        and an empty header value removes that header. Fails unless the current privilege owns the
+        service's old and new privilege.
+     */
+    fn update_service(&mut self, fd: u64, service: Service, url: Option<String>, verb: Option<i32>) -> Option<()> {
+        let Service{taint, privilege, invoker_integrity_clearance, mut headers, ..} = service;
+
+        let Some(DirEntry::Service(service_objref)) = self.dents.get(&fd) else {
+            return None;
+        };
+
+        // get the old service from the backing store. It is replaced with the updated one
+        let mut new_service = service_objref.get(&self.env.fs).map(|s| s.unlabel().clone())?;
+        let old_privilege = new_service.privilege.clone();
+
+        // replace service's taint, if user provided one
+        if let Some(taint) = taint {
+            new_service.taint = taint.into();
+        }
+
+        // replace service's privilege, if user provided one
+        if let Some(privilege) = privilege {
+            new_service.privilege = privilege.into();
+        }
+
+        // replace service's invoker integrity clearance, if user provided one
+        if let Some(invoker_integrity_clearance) = invoker_integrity_clearance {
+            new_service.invoker_integrity_clearance = invoker_integrity_clearance.into();
+        }
+
+        // replace service's url, if user provided one
+        if let Some(url) = url {
+            new_service.url = url;
+        }
+
+        // replace service's verb, if user provided one. An unknown verb fails the update
+        if let Some(verb) = verb {
+            new_service.verb = HttpVerb::from_i32(verb)?;
+        }
+
+        // merge provided headers into the old ones. An empty value removes the header
+        for (name, value) in headers.drain() {
+            if value.is_empty() {
+                new_service.headers.remove(&name);
+            } else {
+                new_service.headers.insert(name, value);
+            }
+        }
+
+        /*
+            changing any field of the service, e.g its url or headers, changes what its privilege is used
+            for, so the current privilege must own both the old and the new privilege
+         */
+        if !owns(&old_privilege) || !owns(&new_service.privilege) {
+            log::info!("Failed to update service with fd {}. Insufficient privilege", fd);
+            return None;
+        }
+
+        service_objref.replace(new_service, &self.env.fs).ok()
+    }
+
+    pub fn dent_read(&mut self, fd: u64) -> DentResult {
+        let result = self.dents.get(&fd).and_then(|entry| {
+            match entry {
+                DirEntry::File(file) => Some(file.read(&self.env.fs)),
+                _ => None
+            }
+        });
+
+        DentResult{
+            success: result.is_some(),
+            fd: Some(fd),
+            data: result
+        }
+    }
+
+    pub fn dent_link(&mut self, dent_link: DentLink) -> DentResult {
+        let DentLink{dir_fd, name, target_fd} = dent_link;
+
+        let base_dir_m = self.dents.get(&dir_fd).cloned();
+        let target_obj_m = self.dents.get(&target_fd).cloned();
+        let result = base_dir_m.clone().zip(target_obj_m).and_then(|(base, target)| {
+            match base {
+                DirEntry::Directory(base_dir) => base_dir
+                    .link(name.clone(), target, &self.env.fs)
+                    .map_err(|e| Into::into(e)),
+                _ => Err(fs::FsError::NotADir)             
+            }.ok()
+        });
+
+        // remember where an object without an origin was linked, so it can be relabeled in place later
+        if result.is_some() && !self.dent_origins.contains_key(&target_fd) {
+            let _ = self.dent_origins.insert(target_fd, (base_dir_m.unwrap(), name));
+        }
+
+        DentResult {
+            success: result.is_some(),
+            fd: None, 
+            data: None
+        }
+    }
+
+    pub fn dent_unlink(&mut self, dent_unlink: DentUnlink) -> DentResult {
+        let DentUnlink{dir_fd, name} = dent_unlink;
+
+        let result = self.dents.get(&dir_fd).cloned().and_then(|entry| {
+            match entry {
+                DirEntry::Directory(base_dir) => base_dir.unlink(&name, &self.env.fs).ok(),
+                _ => None
+            }
+        });
+
+        DentResult{
+            success: result.unwrap_or(false),
+            fd: Some(dir_fd),
+            data: None
+        }
+    }
+
+    pub fn dent_list(&mut self, dir_fd: u64) -> DentListResult {
+        let result = self.dents.get(&dir_fd).and_then(|entry| {
+            match entry {
+                DirEntry::Directory(dir_objref) => Some(
+                    dir_objref
+                        .list(&self.env.fs)
+                        .iter()
+                        .map(
+                            |(name, direntry)| {
+                                let kind = match direntry {
+                                    DirEntry::Directory(_) => DentKind::DentDirectory,
+                                    DirEntry::File(_) => DentKind::DentFile,
+                                    DirEntry::FacetedDirectory(_) => DentKind::DentFacetedDirectory,
+                                    DirEntry::Gate(_) => DentKind::DentGate,
+                                    DirEntry::Service(_) => DentKind::DentService,
+                                    DirEntry::Blob(_) => DentKind::DentBlob
+                                };
+
+                                (name.clone(), kind as i32)
+                            }
+                        )
+                        .collect()
+                ),
+                _ => {
+                    log::info!("List failed. Not a directory.");
+                    None
+                }
+            }
+        });
+
+        if let Some(entries) = result {
+            DentListResult{success: true, entries}
+        } else {
+            DentListResult{success: false, entries: Default::default()}
+        }
+    }
+
+    pub fn dent_relabel(&mut self, fd: u64, new_label: Buckle) -> DentResult {
+        let Some(entry) = self.dents.get(&fd).cloned() else {
+            log::info!("Failed to relabel object with fd {}. No such object", fd);
+            return DentResult{success: false, fd: None, data: None};
+        };
+
+        // read the object's contents. This raises the current label to the object's label
+        enum Contents {
+            File(Vec<u8>),
+            Directory(HashMap<String, DirEntry>),
+            Blob(String),
+            Gate(Gate),
+            Service(fs::Service)
+        }
+
+        let contents = match &entry {
+            DirEntry::File(file_objref) => Some(Contents::File(file_objref.read(&self.env.fs))),
+            DirEntry::Directory(dir_objref) => Some(Contents::Directory(
+                dir_objref.list(&self.env.fs).into_iter().collect()
+            )),
+            DirEntry::Blob(blob_objref) => Some(Contents::Blob(blob_objref.read(&self.env.fs))),
+            DirEntry::Gate(gate_objref) => gate_objref.get(&self.env.fs).map(|g| {
+                fs::utils::taint_with_label(g.label().clone());
+                Contents::Gate(g.unlabel().clone())
+            }),
+            DirEntry::Service(service_objref) => service_objref.get(&self.env.fs).map(|s| {
+                fs::utils::taint_with_label(s.label().clone());
+                Contents::Service(s.unlabel().clone())
+            }),
+            DirEntry::FacetedDirectory(_) => None
+        };
+
+        let Some(contents) = contents else {
+            log::info!("Failed to relabel object with fd {}. Object can't be relabeled", fd);
+            return DentResult{success: false, fd: None, data: None};
+        };
+
+        // label check. Raising is always allowed, lowering requires sufficient privilege
+        let current_label = CURRENT_LABEL.with(|cl| cl.borrow().clone());
+        let lowering = !current_label.can_flow_to(&new_label);
+        if lowering {
+            if !current_label.can_flow_to_with_privilege(&new_label, &fs::utils::get_privilege()) {
+                log::info!("Failed to relabel object with fd {}. Insufficient privilege to lower label", fd);
+                return DentResult{success: false, fd: None, data: None};
+            }
+
+            /*
+                The current label can't write the copy under the lower label, or link it into a directory
+                below the current label. It is declassified with the privilege while the copy is stored,
+                and raised back afterwards, so relabeling never lowers the current label itself.
+             */
+            fs::utils::declassify_with(&fs::utils::get_privilege());
+        }
+
+        /*
+            Objects are stored under their label, so the relabeled object is a copy stored under the new label
+            which replaces the old one under its name in the directory it was opened from or linked into.
+            Storing the copy and replacing the old object happen in one transaction, so either both happen or
+            neither does. If the function already has a transaction open, they become part of it instead.
+         */
+        let own_txn = self.env.txn.begin().is_ok();
+
+        // store a copy of the object under the new label
+        let relabeled = match contents {
+            Contents::File(data) => {
+                let new_entry = self.env.fs.create_file(new_label);
+                match &new_entry {
+                    DirEntry::File(file_objref) => file_objref.write(data, &self.env.fs).ok().map(|_| new_entry.clone()),
+                    _ => None
+                }
+            }
+            Contents::Directory(entries) => {
+                let new_entry = self.env.fs.create_directory(new_label);
+                match &new_entry {
+                    DirEntry::Directory(dir_objref) => entries
+                        .into_iter()
+                        .try_for_each(|(name, dent)| dir_objref.link(name, dent, &self.env.fs).map(|_| ()))
+                        .ok()
+                        .map(|_| new_entry.clone()),
+                    _ => None
+                }
+            }
+            Contents::Blob(name) => self.env.fs.create_blob(new_label, name).ok(),
+            Contents::Gate(Gate::Direct(dg_core)) => self.env.fs.create_direct_gate(new_label, dg_core).ok(),
+            Contents::Gate(Gate::Redirect(rdg_core)) => self.env.fs.create_redirect_gate(new_label, rdg_core).ok(),
+            Contents::Service(service_core) => self.env.fs.create_service(new_label, service_core).ok()
+        };
+
+        let replaced = relabeled.and_then(|relabeled| self.replace_at_origin(fd, &entry, &relabeled).then_some(relabeled));
+        let relabeled = match (replaced, own_txn) {
+            (Some(relabeled), true) => self.env.txn.commit().ok().map(|_| relabeled),
+            (None, true) => { let _ = self.env.txn.abort(); None }
+            (replaced, false) => replaced
+        };
+        if lowering {
+            fs::utils::taint_with_label(current_label);
+        }
+
+        let Some(relabeled) = relabeled else {
+            log::info!("Failed to relabel object with fd {}. Couldn't store relabeled object", fd);
+            return DentResult{success: false, fd: None, data: None};
+        };
+
+        /*
+            Every file descriptor of this function referring to the object now refers to the relabeled copy.
+            Other links to the object, and file descriptors of other functions, keep referring to the old
+            object and its old label.
+         */
+        for dent in self.dents.values_mut() {
+            if *dent == entry {
+                *dent = relabeled.clone();
+            }
+        }
+
+        DentResult{
+            success: true,
+            fd: Some(fd),
+            data: None
+        }
+    }
+
+    /*
+        Replaces the object opened at fd with its relabeled copy under the name it was opened by or linked
+        under. If linking the copy fails, the old object is linked back. An object that was neither opened
+        by name nor linked has nothing to replace. Returns false if the object couldn't be replaced,
+        including when its name was unlinked or refers to another object by now.
+     */
+    fn replace_at_origin(&self, fd: u64, entry: &DirEntry, relabeled: &DirEntry) -> bool {
+        let Some((DirEntry::Directory(parent_dir), name)) = self.dent_origins.get(&fd) else {
+            return true;
+        };
+        if parent_dir.list(&self.env.fs).get(name) != Some(entry) {
+            log::info!("Failed to relabel object with fd {}. {} no longer refers to it", fd, name);
+            return false;
+        }
+
+        if !parent_dir.unlink(name, &self.env.fs).unwrap_or(false) {
+            return false;
+        }
+        if parent_dir.link(name.clone(), relabeled.clone(), &self.env.fs).is_err() {
+            let _ = parent_dir.link(name.clone(), entry.clone(), &self.env.fs);
+            return false;
+        }
+        true
+    }
+
+    pub fn dent_ls_faceted(&mut self, dent_ls_faceted: DentLsFaceted) -> DentLsFacetedResult {
+        let DentLsFaceted { fd: fdir_fd, clearance } = dent_ls_faceted;
+        
+        // clearance defaults to public
+        let clearance = clearance.unwrap_or(Buckle::public());
+
+        let result  = self.dents.get(&fdir_fd).and_then(|entry| {
+            match entry {
+                DirEntry::FacetedDirectory(fdir_objref) => Some(
+                    fdir_objref
+                        .list(&self.env.fs, &clearance)
+                        .iter()
+                        .map(|(label, _)| label.clone().into())
+                        .collect()
+                ),
+                _ => {
+                    log::info!("List failed. Not a faceted directory.");
+                    None
+                }
+            }
+        });
+
+        if let Some(facets) = result {
+            DentLsFacetedResult {success: true, facets} 
+        } else {
+            DentLsFacetedResult{success: false, facets: Default::default()}
+        }
+    }
+
+    pub fn dent_ls_gate(&mut self, gate_fd: u64) -> DentLsGateResult {
+        let result = self.dents.get(&gate_fd).map(Clone::clone).and_then(|entry| {
+            match entry {
+                DirEntry::Gate(gate_objref) => Some(
+                    match gate_objref.get(&self.env.fs).unwrap().unlabel() {
+                        // Case #1: List a Direct Gate
+                        Gate::Direct(dg_core) => {
+                            // open the gate's app image and get its fd
+                            let app_image_fd = {
+                                let blob_id = self.max_blob_id;
+                                self.max_blob_id += 1;
+                                
+                                let Some(blob) = self
+                                    .env.blobstore
+                                    .open(dg_core.function.app_image.clone())
+                                    .ok()
+                                else {
+                                    log::info!("List failed. Failed to open app image.");
+                                    return None;
+                                };
+                                self.blobs.insert(blob_id, blob);
+                                
+                                blob_id
+                            };
+
+                            // open the gate's runtime image and get its fd
+                            let runtime_image_fd = {
+                                let blob_id = self.max_blob_id;
+                                self.max_blob_id += 1;
+
+                                let Some(blob) = self
+                                    .env.blobstore
+                                    .open(dg_core.function.runtime_image.clone())
+                                    .ok()
+                                else {
+                                    log::info!("List failed. Failed to open runtime image.");
+                                    return None;
+                                };
+                                self.blobs.insert(blob_id, blob);
+
+                                blob_id
+                            };
+
+                            // kernel image not used so don't open
+                            
+                            // create the interface version of the function
+                            let function_intf = faasten_interface_types::Function {
+                                memory: dg_core.function.memory as u64,
+                                app_image: app_image_fd,
+                                runtime_image: runtime_image_fd,
+                                kernel: 0 // not used
+                            };
+
+                            // return user-side direct gate
+                            faasten_interface_types::Gate {
+                                kind: Some(gate::Kind::Direct(
+                                    faasten_interface_types::DirectGate { 
+                                        privilege: Some(dg_core.privilege.clone()), 
+                                        invoker_integrity_clearance: Some(dg_core.invoker_integrity_clearance.clone()), 
+                                        function: Some(function_intf), 
+                                        declassify: Some(dg_core.declassify.clone())
+                                    }
+                                ))
+                            }
+                        }
+                        // Case #2: List a Redirect Gate
+                        Gate::Redirect(rdg_core) => {
+                            // return user-side redirect gate
+                            faasten_interface_types::Gate {
+                                kind: Some(gate::Kind::Redirect(
+                                    faasten_interface_types::RedirectGate { 
+                                        privilege: Some(rdg_core.privilege.clone()), 
+                                        invoker_integrity_clearance: Some(rdg_core.invoker_integrity_clearance.clone()), 
+                                        gate: 0, // unused field in this case
+                                        declassify: Some(rdg_core.declassify.clone())
+                                    }
+                                ))
+                            }
+                        }
+                    }
+                ),
+                _ => {
+                    log::info!("List failed. Not a gate.");
+                    None
+                }
+            }
+        });
+
+        DentLsGateResult {success: result.is_some(), gate: result}
+    }
+}
+
+// whether the current privilege owns privilege, i.e may give it to a gate or service
+fn owns(privilege: &Component) -> bool {
+    fs::utils::get_privilege().implies(privilege)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn component(name: &str) -> Component {
+        Buckle::parse(&format!("{},{}", name, name)).unwrap().integrity
+    }
+
+    fn set_privilege(privilege: Component) {
+        fs::utils::set_my_privilge(privilege);
+    }
+
+    fn insert(processor: &mut SyscallProcessor, entry: DirEntry) -> u64 {
+        let fd = processor.max_dent_id;
+        processor.dents.insert(fd, entry);
+        processor.max_dent_id += 1;
+        fd
+    }
+
+    fn create_service(processor: &mut SyscallProcessor, privilege: Component) -> DentResult {
+        processor.dent_create(DentCreate {
+            label: Some(Buckle::public()),
+            kind: Some(dent_create::Kind::Service(Service {
+                taint: Some(Buckle::public()),
+                privilege: Some(privilege),
+                invoker_integrity_clearance: Some(Component::dc_true()),
+                url: "https://example.com/".to_string(),
+                verb: HttpVerb::GET as i32,
+                headers: Default::default()
+            }))
+        })
+    }
+
+    fn service_url(processor: &SyscallProcessor, fd: u64) -> String {
+        let Some(DirEntry::Service(service_objref)) = processor.dents.get(&fd) else {
+            panic!("fd {} isn't a service", fd);
+        };
+        service_objref.get(&processor.env.fs).unwrap().unlabel().url.clone()
+    }
+
+    fn update_service(processor: &mut SyscallProcessor, fd: u64, privilege: Option<Component>, url: Option<&str>) -> bool {
+        processor.dent_update_service(ServiceUpdate {
+            fd,
+            service: Some(Service { privilege, ..Default::default() }),
+            url: url.map(String::from),
+            verb: None
+        }).success
+    }
+
+    fn gate_privilege(processor: &SyscallProcessor, fd: u64) -> Component {
+        let Some(DirEntry::Gate(gate_objref)) = processor.dents.get(&fd) else {
+            panic!("fd {} isn't a gate", fd);
+        };
+        match gate_objref.get(&processor.env.fs).unwrap().unlabel() {
+            Gate::Direct(dg_core) => dg_core.privilege.clone(),
+            Gate::Redirect(rdg_core) => rdg_core.privilege.clone()
+        }
+    }
+
+    fn update_direct_gate(processor: &mut SyscallProcessor, fd: u64, privilege: Option<Component>, memory: u64) -> bool {
+        processor.dent_update(DentUpdate {
+            fd,
+            kind: Some(dent_update::Kind::Gate(faasten_interface_types::Gate {
+                kind: Some(gate::Kind::Direct(faasten_interface_types::DirectGate {
+                    privilege,
+                    invoker_integrity_clearance: None,
+                    function: Some(faasten_interface_types::Function { memory, ..Default::default() }),
+                    declassify: None
+                }))
+            }))
+        }).success
+    }
+
+    fn update_redirect_gate(processor: &mut SyscallProcessor, fd: u64, privilege: Option<Component>) -> bool {
+        processor.dent_update(DentUpdate {
+            fd,
+            kind: Some(dent_update::Kind::Gate(faasten_interface_types::Gate {
+                kind: Some(gate::Kind::Redirect(faasten_interface_types::RedirectGate {
+                    privilege,
+                    invoker_integrity_clearance: None,
+                    gate: 0,
+                    declassify: None
+                }))
+            }))
+        }).success
+    }
+
+    #[test]
+    fn creating_requires_owned_privilege() {
+        let (_dir, mut processor) = SyscallProcessor::for_test(Buckle::public(), component("alice"));
+
+        assert!(!create_service(&mut processor, component("bob")).success);
+        assert!(create_service(&mut processor, component("alice")).success);
+    }
+
+    #[test]
+    fn updating_service_requires_owned_privilege() {
+        let (_dir, mut processor) = SyscallProcessor::for_test(Buckle::public(), component("alice"));
+        let fd = create_service(&mut processor, component("alice")).fd.unwrap();
+
+        // escalating to privilege the function doesn't own fails
+        assert!(!update_service(&mut processor, fd, Some(component("bob")), None));
+
+        // without the service's privilege, neither its url nor its privilege can be changed
+        set_privilege(Component::dc_true());
+        assert!(!update_service(&mut processor, fd, None, Some("https://attacker.example.com/")));
+        assert!(!update_service(&mut processor, fd, Some(Component::dc_true()), None));
+        assert_eq!(service_url(&processor, fd), "https://example.com/");
+
+        // with it, both can
+        set_privilege(component("alice"));
+        assert!(update_service(&mut processor, fd, None, Some("https://example.com/v2")));
+        assert!(update_service(&mut processor, fd, Some(Component::dc_true()), None));
+        assert_eq!(service_url(&processor, fd), "https://example.com/v2");
+    }
+
+    #[test]
+    fn redirecting_secret_bearing_service_requires_its_privilege() {
+        let (_dir, mut processor) = SyscallProcessor::for_test(Buckle::public(), component("alice"));
+        let headers = HashMap::from([("authorization".to_string(), "faasten-secret:/home/alice/api_key".to_string())]);
+        let fd = processor.dent_create(DentCreate {
+            label: Some(Buckle::public()),
+            kind: Some(dent_create::Kind::Service(Service {
+                taint: Some(Buckle::public()),
+                privilege: Some(component("alice")),
+                invoker_integrity_clearance: Some(Component::dc_true()),
+                url: "https://example.com/".to_string(),
+                verb: HttpVerb::GET as i32,
+                headers
+            }))
+        }).fd.unwrap();
+        let update = |url: &str, headers: HashMap<String, String>| DentUpdate {
+            fd,
+            kind: Some(dent_update::Kind::Service(Service { url: url.to_string(), headers, ..Default::default() }))
+        };
+
+        // a function that can write the service object, but lacks its privilege, can't point it elsewhere
+        set_privilege(Component::dc_true());
+        assert!(!processor.dent_update(update("https://attacker.example.com/", HashMap::new())).success);
+        let host = HashMap::from([("host".to_string(), "attacker.example.com".to_string())]);
+        assert!(!processor.dent_update(update("", host)).success);
+        assert_eq!(service_url(&processor, fd), "https://example.com/");
+
+        set_privilege(component("alice"));
+        assert!(processor.dent_update(update("https://api.example.com/", HashMap::new())).success);
+        assert_eq!(service_url(&processor, fd), "https://api.example.com/");
+    }
+
+    #[test]
+    fn updating_direct_gate_requires_owned_privilege() {
+        let (_dir, mut processor) = SyscallProcessor::for_test(Buckle::public(), component("alice"));
+        let fd = processor.gate_for_test(component("alice"), Component::dc_true());
+
+        assert!(!update_direct_gate(&mut processor, fd, Some(component("bob")), 0));
+
+        set_privilege(Component::dc_true());
+        assert!(!update_direct_gate(&mut processor, fd, None, 256));
+        assert!(!update_direct_gate(&mut processor, fd, Some(Component::dc_true()), 0));
+        assert_eq!(gate_privilege(&processor, fd), component("alice"));
+
+        set_privilege(component("alice"));
+        assert!(update_direct_gate(&mut processor, fd, None, 256));
+        assert!(update_direct_gate(&mut processor, fd, Some(Component::dc_true()), 0));
+        assert_eq!(gate_privilege(&processor, fd), Component::dc_true());
+    }
+
+    #[test]
+    fn updating_redirect_gate_requires_owned_privilege() {
+        let (_dir, mut processor) = SyscallProcessor::for_test(Buckle::public(), component("alice"));
+        let inner_fd = processor.gate_for_test(Component::dc_true(), Component::dc_true());
+        let Some(DirEntry::Gate(inner_gate)) = processor.dents.get(&inner_fd).cloned() else {
+            panic!("fd {} isn't a gate", inner_fd);
+        };
+        let gate = processor.env.fs.create_redirect_gate(Buckle::public(), RedirectGate {
+            privilege: component("alice"),
+            invoker_integrity_clearance: Component::dc_true(),
+            declassify: Component::dc_true(),
+            gate: inner_gate
+        }).unwrap();
+        let fd = insert(&mut processor, gate);
+
+        assert!(!update_redirect_gate(&mut processor, fd, Some(component("bob"))));
+
+        set_privilege(Component::dc_true());
+        assert!(!update_redirect_gate(&mut processor, fd, Some(Component::dc_true())));
+        assert_eq!(gate_privilege(&processor, fd), component("alice"));
+
+        set_privilege(component("alice"));
+        assert!(update_redirect_gate(&mut processor, fd, Some(Component::dc_true())));
+        assert_eq!(gate_privilege(&processor, fd), Component::dc_true());
+    }
+
+    // creates a file labeled label holding data, linked into the root directory under name
+    fn linked_file(processor: &mut SyscallProcessor, label: Buckle, name: &str, data: &[u8]) -> u64 {
+        let fd = processor.dent_create(DentCreate {
+            label: Some(label),
+            kind: Some(dent_create::Kind::File)
+        }).fd.unwrap();
+        assert!(processor.dent_update(DentUpdate { fd, kind: Some(dent_update::Kind::File(data.to_vec())) }).success);
+        assert!(processor.dent_link(DentLink { dir_fd: 0, name: name.to_string(), target_fd: fd }).success);
+        fd
+    }
+
+    fn linked(processor: &SyscallProcessor, name: &str) -> Option<DirEntry> {
+        let Some(DirEntry::Directory(root)) = processor.dents.get(&0) else {
+            panic!("fd 0 isn't the root directory");
+        };
+        root.list(&processor.env.fs).get(name).cloned()
+    }
+
+    fn alice() -> Buckle {
+        Buckle::parse("alice,true").unwrap()
+    }
+
+    fn current_label() -> Buckle {
+        CURRENT_LABEL.with(|cl| cl.borrow().clone())
+    }
+
+    #[test]
+    fn raising_label_replaces_linked_object() {
+        let (_dir, mut processor) = SyscallProcessor::for_test(Buckle::public(), Component::dc_true());
+        let fd = linked_file(&mut processor, Buckle::public(), "file", b"data");
+        let old_entry = processor.dents[&fd].clone();
+
+        assert!(processor.dent_relabel(fd, alice()).success);
+
+        assert!(processor.dents[&fd] != old_entry);
+        assert!(linked(&processor, "file").as_ref() == Some(&processor.dents[&fd]));
+        assert_eq!(processor.dent_read(fd).data, Some(b"data".to_vec()));
+    }
+
+    #[test]
+    fn lowering_label_requires_privilege() {
+        let (_dir, mut processor) = SyscallProcessor::for_test(Buckle::public(), Component::dc_true());
+        let fd = linked_file(&mut processor, alice(), "file", b"data");
+        let old_entry = processor.dents[&fd].clone();
+
+        assert!(!processor.dent_relabel(fd, Buckle::public()).success);
+
+        assert!(processor.dents[&fd] == old_entry);
+        assert!(linked(&processor, "file") == Some(old_entry));
+    }
+
+    #[test]
+    fn lowering_label_with_privilege_keeps_current_label() {
+        let (_dir, mut processor) = SyscallProcessor::for_test(Buckle::public(), component("alice"));
+        let fd = linked_file(&mut processor, alice(), "file", b"data");
+        let old_entry = processor.dents[&fd].clone();
+
+        assert!(processor.dent_relabel(fd, Buckle::public()).success);
+
+        assert!(processor.dents[&fd] != old_entry);
+        assert!(linked(&processor, "file").as_ref() == Some(&processor.dents[&fd]));
+        // the object was read at its old label, which the current label still carries
+        assert_eq!(current_label(), alice());
+    }
+
+    #[test]
+    fn relabeling_fails_if_name_refers_to_another_object() {
+        let (_dir, mut processor) = SyscallProcessor::for_test(Buckle::public(), Component::dc_true());
+        let fd = linked_file(&mut processor, Buckle::public(), "file", b"data");
+        let old_entry = processor.dents[&fd].clone();
+
+        // another object takes the name the file was linked under
+        assert!(processor.dent_unlink(DentUnlink { dir_fd: 0, name: "file".to_string() }).success);
+        let other_fd = linked_file(&mut processor, Buckle::public(), "file", b"other");
+        let other_entry = processor.dents[&other_fd].clone();
+
+        assert!(!processor.dent_relabel(fd, alice()).success);
+
+        assert!(processor.dents[&fd] == old_entry);
+        assert!(linked(&processor, "file") == Some(other_entry));
+    }
+}
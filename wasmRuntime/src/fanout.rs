@@ -0,0 +1,247 @@
+use std::time::Instant;
+use faasten_interface_types::DentInvoke;
+use faasten_core::fs::{self, DirEntry};
+use faasten_runtime_interface::{FanOut, FanOutResult, ServiceInvokeResult};
+use labeled::{buckle::Buckle, Label};
+
+use crate::config::{RetryPolicy, ServiceConfig};
+use crate::service::{fails_on_error_status, read_capped, send_with_retries, RequestBody, ResponseBody, ResponseMeta};
+use crate::{current_label, set_label, SyscallProcessor, SyscallProcessorError};
+
+// an invocation of a fan-out that was started but whose result wasn't collected yet
+enum Started {
+    // invocation handle of a gate invoked asynchronously
+    Gate(u64),
+    // failed result of a gate that couldn't be invoked, e.g without a scheduler
+    GateFailed(ServiceInvokeResult),
+    // request to a service with its retry policy, the label the invocation leaves the function at, whether
+    // to write the response to a blob, whether error statuses fail the invocation, and the service's settings
+    Service(Option<(reqwest::blocking::RequestBuilder, RetryPolicy)>, Buckle, bool, bool, ServiceConfig),
+    Failed
+}
+
+// metadata of a service's response, the part of the body that was read, and the response to read the rest from
+type ServiceResponse = (ResponseMeta, Vec<u8>, Option<reqwest::blocking::Response>);
+
+impl SyscallProcessor {
+    /*
+        Invokes a list of gates and services and waits for all of their results. Services are requested
+        in parallel threads, and gates are all sent to the scheduler before any result is collected, each
+        over a connection of its own (see invoke), so every invocation overlaps with the others. The
+        invocation timeout applies to the fan-out as a whole: every result has to arrive within it, counted
+        from the start of the fan-out. Every invocation starts at the label the function had when the
+        fan-out started, so no invocation sees the result of another. Afterwards, the current label is the
+        lub of the labels each invocation would have left the function at on its own.
+     */
+    pub fn fan_out(&mut self, fan_out: FanOut) -> FanOutResult {
+        let start_label = current_label();
+        let timeout = self.env.config.invoke_timeout();
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        let mut started: Vec<Started> = fan_out.invokes.into_iter()
+            .map(|dent_invoke| {
+                set_label(start_label.clone());
+
+                match self.dents.get(&dent_invoke.fd).cloned() {
+                    Some(DirEntry::Gate(_)) => {
+                        let res = self.invoke(DentInvoke { sync: false, ..dent_invoke }, Default::default(), None, None);
+                        match res.fd {
+                            Some(handle) if res.success => Started::Gate(handle),
+                            _ => Started::GateFailed(res)
+                        }
+                    }
+                    Some(DirEntry::Service(service_objref)) => {
+                        let service = service_objref.to_invokable(&self.env.fs);
+
+                        // invocation check, same as dent_invoke
+                        if !fs::utils::get_privilege().implies(&service.invoker_integrity_clearance) {
+                            return Started::Failed;
+                        }
+
+                        fs::utils::declassify_with(&service.privilege);
+                        let settings = self.env.config.service(&service.url);
+                        let request = self.http_request(&service, Some(RequestBody::Bytes(dent_invoke.payload)), dent_invoke.parameters, timeout, &settings)
+                            .ok()
+                            .map(|request| (request, settings.retry_policy(&service.verb.clone().into(), timeout)));
+                        let fail_on_error_status = fails_on_error_status(&service);
+                        Started::Service(request, current_label().lub(service.taint), dent_invoke.toblob, fail_on_error_status, settings)
+                    }
+                    _ => Started::Failed
+                }
+            })
+        .collect();
+        set_label(start_label.clone());
+
+        // send the requests to services in parallel
+        let fixtures = self.env.fixtures.as_ref();
+        let responses: Vec<Option<Result<ServiceResponse, SyscallProcessorError>>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = started.iter_mut()
+                .map(|started| match started {
+                    Started::Service(request, _, toblob, _, settings) => {
+                        let (toblob, max_response_bytes) = (*toblob, settings.max_response_bytes);
+                        request.take().map(|(request, policy)| scope.spawn(move || {
+                            let response = send_with_retries(request, policy, max_response_bytes, fixtures)?;
+                            let meta = ResponseMeta::new(&response);
+
+                            // responses written to a blob are streamed to it after the fan-out's requests are done
+                            if toblob {
+                                return Ok((meta, Vec::new(), Some(response)));
+                            }
+                            let (body, rest) = read_capped(response, max_response_bytes)?;
+                            Ok((meta, body, rest))
+                        }))
+                    }
+                    _ => None
+                })
+            .collect();
+
+            handles.into_iter()
+                .map(|handle| handle.and_then(|handle| handle.join().ok()))
+            .collect()
+        });
+
+        // collect the results. Collecting a gate's result taints the current label with the result's label
+        let mut has_gates = false;
+        let mut end_labels: Vec<Buckle> = Vec::new();
+        let results: Vec<ServiceInvokeResult> = started.into_iter().zip(responses)
+            .map(|(started, response)| match (started, response) {
+                (Started::Gate(handle), _) => {
+                    has_gates = true;
+                    let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+                    let collected = self.invoke_collect(handle, remaining);
+                    if collected.success && !collected.ready {
+                        self.pending_invokes.remove(&handle);
+                        return failed_invoke_with(SyscallProcessorError::Timeout);
+                    }
+                    collected.result.map(Into::into).unwrap_or_else(failed_invoke)
+                }
+                (Started::Service(_, label, toblob, fail_on_error_status, settings), Some(Ok((meta, body, rest)))) => {
+                    end_labels.push(label);
+                    self.service_response_result(meta, body, rest, toblob, fail_on_error_status, &settings)
+                }
+                // the request was sent, so the function is left at the service's label even though it failed
+                (Started::Service(_, label, ..), Some(Err(e))) => {
+                    end_labels.push(label);
+                    failed_invoke_with(e)
+                }
+                (Started::GateFailed(res), _) => {
+                    end_labels.push(start_label.clone());
+                    res
+                }
+                (Started::Service(..), None) | (Started::Failed, _) => {
+                    end_labels.push(start_label.clone());
+                    failed_invoke()
+                }
+            })
+        .collect();
+
+        // gates leave the function at the start label raised by their (declassified) results
+        if has_gates {
+            end_labels.push(current_label());
+        }
+        set_label(end_labels.into_iter().reduce(|a, b| a.lub(b)).unwrap_or(start_label));
+
+        FanOutResult { success: results.iter().all(|r| r.success), results }
+    }
+
+    fn service_response_result(
+        &mut self,
+        mut meta: ResponseMeta,
+        body: Vec<u8>,
+        rest: Option<reqwest::blocking::Response>,
+        toblob: bool,
+        fail_on_error_status: bool,
+        settings: &ServiceConfig
+    ) -> ServiceInvokeResult {
+        // same as dent_invoke, services may count error statuses as failed invocations
+        let success = meta.is_success() || !fail_on_error_status;
+
+        match self.deliver_response(body, rest, toblob, settings) {
+            Ok((body, len)) => {
+                meta.content_length = Some(len);
+                match body {
+                    ResponseBody::Data(data) => meta.into_result(success, None, Some(data)),
+                    ResponseBody::Blob(blobfd) => meta.into_result(success, Some(blobfd), None)
+                }
+            }
+            Err(e) => meta.into_result(false, None, Some(e.to_string().into_bytes()))
+        }
+    }
+}
+
+fn failed_invoke() -> ServiceInvokeResult {
+    ServiceInvokeResult::default()
+}
+
+fn failed_invoke_with(error: SyscallProcessorError) -> ServiceInvokeResult {
+    ServiceInvokeResult { data: Some(error.to_string().into_bytes()), ..failed_invoke() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::net::{TcpListener, TcpStream};
+    use std::time::Duration;
+    use faasten_core::sched::message::ReturnCode;
+    use labeled::buckle::Component;
+
+    // a processor invoking gates through a scheduler listening on the returned listener, and a gate to invoke
+    fn with_scheduler(invoke_timeout_ms: u64) -> (tempfile::TempDir, SyscallProcessor, TcpListener, u64) {
+        let (dir, mut processor) = SyscallProcessor::for_test(Buckle::public(), Component::dc_true());
+        let scheduler = TcpListener::bind("127.0.0.1:0").unwrap();
+        processor.env.config.scheduler_addr = Some(scheduler.local_addr().unwrap());
+        processor.env.config.invoke_timeout_ms = Some(invoke_timeout_ms);
+        let gate = processor.gate_for_test(Component::dc_true(), Component::dc_true());
+        (dir, processor, scheduler, gate)
+    }
+
+    fn gate_invoke(gate: u64) -> DentInvoke {
+        DentInvoke { fd: gate, sync: true, payload: Vec::new(), toblob: false, parameters: Default::default() }
+    }
+
+    // answers every invocation the scheduler receives after its delay, counted from the first accepted one
+    fn answer_after(scheduler: TcpListener, delays: Vec<Duration>) -> std::thread::JoinHandle<()> {
+        std::thread::spawn(move || {
+            let mut conns: Vec<TcpStream> = delays.iter().map(|_| scheduler.accept().unwrap().0).collect();
+            let start = Instant::now();
+            for (conn, delay) in conns.iter_mut().zip(delays) {
+                let mut request = [0; 4096];
+                assert!(io::Read::read(conn, &mut request).unwrap() > 0);
+                std::thread::sleep(delay.saturating_sub(start.elapsed()));
+                // the runtime may have given up on the invocation already
+                let _ = faasten_core::sched::message::write(conn, &crate::task_return(ReturnCode::Success, b"done".to_vec()));
+            }
+        })
+    }
+
+    #[test]
+    fn gates_run_concurrently() {
+        let (_dir, mut processor, scheduler, gate) = with_scheduler(1000);
+        // one after the other, the gates would take longer than the timeout
+        let scheduler = answer_after(scheduler, vec![Duration::from_millis(600), Duration::from_millis(700)]);
+
+        let res = processor.fan_out(FanOut { invokes: vec![gate_invoke(gate), gate_invoke(gate)] });
+        assert!(res.success);
+        assert!(res.results.iter().all(|result| result.data == Some(b"done".to_vec())));
+        scheduler.join().unwrap();
+    }
+
+    #[test]
+    fn timeout_applies_to_whole_fan_out() {
+        let (_dir, mut processor, scheduler, gate) = with_scheduler(1000);
+        // the second gate finishes within the timeout of its own, but not within the fan-out's
+        let scheduler = answer_after(scheduler, vec![Duration::from_millis(600), Duration::from_millis(1400)]);
+
+        let start = Instant::now();
+        let res = processor.fan_out(FanOut { invokes: vec![gate_invoke(gate), gate_invoke(gate)] });
+        assert!(start.elapsed() < Duration::from_millis(1400));
+        assert!(!res.success);
+        assert!(res.results[0].success);
+        assert!(!res.results[1].success);
+        assert_eq!(res.results[1].data, Some(SyscallProcessorError::Timeout.to_string().into_bytes()));
+        // the timed out invocation was dropped along with its connection
+        assert!(processor.pending_invokes.is_empty());
+        scheduler.join().unwrap();
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{FixtureConfig, FixtureMode, RetryPolicy};
+use crate::service::{read_capped, send_over_network};
+use crate::SyscallProcessorError;
+
+/*
+    A request to a service and the response it got, as stored in a fixture file. Request headers
+    aren't stored, so secrets filled into them never end up in the file.
+ */
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Exchange {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub url: String,
+    // None for bodies streamed from a blob. Matches any body when replaying
+    #[serde(default)]
+    pub body: Option<Vec<u8>>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    pub body: Vec<u8>,
+}
+
+/*
+    Serves requests to services from a fixture file instead of the network (replay mode), or
+    sends them and appends each request and its response to the file (record mode). Used to
+    test functions that invoke services without network access.
+
+    Recording starts a new file and rewrites it whole after every exchange. Each runtime process
+    runs one function, so recording several invocations at once, e.g of gates invoking each other,
+    leaves only the exchanges of the process that wrote last. Record one invocation at a time.
+ */
+pub struct Fixtures {
+    mode: FixtureMode,
+    path: String,
+    // the exchanges, and whether each was already replayed
+    exchanges: Mutex<Vec<(Exchange, bool)>>,
+}
+
+impl Fixtures {
+    pub fn load(config: &FixtureConfig) -> Result<Self, String> {
+        // recording starts a new fixture file
+        let exchanges: Vec<Exchange> = match config.mode {
+            FixtureMode::Replay => {
+                let contents = std::fs::read_to_string(&config.path)
+                    .map_err(|e| format!("Couldn't read fixture file {}. {}", config.path, e))?;
+                serde_json::from_str(&contents)
+                    .map_err(|e| format!("Couldn't parse fixture file {}. {}", config.path, e))?
+            }
+            FixtureMode::Record => Vec::new()
+        };
+
+        Ok(Self {
+            mode: config.mode,
+            path: config.path.clone(),
+            exchanges: Mutex::new(exchanges.into_iter().map(|exchange| (exchange, false)).collect()),
+        })
+    }
+
+    /*
+        Sends a request, replaying or recording it. Recorded responses are read whole, so responses
+        longer than max_response_bytes (if set) fail instead of being recorded.
+     */
+    pub fn send(
+        &self,
+        request: reqwest::blocking::RequestBuilder,
+        policy: RetryPolicy,
+        max_response_bytes: Option<u64>
+    ) -> Result<reqwest::blocking::Response, SyscallProcessorError> {
+        let (client, request) = request.build_split();
+        let request = request.map_err(SyscallProcessorError::from_http)?;
+        let recorded_request = RecordedRequest {
+            method: request.method().to_string(),
+            url: request.url().to_string(),
+            body: request.body().and_then(|body| body.as_bytes()).map(Vec::from),
+        };
+
+        match self.mode {
+            FixtureMode::Replay => self.replay(&recorded_request),
+            FixtureMode::Record => {
+                let request = reqwest::blocking::RequestBuilder::from_parts(client, request);
+                let response = send_over_network(request, policy)?;
+                let status = response.status().as_u16();
+                let headers = response.headers().iter()
+                    .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+                    .collect();
+
+                // the response is read whole to be recorded, then handed on as if it came from the network
+                let (body, rest) = read_capped(response, max_response_bytes)?;
+                if rest.is_some() {
+                    log::info!("Response to {} {} is too large to record", recorded_request.method, recorded_request.url);
+                    return Err(SyscallProcessorError::ResponseTooLarge);
+                }
+                let recorded_response = RecordedResponse { status, headers, body };
+                self.record(Exchange { request: recorded_request, response: recorded_response.clone() })?;
+                into_response(recorded_response)
+            }
+        }
+    }
+
+    // the first matching exchange that wasn't replayed yet, or the last matching one if all were
+    fn replay(&self, request: &RecordedRequest) -> Result<reqwest::blocking::Response, SyscallProcessorError> {
+        let mut exchanges = self.exchanges.lock().unwrap();
+        let matches = |recorded: &RecordedRequest| recorded.method == request.method
+            && recorded.url == request.url
+            && (recorded.body.is_none() || recorded.body == request.body);
+
+        let position = exchanges.iter().position(|(exchange, replayed)| !replayed && matches(&exchange.request))
+            .or_else(|| exchanges.iter().rposition(|(exchange, _)| matches(&exchange.request)));
+        let Some(position) = position else {
+            log::info!("No recorded response for {} {}", request.method, request.url);
+            return Err(SyscallProcessorError::NoFixture);
+        };
+
+        exchanges[position].1 = true;
+        into_response(exchanges[position].0.response.clone())
+    }
+
+    /*
+        The fixture file is rewritten after every exchange, so a crashing function keeps what was
+        recorded. It is written to a file of this process and renamed over the fixture file, so the
+        fixture file is never left half written.
+     */
+    fn record(&self, exchange: Exchange) -> Result<(), SyscallProcessorError> {
+        let mut exchanges = self.exchanges.lock().unwrap();
+        exchanges.push((exchange, false));
+
+        let recorded: Vec<&Exchange> = exchanges.iter().map(|(exchange, _)| exchange).collect();
+        let contents = serde_json::to_string_pretty(&recorded).map_err(|e| SyscallProcessorError::Fixture(e.into()))?;
+        let tmp_path = format!("{}.{}.tmp", self.path, std::process::id());
+        std::fs::write(&tmp_path, contents)
+            .and_then(|_| std::fs::rename(&tmp_path, &self.path))
+            .map_err(SyscallProcessorError::Fixture)
+    }
+}
+
+/*
+    Turns a recorded response back into a response. The final url isn't recorded, so the url
+    returned for replayed responses is reqwest's placeholder.
+ */
+fn into_response(recorded: RecordedResponse) -> Result<reqwest::blocking::Response, SyscallProcessorError> {
+    let mut response = http::Response::builder().status(recorded.status);
+    for (name, value) in &recorded.headers {
+        response = response.header(name, value);
+    }
+    let response = response.body(recorded.body)
+        .map_err(|e| SyscallProcessorError::Fixture(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+    Ok(response.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exchange(method: &str, url: &str, body: Option<&str>, response: &str) -> Exchange {
+        Exchange {
+            request: RecordedRequest { method: method.to_string(), url: url.to_string(), body: body.map(Vec::from) },
+            response: RecordedResponse { status: 200, headers: HashMap::new(), body: Vec::from(response) },
+        }
+    }
+
+    fn request(method: &str, url: &str, body: Option<&str>) -> RecordedRequest {
+        exchange(method, url, body, "").request
+    }
+
+    fn replaying(exchanges: Vec<Exchange>) -> Fixtures {
+        Fixtures {
+            mode: FixtureMode::Replay,
+            path: String::new(),
+            exchanges: Mutex::new(exchanges.into_iter().map(|exchange| (exchange, false)).collect()),
+        }
+    }
+
+    fn replayed(fixtures: &Fixtures, request: &RecordedRequest) -> Option<String> {
+        fixtures.replay(request).ok().map(|response| response.text().unwrap())
+    }
+
+    #[test]
+    fn replay_matches_method_url_and_body() {
+        let fixtures = replaying(vec![
+            exchange("GET", "https://a.com/", None, "get"),
+            exchange("POST", "https://a.com/", Some("1"), "post 1"),
+            exchange("POST", "https://a.com/", Some("2"), "post 2"),
+        ]);
+
+        assert_eq!(replayed(&fixtures, &request("POST", "https://a.com/", Some("2"))).as_deref(), Some("post 2"));
+        assert_eq!(replayed(&fixtures, &request("POST", "https://a.com/", Some("1"))).as_deref(), Some("post 1"));
+        // exchanges recorded without a body match any body
+        assert_eq!(replayed(&fixtures, &request("GET", "https://a.com/", Some("x"))).as_deref(), Some("get"));
+
+        assert!(matches!(fixtures.replay(&request("POST", "https://a.com/", Some("3"))), Err(SyscallProcessorError::NoFixture)));
+        assert!(matches!(fixtures.replay(&request("PUT", "https://a.com/", None)), Err(SyscallProcessorError::NoFixture)));
+        assert!(matches!(fixtures.replay(&request("GET", "https://b.com/", None)), Err(SyscallProcessorError::NoFixture)));
+    }
+
+    #[test]
+    fn replay_serves_exchanges_in_order_then_repeats_the_last() {
+        let fixtures = replaying(vec![
+            exchange("GET", "https://a.com/", None, "first"),
+            exchange("GET", "https://b.com/", None, "other"),
+            exchange("GET", "https://a.com/", None, "second"),
+        ]);
+        let get = request("GET", "https://a.com/", None);
+
+        assert_eq!(replayed(&fixtures, &get).as_deref(), Some("first"));
+        assert_eq!(replayed(&fixtures, &get).as_deref(), Some("second"));
+        assert_eq!(replayed(&fixtures, &get).as_deref(), Some("second"));
+    }
+
+    #[test]
+    fn recorded_exchanges_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fixtures.json").to_str().unwrap().to_string();
+
+        let recording = Fixtures::load(&FixtureConfig { path: path.clone(), mode: FixtureMode::Record }).unwrap();
+        recording.record(exchange("GET", "https://a.com/", None, "a")).unwrap();
+        recording.record(exchange("POST", "https://a.com/", Some("1"), "b")).unwrap();
+        // only the fixture file is left behind
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let replaying = Fixtures::load(&FixtureConfig { path, mode: FixtureMode::Replay }).unwrap();
+        assert_eq!(replayed(&replaying, &request("POST", "https://a.com/", Some("1"))).as_deref(), Some("b"));
+        assert_eq!(replayed(&replaying, &request("GET", "https://a.com/", None)).as_deref(), Some("a"));
+    }
+}
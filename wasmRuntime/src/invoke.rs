@@ -0,0 +1,657 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+use faasten_interface_types::{DentInvoke, DentInvokeResult, DentResult};
+use faasten_core::fs::{self, DirEntry, CURRENT_LABEL, PRIVILEGE};
+use faasten_core::sched::message::{LabeledInvoke, ReturnCode, TaskReturn};
+use faasten_runtime_interface::{DentInvokeBlobs, InvokeAwait, InvokeHandleResult, ServiceInvokeResult};
+use labeled::{buckle::{Buckle, Component}, HasPrivilege};
+
+use crate::blobs::BlobReader;
+use crate::service::{fails_on_error_status, read_capped, RequestBody, ResponseBody, ResponseMeta};
+use crate::{SyscallProcessor, SyscallProcessorError};
+
+// how long the rest of a result may take to arrive once it started arriving, unless the wait is longer
+const RESULT_READ_TIMEOUT: Duration = Duration::from_secs(5);
+// how long to wait for a connection to the scheduler
+const SCHEDULER_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/*
+    A gate invocation whose result wasn't delivered to the function yet. toblob is whether to
+    write the result to a blob, declassify is the gate's declassify component. conn is the
+    connection to the scheduler the invocation was sent over, which its result arrives on.
+ */
+pub(crate) struct PendingInvoke {
+    toblob: bool,
+    declassify: Component,
+    conn: TcpStream,
+}
+
+/*
+    Invocation of gates and services by the syscall processor.
+ */
+impl SyscallProcessor {
+    pub fn dent_invoke(&mut self, dent_invoke: DentInvoke) -> DentInvokeResult {
+        let timeout = self.env.config.invoke_timeout();
+        self.invoke(dent_invoke, Default::default(), timeout, None).into()
+    }
+
+    pub fn dent_invoke_blobs(&mut self, dent_invoke_blobs: DentInvokeBlobs) -> DentInvokeResult {
+        self.invoke_blobs(dent_invoke_blobs).into()
+    }
+
+    // same as dent_invoke_blobs, but only invokes services and also returns the response's metadata
+    pub fn service_invoke(&mut self, dent_invoke_blobs: DentInvokeBlobs) -> ServiceInvokeResult {
+        let fd = dent_invoke_blobs.invoke.as_ref().map(|invoke| invoke.fd);
+        if !matches!(fd.and_then(|fd| self.dents.get(&fd)), Some(DirEntry::Service(_))) {
+            log::info!("Invoke failed. {:?} isn't the fd of a service", fd);
+            return ServiceInvokeResult::default();
+        }
+        self.invoke_blobs(dent_invoke_blobs)
+    }
+
+    fn invoke_blobs(&mut self, dent_invoke_blobs: DentInvokeBlobs) -> ServiceInvokeResult {
+        let DentInvokeBlobs { invoke, payload_blob, blobs, timeout_ms } = dent_invoke_blobs;
+        let failed = ServiceInvokeResult::default();
+
+        let Some(mut dent_invoke) = invoke else {
+            return failed;
+        };
+
+        // the payload comes from a blob instead of the invoke message. Services get the blob streamed
+        // as the request body, gates get its contents
+        let mut payload_stream = None;
+        if let Some(payload_fd) = payload_blob {
+            let read = match self.dents.get(&dent_invoke.fd) {
+                Some(DirEntry::Service(_)) => self.blob_reader(payload_fd).map(|reader| payload_stream = Some(reader)),
+                _ => self.blob_contents(payload_fd).map(|payload| dent_invoke.payload = payload)
+            };
+            if read.is_none() {
+                log::info!("Invoke failed. Couldn't read payload blob {}", payload_fd);
+                return failed;
+            }
+        }
+
+        let blob_names = match self.invoked_blobs(blobs) {
+            Ok(blob_names) => blob_names,
+            Err(blobfd) => {
+                log::info!("Invoke failed. No blob with fd {}", blobfd);
+                return failed;
+            }
+        };
+
+        let timeout = timeout_ms.map(Duration::from_millis).or(self.env.config.invoke_timeout());
+        self.invoke(dent_invoke, blob_names, timeout, payload_stream)
+    }
+
+    /*
+        Invokes a gate or a service. blobs are only passed to gates, services have no way of
+        receiving them. payload_stream, if set, is sent to services as the request body in place
+        of the payload. Synchronous invocations wait at most timeout (forever if None) for the
+        gate's result or the service's response. Results of services carry the response's metadata.
+     */
+    pub(crate) fn invoke(
+        &mut self,
+        dent_invoke: DentInvoke,
+        blobs: HashMap<String, String>,
+        timeout: Option<Duration>,
+        payload_stream: Option<BlobReader>
+    ) -> ServiceInvokeResult {
+        let DentInvoke { fd, sync, payload, toblob, parameters } = dent_invoke;
+        let mut error: Option<SyscallProcessorError> = None;
+        let mut response_meta: Option<ResponseMeta> = None;
+
+        let (blobfd, data) = self.dents.get(&fd)
+            .cloned()
+            .and_then(|entry| 
+                match entry {
+                    DirEntry::Gate(gate_objref) => {
+                        let gate = gate_objref.to_invokable(&self.env.fs);
+
+                        // invocation check. Current privilege must be at least as strong as gate's invoker clearance requirement
+                        if !fs::utils::get_privilege().implies(&gate.invoker_integrity_clearance) {
+                            return None;
+                        }
+
+                        /*
+                            Scheduler results carry no invocation id, so every invocation is sent over a
+                            connection of its own, which its result arrives on. Invocations don't wait for
+                            each other that way. Results are always requested, asynchronous invocations
+                            collect theirs later through the invocation handle.
+                         */
+                        let request = LabeledInvoke {
+                            function: Some(gate.function.into()),
+                            label: Some(CURRENT_LABEL.with(|cl| cl.borrow().clone().into())),
+                            gate_privilege: Some(gate.privilege.into()),
+                            blobs,
+                            payload,
+                            headers: parameters,
+                            sync: true,
+                            invoker: Some(PRIVILEGE.with(|p| p.borrow().clone().into()))
+                        };
+
+                        let Some(conn) = self.send_invoke(request) else {
+                            error = Some(SyscallProcessorError::UnreachableScheduler);
+                            return None;
+                        };
+
+                        let handle = self.max_invoke_id;
+                        self.max_invoke_id += 1;
+                        self.pending_invokes.insert(handle, PendingInvoke {
+                            toblob,
+                            declassify: gate.declassify.clone(),
+                            conn
+                        });
+
+                        // whether to wait for result or not
+                        if !sync {
+                            Some((Some(handle), None, None))
+                        } else {
+                            let collected = self.invoke_collect(handle, timeout);
+                            if collected.success && !collected.ready {
+                                // closing the connection discards the result
+                                self.pending_invokes.remove(&handle);
+                                error = Some(SyscallProcessorError::Timeout);
+                                return None;
+                            }
+                            collected.result.map(|res| (res.fd, res.data))
+                        }
+                    }
+                    DirEntry::Service(service_objref) => {
+                        let service = service_objref.to_invokable(&self.env.fs);
+                        let settings = self.env.config.service(&service.url);
+
+                        // invocation check. Current privilege must be at least as strong as service's invoker clearance requirement
+                        if !fs::utils::get_privilege().implies(&service.invoker_integrity_clearance) {
+                            return None;
+                        }
+
+                        // declassify the current label before making http request
+                        fs::utils::declassify_with(&service.privilege);
+                        let body = match payload_stream {
+                            Some(reader) => RequestBody::Blob(reader),
+                            None => RequestBody::Bytes(payload)
+                        };
+                        let send_res = self.http_send(&service, Some(body), parameters, timeout, &settings);
+
+                        // re-taint the current label according to the service taint
+                        fs::utils::taint_with_label(service.taint);
+
+                        // process the response of the http request
+                        match send_res {
+                            Ok(response) => {
+                                let mut meta = ResponseMeta::new(&response);
+
+                                // services may count error statuses as failed invocations
+                                if fails_on_error_status(&service) && !meta.is_success() {
+                                    error = Some(SyscallProcessorError::HttpStatus(meta.status));
+                                }
+
+                                // whether to write response to blob or not. Responses written to a blob are never buffered
+                                let read = if toblob {
+                                    Ok((Vec::new(), Some(response)))
+                                } else {
+                                    read_capped(response, settings.max_response_bytes)
+                                };
+
+                                let delivered = read.and_then(|(body, rest)| self.deliver_response(body, rest, toblob, &settings));
+                                if let Ok((_, len)) = &delivered {
+                                    meta.content_length = Some(*len);
+                                }
+                                response_meta = Some(meta);
+                                match delivered {
+                                    Ok((ResponseBody::Data(data), _)) => Some((None, Some(data))),
+                                    Ok((ResponseBody::Blob(blobfd), _)) => Some((Some(blobfd), None)),
+                                    Err(e) => {
+                                        error = Some(e);
+                                        None
+                                    }
+                                }
+                            },
+                            Err(e) => {
+                                error = Some(e);
+                                None
+                            }
+                        }
+                    },
+                    _ => None
+                }
+            )
+        .unwrap_or((None, None));
+
+        let success = (blobfd.is_some() || data.is_some()) && error.is_none();
+        // failed invocations without a response body describe the error, e.g a timeout
+        let data = data.or_else(|| error.map(|e| e.to_string().into_bytes()));
+        match response_meta {
+            Some(meta) => meta.into_result(success, blobfd, data),
+            None => ServiceInvokeResult { success, fd: blobfd, data, ..Default::default() }
+        }
+    }
+
+    pub fn invoke_poll(&mut self, handle: u64) -> InvokeHandleResult {
+        self.invoke_collect(handle, Some(Duration::ZERO))
+    }
+
+    pub fn invoke_await(&mut self, invoke_await: InvokeAwait) -> InvokeHandleResult {
+        let InvokeAwait { handle, timeout_ms } = invoke_await;
+        self.invoke_collect(handle, timeout_ms.map(Duration::from_millis))
+    }
+
+    // closing the invocation's connection discards its result
+    pub fn invoke_cancel(&mut self, handle: u64) -> DentResult {
+        let success = self.pending_invokes.remove(&handle).is_some();
+        DentResult { success, fd: Some(handle), data: None }
+    }
+
+    /*
+        Collects the result of an asynchronous invocation, waiting at most wait for it to arrive
+        (forever if None). An invocation whose result can't be read, e.g because the scheduler closed
+        the connection, is collected as failed.
+     */
+    pub(crate) fn invoke_collect(&mut self, handle: u64, wait: Option<Duration>) -> InvokeHandleResult {
+        let Some(mut pending) = self.pending_invokes.remove(&handle) else {
+            log::info!("Collect failed. No invocation with handle {}", handle);
+            return InvokeHandleResult { success: false, ready: false, result: None };
+        };
+
+        let res = match read_result(&mut pending.conn, wait) {
+            Ok(Some(res)) => res,
+            Ok(None) => {
+                self.pending_invokes.insert(handle, pending);
+                return InvokeHandleResult { success: true, ready: false, result: None };
+            }
+            Err(e) => {
+                log::info!("Collect failed. {}", e);
+                crate::task_return(ReturnCode::ProcessRequestFailed, e.to_string().into_bytes())
+            }
+        };
+        InvokeHandleResult { success: true, ready: true, result: Some(self.task_return_result(res, pending)) }
+    }
+
+    /*
+        Sends an invocation to the scheduler over a new connection, which is returned so the result can
+        be read from it. Returns None if no scheduler is configured or the invocation can't be sent.
+     */
+    fn send_invoke(&self, request: LabeledInvoke) -> Option<TcpStream> {
+        let Some(addr) = self.env.config.scheduler_addr else {
+            log::info!("Invoke failed. No scheduler configured");
+            return None;
+        };
+
+        match TcpStream::connect_timeout(&addr, SCHEDULER_CONNECT_TIMEOUT) {
+            Ok(mut conn) if faasten_core::sched::rpc::labeled_invoke(&mut conn, request).is_ok() => Some(conn),
+            Ok(_) => {
+                log::info!("Invoke failed. Couldn't send request to the scheduler");
+                None
+            }
+            Err(e) => {
+                log::info!("Invoke failed. Couldn't connect to the scheduler. {}", e);
+                None
+            }
+        }
+    }
+
+    /*
+        Delivers the result of a gate invocation to the function. Declassifies the result's label with
+        the gate's declassify component and taints the current label with it. If toblob is set, writes
+        the result to a new blob. Failed functions return their error message, if any, which is returned
+        as the failed invocation's data.
+     */
+    fn task_return_result(&mut self, res: TaskReturn, pending: PendingInvoke) -> DentInvokeResult {
+        let PendingInvoke { toblob, declassify, .. } = pending;
+        let failed = |data: Option<Vec<u8>>| DentInvokeResult { success: false, fd: None, data, headers: Default::default() };
+
+        let res_label: Buckle = res.label
+            .clone()
+            .map(|rpc_label| -> Buckle {rpc_label.into()})
+        .unwrap_or(Buckle::public());
+        
+        // the gate vouches for its result, which lets it act as a declassifier for its function
+        fs::utils::taint_with_label(res_label.downgrade(&declassify));
+
+        let body = res.payload.and_then(|payload| payload.body);
+        if res.code != ReturnCode::Success as i32 {
+            log::info!("Invoke failed. Function returned code {}", res.code);
+            return failed(body);
+        }
+        let Some(body) = body else {
+            log::info!("Invoke failed. No result");
+            return failed(None);
+        };
+
+        // whether to write function's result to a blob or not
+        if !toblob {
+            DentInvokeResult { success: true, fd: None, data: Some(body), headers: Default::default() }
+        } else {
+            let saved = self.env.blobstore.create().and_then(|mut newblob| {
+                newblob.write_all(&body)?;
+                self.env.blobstore.save(newblob)
+            });
+            let blob = match saved {
+                Ok(blob) => blob,
+                Err(e) => {
+                    log::info!("Invoke failed. Couldn't write result to blob. {}", e);
+                    return failed(None);
+                }
+            };
+
+            let blobfd = self.max_blob_id;
+            self.max_blob_id += 1;
+            self.blobs.insert(blobfd, blob);
+
+            DentInvokeResult { success: true, fd: Some(blobfd), data: None, headers: Default::default() }
+        }
+    }
+}
+
+/*
+    Reads the result of an invocation from its scheduler connection. Waits at most wait (forever if None)
+    for the result to start arriving, and returns None if it doesn't. The read timeout stays set while the
+    rest of the result is read. The connection only carries this result, so it is dropped afterwards and
+    its read timeout doesn't need to be reset.
+ */
+fn read_result(conn: &mut TcpStream, wait: Option<Duration>) -> io::Result<Option<TaskReturn>> {
+    if let Some(wait) = wait {
+        if !result_arriving(conn, wait)? {
+            return Ok(None);
+        }
+        conn.set_read_timeout(Some(wait.max(RESULT_READ_TIMEOUT)))?;
+    }
+
+    match faasten_core::sched::message::read::<TaskReturn>(conn) {
+        Ok(res) => Ok(Some(res)),
+        Err(_) => Err(io::Error::other("couldn't read result from the scheduler"))
+    }
+}
+
+/*
+    Whether a result is arriving on a scheduler connection, waiting at most wait for it to start.
+    Only peeks, so nothing is read.
+ */
+fn result_arriving(conn: &mut TcpStream, wait: Duration) -> io::Result<bool> {
+    if wait.is_zero() {
+        conn.set_nonblocking(true)?;
+    } else {
+        conn.set_read_timeout(Some(wait))?;
+    }
+    let peeked = conn.peek(&mut [0; 1]);
+    conn.set_nonblocking(false)?;
+    conn.set_read_timeout(None)?;
+
+    match peeked {
+        // a closed connection is reported by the read that follows
+        Ok(_) => Ok(true),
+        Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => Ok(false),
+        Err(e) => Err(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::time::Instant;
+    use faasten_interface_types::{dent_create, DentCreate, Service};
+    use crate::service::FAIL_ON_ERROR_STATUS_HEADER;
+
+    fn connected() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        (client, server)
+    }
+
+    #[test]
+    fn result_arriving_waits_at_most_wait() {
+        let (mut conn, _server) = connected();
+
+        assert!(!result_arriving(&mut conn, Duration::ZERO).unwrap());
+        let start = Instant::now();
+        assert!(!result_arriving(&mut conn, Duration::from_millis(50)).unwrap());
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        // the connection is blocking again, without a read timeout
+        assert_eq!(conn.read_timeout().unwrap(), None);
+    }
+
+    #[test]
+    fn result_arriving_only_peeks() {
+        let (mut conn, mut server) = connected();
+        server.write_all(&[7, 8]).unwrap();
+
+        assert!(result_arriving(&mut conn, Duration::from_secs(5)).unwrap());
+        assert!(result_arriving(&mut conn, Duration::ZERO).unwrap());
+        let mut buf = [0; 2];
+        io::Read::read_exact(&mut conn, &mut buf).unwrap();
+        assert_eq!(buf, [7, 8]);
+    }
+
+    #[test]
+    fn closed_connection_is_left_to_the_read() {
+        let (mut conn, server) = connected();
+        drop(server);
+
+        assert!(result_arriving(&mut conn, Duration::from_secs(5)).unwrap());
+    }
+
+    // a processor invoking gates through a scheduler listening on the returned listener, and a gate to invoke
+    fn with_scheduler() -> (tempfile::TempDir, SyscallProcessor, TcpListener, u64) {
+        let (dir, mut processor) = SyscallProcessor::for_test(Buckle::public(), Component::dc_true());
+        let scheduler = TcpListener::bind("127.0.0.1:0").unwrap();
+        processor.env.config.scheduler_addr = Some(scheduler.local_addr().unwrap());
+        let gate = processor.gate_for_test(Component::dc_true(), Component::dc_true());
+        (dir, processor, scheduler, gate)
+    }
+
+    fn spawn(processor: &mut SyscallProcessor, gate: u64, payload: &[u8]) -> u64 {
+        let res = processor.dent_invoke(DentInvoke { fd: gate, sync: false, payload: payload.to_vec(), toblob: false, parameters: Default::default() });
+        assert!(res.success);
+        res.fd.unwrap()
+    }
+
+    // answers the invocation sent over conn with a successful result holding body
+    fn reply(conn: &mut TcpStream, body: &[u8]) {
+        reply_labeled(conn, body, Buckle::public());
+    }
+
+    // same as reply, with a result the function returned at label
+    fn reply_labeled(conn: &mut TcpStream, body: &[u8], label: Buckle) {
+        let mut request = [0; 4096];
+        assert!(io::Read::read(conn, &mut request).unwrap() > 0);
+        let result = TaskReturn { label: Some(label.into()), ..crate::task_return(ReturnCode::Success, body.to_vec()) };
+        faasten_core::sched::message::write(conn, &result).unwrap();
+    }
+
+    fn await_result(processor: &mut SyscallProcessor, handle: u64) -> InvokeHandleResult {
+        processor.invoke_await(InvokeAwait { handle, timeout_ms: Some(5000) })
+    }
+
+    #[test]
+    fn async_invocations_are_in_flight_together() {
+        let (_dir, mut processor, scheduler, gate) = with_scheduler();
+        let first = spawn(&mut processor, gate, b"first");
+        let second = spawn(&mut processor, gate, b"second");
+
+        // both invocations reached the scheduler, and the second one finishes first
+        let (mut first_conn, _) = scheduler.accept().unwrap();
+        let (mut second_conn, _) = scheduler.accept().unwrap();
+        reply(&mut second_conn, b"second result");
+
+        let collected = await_result(&mut processor, second);
+        assert!(collected.ready);
+        assert_eq!(collected.result.unwrap().data, Some(b"second result".to_vec()));
+        assert!(!processor.invoke_poll(first).ready);
+
+        reply(&mut first_conn, b"first result");
+        let collected = await_result(&mut processor, first);
+        assert!(collected.ready);
+        assert_eq!(collected.result.unwrap().data, Some(b"first result".to_vec()));
+        // results can only be collected once
+        assert!(!processor.invoke_poll(first).success);
+    }
+
+    #[test]
+    fn lost_connection_only_fails_its_invocation() {
+        let (_dir, mut processor, scheduler, gate) = with_scheduler();
+        let first = spawn(&mut processor, gate, b"first");
+        let second = spawn(&mut processor, gate, b"second");
+        let (first_conn, _) = scheduler.accept().unwrap();
+        let (mut second_conn, _) = scheduler.accept().unwrap();
+
+        drop(first_conn);
+        let collected = await_result(&mut processor, first);
+        assert!(collected.success && collected.ready);
+        assert!(!collected.result.unwrap().success);
+
+        reply(&mut second_conn, b"second result");
+        assert!(await_result(&mut processor, second).result.unwrap().success);
+    }
+
+    #[test]
+    fn cancelled_invocation_closes_its_connection() {
+        let (_dir, mut processor, scheduler, gate) = with_scheduler();
+        let handle = spawn(&mut processor, gate, b"payload");
+        let (mut conn, _) = scheduler.accept().unwrap();
+
+        assert!(processor.invoke_cancel(handle).success);
+        assert!(!processor.invoke_cancel(handle).success);
+        // reading ends once the processor closed its side
+        conn.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut request = Vec::new();
+        io::Read::read_to_end(&mut conn, &mut request).unwrap();
+    }
+
+    #[test]
+    fn gates_need_a_scheduler() {
+        let (_dir, mut processor) = SyscallProcessor::for_test(Buckle::public(), Component::dc_true());
+        let gate = processor.gate_for_test(Component::dc_true(), Component::dc_true());
+
+        let res = processor.dent_invoke(DentInvoke { fd: gate, sync: true, payload: Vec::new(), toblob: false, parameters: Default::default() });
+        assert!(!res.success);
+        assert_eq!(res.data, Some(SyscallProcessorError::UnreachableScheduler.to_string().into_bytes()));
+    }
+
+    fn alice() -> Buckle {
+        Buckle::parse("alice,true").unwrap()
+    }
+
+    // invokes a gate with the given declassify component whose function returns a result labeled alice
+    fn invoke_alice_gate(declassify: Component) -> (tempfile::TempDir, SyscallProcessor, DentInvokeResult) {
+        let (dir, mut processor, scheduler, _) = with_scheduler();
+        let gate = processor.gate_for_test(Component::dc_true(), declassify);
+        let handle = spawn(&mut processor, gate, b"payload");
+        let (mut conn, _) = scheduler.accept().unwrap();
+        reply_labeled(&mut conn, b"secret", alice());
+
+        let collected = await_result(&mut processor, handle);
+        assert!(collected.ready);
+        (dir, processor, collected.result.unwrap())
+    }
+
+    #[test]
+    fn gate_declassifies_its_result() {
+        let alice_component = Buckle::parse("alice,alice").unwrap().integrity;
+        let (_dir, _processor, res) = invoke_alice_gate(alice_component);
+
+        assert!(res.success);
+        assert!(CURRENT_LABEL.with(|cl| cl.borrow().clone()) == Buckle::public());
+    }
+
+    #[test]
+    fn gate_without_declassify_taints_with_result_label() {
+        let (_dir, _processor, res) = invoke_alice_gate(Component::dc_true());
+
+        assert!(res.success);
+        assert!(CURRENT_LABEL.with(|cl| cl.borrow().clone()) == alice());
+    }
+
+    // serves 404 responses with "missing" as the body, returns the url it listens on
+    fn missing_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://127.0.0.1:{}/", listener.local_addr().unwrap().port());
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let mut request = Vec::new();
+                let mut byte = [0; 1];
+                while !request.ends_with(b"\r\n\r\n") && io::Read::read(&mut stream, &mut byte).map_or(false, |n| n == 1) {
+                    request.push(byte[0]);
+                }
+                let _ = stream.write_all(b"HTTP/1.1 404 Not Found\r\ncontent-length: 7\r\nconnection: close\r\n\r\nmissing");
+            }
+        });
+        url
+    }
+
+    fn create_service(processor: &mut SyscallProcessor, url: &str, headers: HashMap<String, String>) -> u64 {
+        let res = processor.dent_create(DentCreate {
+            label: Some(Buckle::public()),
+            kind: Some(dent_create::Kind::Service(Service {
+                taint: Some(Buckle::public()),
+                privilege: Some(Component::dc_true()),
+                invoker_integrity_clearance: Some(Component::dc_true()),
+                url: url.to_string(),
+                verb: fs::HttpVerb::GET as i32,
+                headers
+            }))
+        });
+        assert!(res.success);
+        res.fd.unwrap()
+    }
+
+    fn service_invoke(processor: &mut SyscallProcessor, fd: u64) -> ServiceInvokeResult {
+        processor.service_invoke(DentInvokeBlobs {
+            invoke: Some(DentInvoke { fd, sync: true, payload: Vec::new(), toblob: false, parameters: Default::default() }),
+            payload_blob: None,
+            blobs: Vec::new(),
+            timeout_ms: Some(5000),
+        })
+    }
+
+    #[test]
+    fn service_invoke_returns_response_metadata() {
+        let (_dir, mut processor) = SyscallProcessor::for_test(Buckle::public(), Component::dc_true());
+        let url = missing_server();
+        let service = create_service(&mut processor, &url, Default::default());
+
+        let res = service_invoke(&mut processor, service);
+        assert!(res.success);
+        assert_eq!(res.data, Some(b"missing".to_vec()));
+        assert_eq!(res.status, Some(404));
+        assert_eq!(res.url, Some(url));
+        assert_eq!(res.content_length, Some(7));
+
+        // dent_invoke returns only the response's own headers
+        let res = processor.dent_invoke(DentInvoke { fd: service, sync: true, payload: Vec::new(), toblob: false, parameters: Default::default() });
+        assert!(res.success);
+        assert!(res.headers.contains_key("content-length"));
+        assert!(res.headers.keys().all(|name| !name.starts_with(':')));
+    }
+
+    #[test]
+    fn error_statuses_fail_services_that_ask_for_it() {
+        let (_dir, mut processor) = SyscallProcessor::for_test(Buckle::public(), Component::dc_true());
+        let url = missing_server();
+        let headers = HashMap::from([(FAIL_ON_ERROR_STATUS_HEADER.to_string(), "true".to_string())]);
+        let service = create_service(&mut processor, &url, headers);
+
+        // the response is still returned
+        let res = service_invoke(&mut processor, service);
+        assert!(!res.success);
+        assert_eq!(res.data, Some(b"missing".to_vec()));
+        assert_eq!(res.status, Some(404));
+    }
+
+    #[test]
+    fn service_invoke_only_invokes_services() {
+        let (_dir, mut processor, scheduler, gate) = with_scheduler();
+
+        assert!(!service_invoke(&mut processor, gate).success);
+        // nothing was sent to the scheduler
+        scheduler.set_nonblocking(true).unwrap();
+        assert!(scheduler.accept().is_err());
+    }
+}
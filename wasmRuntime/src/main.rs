@@ -1,33 +1,89 @@
 use core::str;
 use std::env;
 use std::collections::HashMap;
-use std::io::Write;
-use std::net::TcpStream;
+use std::path::{Path, PathBuf};
 use extism::*;
-use extism_convert::Json;
-use faasten_interface_types::{dent_create, dent_open, dent_update, gate,
+use extism_convert::{Json, Prost};
+use faasten_interface_types::{
     DentCreate, DentKind, DentLink, DentListResult, DentLsFaceted, DentLsFacetedResult, DentLsGateResult,
-    DentOpen, DentOpenResult, DentResult, DentUnlink, DentUpdate, Service,
+    DentOpen, DentOpenResult, DentResult, DentUnlink, DentUpdate,
     DentInvoke, DentInvokeResult, BlobWrite, BlobResult, BlobFinalize, BlobRead, BlobClose};
-use faasten_core::fs::{self, lmdb, BackingStore, DirEntry, DirectGate, Gate, 
-    HttpVerb, RedirectGate, CURRENT_LABEL, FS, PRIVILEGE};
+use faasten_core::fs::{self, lmdb, BackingStore, DirEntry, CURRENT_LABEL, FS};
 use faasten_core::blobstore::{Blob, Blobstore, NewBlob};
 use faasten_core::sched::message::{TaskReturn, ReturnCode};
 use labeled::{buckle::{Buckle, Component}, Label};
+use sha2::Sha256;
+use faasten_runtime_interface::{Batch, BatchResult, BlobReadNext, BlobSeek, BlobStat, DentInvokeBlobs, FanOut, FanOutResult, InputBlobs, InvokeAwait, InvokeHandleResult, MaybeBuckle, ServiceInvokeResult, ServiceUpdate};
+use config::RuntimeConfig;
+use fixtures::Fixtures;
+use invoke::PendingInvoke;
+use txn::{TxnHandle, TxnStore};
+
+mod batch;
+mod blobs;
+mod config;
+mod dents;
+mod fanout;
+mod fixtures;
+mod invoke;
+mod service;
+mod txn;
 
 const BACKING_STORE_PATH : &str = "./backing.fstn";
 const BLOBSTORE_BASE_DIR_PATH: &str = "./blobs";
 const BLOBSTORE_TMP_PATH: &str = "./tmp";
 
+/*
+    Declares a cloudcall as two host functions sharing the same body. The first exchanges JSON-encoded
+    messages with the guest, the second (named with a _pb suffix) exchanges protobuf-encoded messages,
+    which keeps byte payloads compact. Arguments marked msg are encoded messages, arguments marked raw
+    (file descriptors, strings) are passed the same way by both.
+
+    The body gets the locked SyscallProcessor as its first closure argument and evaluates to the result.
+ */
+macro_rules! cloudcall {
+    ($name:ident, $pb_name:ident, |$state:ident $(, $arg:ident: $kind:ident $arg_ty:ty)*| -> $ret:ty $body:block) => {
+        host_fn!(
+            $name(user_data: SyscallProcessor; $($arg: cloudcall_arg_ty!(Json, $kind, $arg_ty)),*) -> Json<$ret> {
+                let state = user_data.get()?;
+                #[allow(unused_mut)]
+                let mut $state = state.lock().unwrap();
+
+                $(let $arg = cloudcall_arg!($kind, $arg);)*
+                Ok(Json($body))
+            }
+        );
+
+        host_fn!(
+            $pb_name(user_data: SyscallProcessor; $($arg: cloudcall_arg_ty!(Prost, $kind, $arg_ty)),*) -> Prost<$ret> {
+                let state = user_data.get()?;
+                #[allow(unused_mut)]
+                let mut $state = state.lock().unwrap();
+
+                $(let $arg = cloudcall_arg!($kind, $arg);)*
+                Ok(Prost($body))
+            }
+        );
+    };
+}
+
+// type of a cloudcall argument as received by the host function
+macro_rules! cloudcall_arg_ty {
+    ($encoding:ident, msg, $arg_ty:ty) => { $encoding<$arg_ty> };
+    ($encoding:ident, raw, $arg_ty:ty) => { $arg_ty };
+}
+
+// value of a cloudcall argument as passed to the cloudcall body
+macro_rules! cloudcall_arg {
+    (msg, $arg:ident) => { $arg.0 };
+    (raw, $arg:ident) => { $arg };
+}
+
 /*
     Gets the function's current label
  */
-host_fn!(
-    get_current_label(user_data: SyscallProcessor;) -> Json<Buckle> {
-        Ok(Json(CURRENT_LABEL
-            .with(|cl| Buckle::from(cl.borrow().clone()))
-        ))
-    }
+cloudcall!(
+    get_current_label, get_current_label_pb, |_state| -> Buckle { CURRENT_LABEL.with(|cl| Buckle::from(cl.borrow().clone())) }
 );
 
 /*
@@ -40,20 +96,25 @@ host_fn!(
     }
 );
 
+host_fn!(
+    buckle_parse_pb(user_data: SyscallProcessor; input_str: &str) -> Prost<MaybeBuckle> {
+        let label = Buckle::parse(input_str).ok();
+        Ok(Prost(MaybeBuckle { label }))
+    }
+);
+
 /*
     Takes an input label in Buckle format. Taints the function's current label
     with the input label (new label = lub of the current and input labels)
  */
-host_fn!(
-    taint_with_label(user_data: SyscallProcessor; input_label_json: Json<Buckle>) -> Json<Buckle> {
-        let Json(input_label) = input_label_json;
-        Ok(Json(CURRENT_LABEL
+cloudcall!(
+    taint_with_label, taint_with_label_pb, |_state, input_label: msg Buckle| -> Buckle {
+        CURRENT_LABEL
             .with(|cl| {
                 let new_label = cl.borrow().clone().lub(input_label);
-                *cl.borrow_mut() = new_label;//cl.clone().borrow().lub(input_label);
+                *cl.borrow_mut() = new_label;
                 Buckle::from(cl.borrow().clone())
             })
-        ))
     }
 );
 
@@ -64,13 +125,10 @@ host_fn!(
     
     *** CURRENTLY DOESN'T ACTUALLY UPDATE CURRENT LABEL. SHOULD IT? RATIONALE BEHIND MAYBEBUCKLE? ****
  */
-host_fn!(
-    declassify(user_data: SyscallProcessor; target_secrecy_json: Json<Component>) -> Json<Buckle> {
-        let Json(target_secrecy) = target_secrecy_json;
-
-        let res = fs::utils::declassify(target_secrecy);
-        match res {
-            Ok(l) | Err(l) => Ok(Json(l))
+cloudcall!(
+    declassify, declassify_pb, |_state, target_secrecy: msg Component| -> Buckle {
+        match fs::utils::declassify(target_secrecy) {
+            Ok(l) | Err(l) => l
         }
     }
 );
@@ -78,13 +136,13 @@ host_fn!(
 /*
     Returns the root's file descriptor in the dents file descriptor table
  */
-host_fn!(
-    root(user_data: SyscallProcessor;) -> Json<DentResult> {
-        Ok(Json(DentResult{
+cloudcall!(
+    root, root_pb, |_state| -> DentResult {
+        DentResult{
             success: true,
             fd: Some(0),
             data: None
-        }))
+        }
     }
 );
 
@@ -123,89 +181,16 @@ impl From<&DirEntry> for DentKindWrap {
     Returns success if it works as expected along with the file descriptor of the newly opened
     object, and the kind of object opened. Returns false otherwise
  */
-host_fn!(
-    dent_open(user_data: SyscallProcessor; dent_open_json: Json<DentOpen>) -> Json<DentOpenResult> {
-        let state = user_data.get()?;
-        let mut state = state.lock().unwrap();
-
-        let Json(DentOpen{fd: dir_fd, entry}) = dent_open_json;
-
-        let result: Option<(u64, DentKindWrap)> = state.dents
-            .get(&dir_fd)
-            .cloned()
-            .and_then(|base| match (base, entry.unwrap()) {
-                ( // Case #1: base = a Directory object. entry = a Name enum. Looking to open an object by it's name 
-                    DirEntry::Directory(base_dir),
-                    dent_open::Entry::Name(name)
-                ) => {
-                    base_dir.list(&state.env.fs).get(&name).map(|dent| {
-                        let res_id = state.max_dent_id;
-                        let _ = state.dents.insert(res_id, dent.clone());
-                        state.max_dent_id += 1;
-                        (res_id, dent.into())
-                    })
-                }
-                (
-                    // Case #2: base = a FacetedDirectory object. entry = a Facet enum. Looking to open an object by it's label
-                    DirEntry::FacetedDirectory(base_dir),
-                    dent_open::Entry::Facet(label)
-                ) => {
-                    let dent = DirEntry::Directory(base_dir.open(&label.into(), &state.env.fs));
-                    let res_id = state.max_dent_id;
-                    let _ = state.dents.insert(res_id, dent.clone());
-                    state.max_dent_id += 1;
-                    Some((res_id, (&dent).into()))
-                }
-                (
-                    // Case #3: base = a FacetedDirectory object. entry = a Name enum. Name contains a string which represent a label
-                    DirEntry::FacetedDirectory(base_dir),
-                    dent_open::Entry::Name(label_name)
-                ) => {
-                    if let Ok(label) = Buckle::parse(label_name.as_str()) {
-                        let dent = DirEntry::Directory(base_dir.open(&label, &state.env.fs));
-                        let res_id = state.max_dent_id;
-                        let _ = state.dents.insert(res_id, dent.clone());
-                        state.max_dent_id += 1;
-                        Some((res_id, (&dent).into()))
-                    } else {
-                        None
-                    }
-                }
-                _ => None
-            });
-        
-        // return a DentOpenResult indicating success or failure
-        if let Some(result) = result {
-            Ok(Json(DentOpenResult{
-                    success: true,
-                    fd: result.0,
-                    kind: result.1.kind.into()
-            }))
-        } else {
-            Ok(Json(DentOpenResult{
-                success: false,
-                fd: 0,
-                kind: DentKind::DentDirectory.into()
-            }))
-        }
-    }
+cloudcall!(
+    dent_open, dent_open_pb, |state, dent_open: msg DentOpen| -> DentOpenResult { state.dent_open(dent_open) }
 );
 
 /*
     Takes a file descriptor and tries to close the associated entry.
     Returns true if a matching entry was found, false otherwise
  */
-host_fn!(
-    dent_close(user_data: SyscallProcessor; input_fd: u64) -> Json<DentResult> {
-        let state = user_data.get()?;
-        let mut state = state.lock().unwrap();
-
-        Ok(Json(DentResult{
-            success: state.dents.remove(&input_fd).is_some(),
-            fd: None,
-            data: None
-        }))
-    }
+cloudcall!(
+    dent_close, dent_close_pb, |state, input_fd: raw u64| -> DentResult { state.dent_close(input_fd) }
 );
 
 /*
@@ -224,145 +209,8 @@ host_fn!(
     file descriptor in dents. 
     Returns false if create failed.
  */
-host_fn!(
-    dent_create(user_data: SyscallProcessor; dent_create_json: Json<DentCreate>) -> Json<DentResult> {
-        let state = user_data.get()?;
-        let mut state = state.lock().unwrap();
-        
-        let Json(DentCreate{label, kind}) = dent_create_json;
-
-        // if kind is none, return failure
-        if kind.is_none() {
-            return Ok(Json(DentResult{success: false, fd: None, data: None}));
-        }
-
-        // get the actual label and kind passed into dent create. label defaults to public
-        let kind = kind.unwrap();
-        let label = label.unwrap_or(Buckle::public());
-
-
-        let maybe_entry: Option<DirEntry> = match kind {
-            dent_create::Kind::Directory => Some(state.env.fs.create_directory(label)),
-            dent_create::Kind::File => Some(state.env.fs.create_file(label)),
-            dent_create::Kind::FacetedDirectory => Some(state.env.fs.create_faceted_directory()),
-            dent_create::Kind::Blob(blobfd) => {
-                // get blob from blobs table. create a blob entry in dents table which wraps around the blob name
-                let blob = state.blobs.get(&blobfd);
-                
-                if blob.is_some() { state.env.fs.create_blob(label, blob.unwrap().name.clone()).ok() }
-                else {None}
-            }
-            dent_create::Kind::Gate(faasten_interface_types::Gate {kind: gate_kind}) => {
-                if gate_kind.is_none() {
-                    None
-                } 
-                else {
-                    let gate_kind = gate_kind.unwrap();
-                    match gate_kind {
-                        // Case #1: creating a direct gate
-                        gate::Kind::Direct(dg) => {
-                            let Some(function) = dg.function
-                            else {
-                                log::info!("No function provided when creating gate");
-                                return Ok(Json(DentResult{success: false, fd: None, data: None}));
-                            };
-                            
-                            // get the function's app image
-                            let Some(DirEntry::Blob(app_image)) = state.dents.get(&function.app_image)
-                            else {
-                                log::info!("Failed to read function's app image when creating gate");
-                                return Ok(Json(DentResult{success: false, fd: None, data: None}));
-                            };
-
-                            // get the function's runtime image
-                            let Some(DirEntry::Blob(runtime_image)) = state.dents.get(&function.runtime_image)
-                            else {
-                                log::info!("Failed to read function's runtime image when creating gate");
-                                return Ok(Json(DentResult{success: false, fd: None, data: None}));
-                            };
-
-                            // create an internal version of the function
-                            let new_func = fs::Function {
-                                memory: function.memory as usize,
-                                app_image: app_image.get(&state.env.fs).unwrap().unlabel().clone(),
-                                runtime_image: runtime_image.get(&state.env.fs).unwrap().unlabel().clone(),
-                                kernel: "Kernel Not Used".to_string()
-                            };
-
-                            // create the direct gate
-                            state.env.fs.create_direct_gate(label, 
-                                DirectGate {
-                                    privilege: dg.privilege.unwrap().into(),
-                                    invoker_integrity_clearance: dg.invoker_integrity_clearance.unwrap().into(),
-                                    declassify: dg.declassify.map(|d| d.into()).unwrap_or(Component::dc_true()),
-                                    function: new_func
-                                }
-                            ).ok()
-                        }
-                        // Case #2: creating a redirect gate
-                        gate::Kind::Redirect(rdg) => {
-                            let maybe_inner_gate = state.dents.get(&rdg.gate);
-                            
-                            if let Some(DirEntry::Gate(gate_objref)) = maybe_inner_gate {
-                                state.env.fs.create_redirect_gate(label, 
-                                    RedirectGate {
-                                        privilege: rdg.privilege.unwrap().into(),
-                                        invoker_integrity_clearance: rdg.invoker_integrity_clearance.unwrap().into(),
-                                        declassify: rdg.declassify.map(|d| d.into()).unwrap_or(Component::dc_true()),
-                                        gate: *gate_objref
-                                    }
-                                ).ok()
-                            } else {
-                                log::info!("Failed to get inner gate when creating redirect gate");
-                                return Ok(Json(DentResult{success: false, fd: None, data: None}));
-                            }
-                        }
-                    }
-                }
-            },
-            dent_create::Kind::Service(Service {
-                taint,
-                privilege,
-                invoker_integrity_clearance,
-                url,
-                verb,
-                mut headers
-            }) => {
-                let verb = HttpVerb::from_i32(verb).unwrap_or(HttpVerb::HEAD);
-                let headers: std::collections::BTreeMap<String, String> = headers.drain().collect();
-                
-                state.env.fs.create_service(label, 
-                    fs::Service {
-                        taint: taint.unwrap().into(),
-                        privilege: privilege.unwrap().into(),
-                        invoker_integrity_clearance: invoker_integrity_clearance.unwrap().into(),
-                        url,
-                        verb,
-                        headers
-                    }
-                ).ok()
-            }
-        };
-
-        // if entry is valid, insert into dents table
-        if let Some(entry) = maybe_entry {
-            let res_id = state.max_dent_id;
-            let _ = state.dents.insert(res_id, entry);
-            state.max_dent_id += 1;
-
-            Ok(Json(DentResult{
-                success: true,
-                fd: Some(res_id),
-                data: None
-            }))
-        } else {
-            Ok(Json(DentResult{
-                success: false,
-                fd: None,
-                data: None
-            }))
-        }
-    }
+cloudcall!(
+    dent_create, dent_create_pb, |state, dent_create: msg DentCreate| -> DentResult { state.dent_create(dent_create) }
 );
 
 /*
@@ -370,188 +218,54 @@ host_fn!(
         File: Overwrites previoues contents. Performs label check to ensure write is valid
         Blob: Replaces the name/content-hash of old Blob with a new one fetched from the blobstore. Performs
             a label check to ensure write is valid
-        Gate, Service: Replace fields of the gate. Performs label check to ensure the write and
-            any new privilege is valid
+        Gate, Service: Replace only the fields that were provided, leaving the others unchanged.
+            Unset labels, empty urls, a zero fd, and the default verb all count as "not provided", so a
+            service's verb can't be set to the default one here. Use dent_update_service for that.
+            Service headers are merged into the existing ones, and an empty header value removes that header.
+            Performs label check to ensure the write and any new privilege or declassify is valid. Changing any
+            field of a gate or service, e.g a service's url or headers, requires the current privilege to own
+            the object's privilege, since a service sends its secrets wherever its url points
     Returns true if update is successful and false otherwise. Note that the object at the given file descriptor
     must match the type of data given to replace it.
  */
-host_fn!(
-    dent_update(user_data: SyscallProcessor; dent_update_json: Json<DentUpdate>) -> Json<DentResult> {
-        let state = user_data.get()?;
-        let state = state.lock().unwrap();
-
-        let Json(DentUpdate{fd, kind}) = dent_update_json;
-
-        // if kind is none, return failure
-        if kind.is_none() {
-            return Ok(Json(DentResult{success: false, fd: None, data: None}))
-        }
-
-        let kind = kind.unwrap();
-
-        let result = match kind {
-            dent_update::Kind::File(data) => {
-                if let Some(DirEntry::File(file_objref)) = state.dents.get(&fd) {
-                    file_objref.write(data, &state.env.fs).ok()
-                } else { None }
-            }
-            dent_update::Kind::Blob(blobfd) => {
-                let new_blob = state.blobs.get(&blobfd);
-                if new_blob.is_none() {
-                    log::info!("Failed to update object with fd {}", fd);
-                    return Ok(Json(DentResult{success: false, fd: None, data: None}));
-                }
-                let new_blob = new_blob.unwrap();
-
-                if let Some(DirEntry::Blob(blob_objref)) = state.dents.get(&fd) {
-                    blob_objref.replace(new_blob.name.clone(), &state.env.fs).ok()
-                } else { None }
-            }
-            dent_update::Kind::Gate(faasten_interface_types::Gate { kind }) => {
-                if let Some(DirEntry::Gate(gate_objref)) = state.dents.get(&fd) {
-                    if let Some( kind ) = kind {
-                        match kind {
-                            // Case #1: replacing a direct gate
-                            gate::Kind::Direct(dg_intf) => {
-                                // will use this to replace the old gate in the backing store
-                                let mut new_gate;
-                                
-                                // get the old gate from the backing store
-                                if let Some(Gate::Direct(dg_core)) = gate_objref.get(&state.env.fs).map(|g| g.unlabel().clone()) {
-                                    new_gate = dg_core;
-                                } else {
-                                    log::info!("Failed to update object with fd {}", fd);
-                                    return Ok(Json(DentResult{success: false, fd: None, data: None}));
-                                }
-
-                                // replace gate's function field, if user provided one
-                                if let Some(function) = dg_intf.function {
-                                    // replace function's fields if user provided a valid fd for them
-
-                                    // replacing app image
-                                    if function.app_image > 0 {
-                                        let Some(DirEntry::Blob(app_image)) = state.dents.get(&function.app_image) 
-                                        else {
-                                            log::info!("Failed to update object with fd {}", fd);
-                                            return Ok(Json(DentResult{success: false, fd: None, data: None}));
-                                        };
-
-                                        new_gate.function.app_image = app_image.get(&state.env.fs).unwrap().unlabel().clone();
-                                    }
-
-                                    // replacing runtime image
-                                    if function.runtime_image > 0 {
-                                        let Some(DirEntry::Blob(runtime_image)) = state.dents.get(&function.runtime_image)
-                                        else {
-                                            log::info!("Failed to update object with fd {}", fd);
-                                            return Ok(Json(DentResult{success: false, fd: None, data: None}));
-                                        };
-
-                                        new_gate.function.runtime_image = runtime_image.get(&state.env.fs).unwrap().unlabel().clone();
-                                    }
-
-                                    // kernel not used, so not replaced
-                                    
-                                    // replacing memory
-                                    if function.memory > 0 {
-                                        new_gate.function.memory = function.memory as usize;
-                                    }
-                                }
-
-                                // replace gate's privilege, if user provided one
-                                if let Some(privilege) = dg_intf.privilege {
-                                    new_gate.privilege = privilege.into();
-                                }
-
-                                // replace gate's invoker integrity clearance, if user provided one
-                                if let Some(invoker_integrity_clearance) = dg_intf.invoker_integrity_clearance {
-                                    new_gate.invoker_integrity_clearance = invoker_integrity_clearance.into();
-                                }
-
-                                gate_objref.replace(Gate::Direct(new_gate), &state.env.fs).ok()
-                            }
-                            // Case #2 replacing an indirect gate
-                            gate::Kind::Redirect(rdg_intf) => {
-                                // will use this to replace the old gate in the backing store
-                                let mut new_gate;
-
-                                // get the old gate from the backing store
-                                if let Some(Gate::Redirect(rdg_core)) = gate_objref.get(&state.env.fs).map(|g| g.unlabel().clone()) {
-                                    new_gate = rdg_core;
-                                } else {
-                                    log::info!("Failed to update object with fd {}", fd);
-                                    return Ok(Json(DentResult{success: false, fd: None, data: None}));
-                                }
-
-                                // replace inner gate, if user provided a valid fd for it
-                                if rdg_intf.gate > 0 {
-                                    if let Some(DirEntry::Gate(inner_gate_objref)) = state.dents.get(&rdg_intf.gate) {
-                                        new_gate.gate = *inner_gate_objref;
-                                    } else{
-                                        log::info!("Failed to update object with fd {}", fd);
-                                        return Ok(Json(DentResult{success: false, fd: None, data: None}));
-                                    }
-                                }
-
-                                // replace gate's privilege, if user provided one
-                                if let Some(privilege) = rdg_intf.privilege {
-                                    new_gate.privilege = privilege.into();
-                                }
-
-                                // replace gate's invoker integrity clearance, if user provided one
-                                if let Some(invoker_integrity_clearance) = rdg_intf.invoker_integrity_clearance {
-                                    new_gate.invoker_integrity_clearance = invoker_integrity_clearance;
-                                }
-
-                                gate_objref.replace(Gate::Redirect(new_gate), &state.env.fs).ok()
-                            }
-                        }
-                    } else { None }
-                } else { None }
-            },
-            dent_update::Kind::Service( Service{
-                taint,
-                privilege,
-                invoker_integrity_clearance,
-                url,
-                verb,
-                mut headers
-            } ) => {
-                if let Some(DirEntry::Service(service_objref)) = state.dents.get(&fd) {
-                    let verb = HttpVerb::from_i32(verb).unwrap_or(HttpVerb::HEAD).into();
-                    let headers: std::collections::BTreeMap<String, String> = headers.drain().collect();
-                    
-                    service_objref.replace(
-                        fs::Service {
-                            taint: taint.unwrap().into(),
-                            privilege: privilege.unwrap().into(),
-                            invoker_integrity_clearance: invoker_integrity_clearance.unwrap().into(),
-                            url,
-                            verb,
-                            headers
-                        },
-                        &state.env.fs
-                    ).ok()
-                } else {
-                    None
-                }
-            }
-        };
+cloudcall!(
+    dent_update, dent_update_pb, |state, dent_update: msg DentUpdate| -> DentResult { state.dent_update(dent_update) }
+);
 
-        if result.is_some() {
-            Ok(Json(DentResult{
-                success: true,
-                fd: None,
-                data: None
-            }))
-        } else {
-            log::info!("Failed to update object with fd {}", fd);
-            return Ok(Json(DentResult{success: false, fd: None, data: None}));
-        }
-        
+/*
+    Updates the provided fields of a service given its file descriptor, like dent_update. The url and verb
+    are explicitly present or absent, so every verb and url can be set.
+    Returns true if update is successful and false otherwise, including if the verb is unknown.
+ */
+cloudcall!(
+    dent_update_service, dent_update_service_pb, |state, service_update: msg ServiceUpdate| -> DentResult {
+        state.dent_update_service(service_update)
     }
 );
 
+/*
+    Relabels an object given its file descriptor and a new label. Files, directories, blobs, gates and
+    services can be relabeled. Faceted directories have no label and can't be relabeled.
+
+    The object is read first, so the current label is raised to (at least) the object's label. The
+    new label must then be at least as restrictive as the current label, i.e raising a label is always
+    allowed. Lowering a label is only allowed if the current privilege is sufficient. Either way, the
+    current label is left raised to the object's label.
+
+    The backing store keeps a label per object, so the relabeled object is stored as a copy holding the same
+    contents. If the object was opened from or linked into a directory by this function, the copy replaces the
+    object under that name. Relabeling fails if that name was unlinked or refers to another object by now,
+    since the object can no longer be replaced. Storing the copy and replacing the object are
+    atomic: they run in a transaction (the function's open one, if any), and a failure leaves the old object
+    linked. Every file descriptor of this function referring to the object refers to the relabeled copy
+    afterwards. Other links to the old object, and other functions holding it open, keep the old label.
+
+    Returns true along with the file descriptor if successful. Returns false otherwise.
+ */
+cloudcall!(
+    dent_relabel, dent_relabel_pb, |state, fd: raw u64, new_label: msg Buckle| -> DentResult { state.dent_relabel(fd, new_label) }
+);
+
 /*
     Reads a file object given its file descriptor. Performs an implicit label raise, doing a lub
     of the current label and the file's label.
@@ -559,24 +273,8 @@ host_fn!(
     Returns true as well as the file's data and file descriptor if the read is successful. Returns
     false otherwise.
  */
-host_fn!(
-    dent_read(user_data: SyscallProcessor; fd: u64) -> Json<DentResult> {
-        let state = user_data.get()?;
-        let state = state.lock().unwrap();
-
-        let result = state.dents.get(&fd).and_then(|entry| {
-            match entry {
-                DirEntry::File(file) => Some(file.read(&state.env.fs)),
-                _ => None
-            }
-        });
-
-        Ok(Json(DentResult{
-            success: result.is_some(),
-            fd: Some(fd),
-            data: result
-        }))
-    }
+cloudcall!(
+    dent_read, dent_read_pb, |state, fd: raw u64| -> DentResult { state.dent_read(fd) }
 );
 
 /*
@@ -588,30 +286,8 @@ host_fn!(
 
     Returns true if the link is successful and false otherwise.
  */
-host_fn!(
-    dent_link(user_data: SyscallProcessor; dent_link_json: Json<DentLink>) -> Json<DentResult> {
-        let state = user_data.get()?;
-        let state = state.lock().unwrap();
-
-        let Json(DentLink{dir_fd, name, target_fd}) = dent_link_json;
-
-        let base_dir_m = state.dents.get(&dir_fd).cloned();
-        let target_obj_m = state.dents.get(&target_fd).cloned();
-        let result = base_dir_m.zip(target_obj_m).and_then(|(base, target)| {
-            match base {
-                DirEntry::Directory(base_dir) => base_dir
-                    .link(name, target, &state.env.fs)
-                    .map_err(|e| Into::into(e)),
-                _ => Err(fs::FsError::NotADir)             
-            }.ok()
-        });
-
-        Ok(Json(DentResult {
-            success: result.is_some(),
-            fd: None, 
-            data: None
-        }))
-    }
+cloudcall!(
+    dent_link, dent_link_pb, |state, dent_link: msg DentLink| -> DentResult { state.dent_link(dent_link) }
 );
 
 /*
@@ -621,26 +297,8 @@ host_fn!(
 
     Returns true along with the directory file descriptor if the unlink is successful. Returns false otherwise.
  */
-host_fn!(
-    dent_unlink(user_data: SyscallProcessor; dent_unlink_json: Json<DentUnlink>) -> Json<DentResult> {
-        let state = user_data.get()?;
-        let state = state.lock().unwrap();
-
-        let Json(DentUnlink{dir_fd, name}) = dent_unlink_json;
-
-        let result = state.dents.get(&dir_fd).cloned().and_then(|entry| {
-            match entry {
-                DirEntry::Directory(base_dir) => base_dir.unlink(&name, &state.env.fs).ok(),
-                _ => None
-            }
-        });
-
-        Ok(Json(DentResult{
-            success: result.unwrap_or(false),
-            fd: Some(dir_fd),
-            data: None
-        }))
-    }
+cloudcall!(
+    dent_unlink, dent_unlink_pb, |state, dent_unlink: msg DentUnlink| -> DentResult { state.dent_unlink(dent_unlink) }
 );
 
 /*
@@ -649,46 +307,8 @@ host_fn!(
 
     Returns true along with the directory's content (name and type) if successful. Returns false otherwise.
  */
-host_fn!(
-    dent_list(user_data: SyscallProcessor; dir_fd: u64) -> Json<DentListResult> {
-        let state = user_data.get()?;
-        let state = state.lock().unwrap();
-
-        let result = state.dents.get(&dir_fd).and_then(|entry| {
-            match entry {
-                DirEntry::Directory(dir_objref) => Some(
-                    dir_objref
-                        .list(&state.env.fs)
-                        .iter()
-                        .map(
-                            |(name, direntry)| {
-                                let kind = match direntry {
-                                    DirEntry::Directory(_) => DentKind::DentDirectory,
-                                    DirEntry::File(_) => DentKind::DentFile,
-                                    DirEntry::FacetedDirectory(_) => DentKind::DentFacetedDirectory,
-                                    DirEntry::Gate(_) => DentKind::DentGate,
-                                    DirEntry::Service(_) => DentKind::DentService,
-                                    DirEntry::Blob(_) => DentKind::DentBlob
-                                };
-
-                                (name.clone(), kind as i32)
-                            }
-                        )
-                        .collect()
-                ),
-                _ => {
-                    log::info!("List failed. Not a directory.");
-                    None
-                }
-            }
-        });
-
-        if let Some(entries) = result {
-            Ok(Json(DentListResult{success: true, entries}))
-        } else {
-            Ok(Json(DentListResult{success: false, entries: Default::default()}))
-        }
-    }
+cloudcall!(
+    dent_list, dent_list_pb, |state, dir_fd: raw u64| -> DentListResult { state.dent_list(dir_fd) }
 );
 
 /*
@@ -698,38 +318,8 @@ host_fn!(
     The list operation reveals only the contents whose label can flow to the given clearance. Returns true and the
     contents of the faceted directory if the list is successful. Returns false otherwise.
  */
-host_fn!(
-    dent_ls_faceted(user_data: SyscallProcessor; dent_ls_faceted_json: Json<DentLsFaceted>) -> Json<DentLsFacetedResult> {
-        let state = user_data.get()?;
-        let state = state.lock().unwrap();
-
-        let Json(DentLsFaceted { fd: fdir_fd, clearance }) = dent_ls_faceted_json;
-        
-        // clearance defaults to public
-        let clearance = clearance.unwrap_or(Buckle::public());
-
-        let result  = state.dents.get(&fdir_fd).and_then(|entry| {
-            match entry {
-                DirEntry::FacetedDirectory(fdir_objref) => Some(
-                    fdir_objref
-                        .list(&state.env.fs, &clearance)
-                        .iter()
-                        .map(|(label, _)| label.clone().into())
-                        .collect()
-                ),
-                _ => {
-                    log::info!("List failed. Not a faceted directory.");
-                    None
-                }
-            }
-        });
-
-        if let Some(facets) = result {
-            Ok(Json(DentLsFacetedResult {success: true, facets} ))
-        } else {
-            Ok(Json(DentLsFacetedResult{success: false, facets: Default::default()}))
-        }
-    }
+cloudcall!(
+    dent_ls_faceted, dent_ls_faceted_pb, |state, dent_ls_faceted: msg DentLsFaceted| -> DentLsFacetedResult { state.dent_ls_faceted(dent_ls_faceted) }
 );
 
 /*
@@ -737,100 +327,8 @@ host_fn!(
 
     Returns true and the gate contents if succesful. Returns false otherwise.
  */
-host_fn!(
-    dent_ls_gate(user_data: SyscallProcessor; gate_fd: u64) -> Json<DentLsGateResult> {
-        let state = user_data.get()?;
-        let mut state = state.lock().unwrap();
-
-        let result = state.dents.get(&gate_fd).map(Clone::clone).and_then(|entry| {
-            match entry {
-                DirEntry::Gate(gate_objref) => Some(
-                    match gate_objref.get(&state.env.fs).unwrap().unlabel() {
-                        // Case #1: List a Direct Gate
-                        Gate::Direct(dg_core) => {
-                            // open the gate's app image and get its fd
-                            let app_image_fd = {
-                                let blob_id = state.max_blob_id;
-                                state.max_blob_id += 1;
-                                
-                                let Some(blob) = state
-                                    .env.blobstore
-                                    .open(dg_core.function.app_image.clone())
-                                    .ok()
-                                else {
-                                    log::info!("List failed. Failed to open app image.");
-                                    return None;
-                                };
-                                state.blobs.insert(blob_id, blob);
-                                
-                                blob_id
-                            };
-
-                            // open the gate's runtime image and get its fd
-                            let runtime_image_fd = {
-                                let blob_id = state.max_blob_id;
-                                state.max_blob_id += 1;
-
-                                let Some(blob) = state
-                                    .env.blobstore
-                                    .open(dg_core.function.runtime_image.clone())
-                                    .ok()
-                                else {
-                                    log::info!("List failed. Failed to open runtime image.");
-                                    return None;
-                                };
-                                state.blobs.insert(blob_id, blob);
-
-                                blob_id
-                            };
-
-                            // kernel image not used so don't open
-                            
-                            // create the interface version of the function
-                            let function_intf = faasten_interface_types::Function {
-                                memory: dg_core.function.memory as u64,
-                                app_image: app_image_fd,
-                                runtime_image: runtime_image_fd,
-                                kernel: 0 // not used
-                            };
-
-                            // return user-side direct gate
-                            faasten_interface_types::Gate {
-                                kind: Some(gate::Kind::Direct(
-                                    faasten_interface_types::DirectGate { 
-                                        privilege: Some(dg_core.privilege.clone()), 
-                                        invoker_integrity_clearance: Some(dg_core.invoker_integrity_clearance.clone()), 
-                                        function: Some(function_intf), 
-                                        declassify: Some(dg_core.declassify.clone())
-                                    }
-                                ))
-                            }
-                        }
-                        // Case #2: List a Redirect Gate
-                        Gate::Redirect(rdg_core) => {
-                            // return user-side redirect gate
-                            faasten_interface_types::Gate {
-                                kind: Some(gate::Kind::Redirect(
-                                    faasten_interface_types::RedirectGate { 
-                                        privilege: Some(rdg_core.privilege.clone()), 
-                                        invoker_integrity_clearance: Some(rdg_core.invoker_integrity_clearance.clone()), 
-                                        gate: 0, // unused field in this case
-                                        declassify: Some(rdg_core.declassify.clone())
-                                    }
-                                ))
-                            }
-                        }
-                    }
-                ),
-                _ => {
-                    log::info!("List failed. Not a gate.");
-                    None
-                }
-            }
-        });
-
-        Ok(Json(DentLsGateResult {success: result.is_some(), gate: result}))
-    }
+cloudcall!(
+    dent_ls_gate, dent_ls_gate_pb, |state, gate_fd: raw u64| -> DentLsGateResult { state.dent_ls_gate(gate_fd) }
 );
 
 /*
@@ -842,136 +340,120 @@ host_fn!(
         parameters: ********************************************************** UNCLEAR
 
     Performs an invocation check to ensure that the current privilege meets the gate's
-    invoker clearance requirement. For service gates, performs a declassify before sending
-    a http request. Taints the label immediately after according to the service object's taint field.
+    invoker clearance requirement. A gate's result label is declassified with the gate's declassify
+    component before the current label is tainted with it. For service gates, performs a declassify
+    before sending a http request. Taints the label immediately after according to the service
+    object's taint field.
+
+    Parameters are filled into the service's url (percent-encoded), templated header values and body
+    template (see the README), and the final url, as well as every url it redirects to, must be allowed
+    by the runtime's egress policy. Service headers whose value is "faasten-secret:<path>" are sent with the
+    contents of the File object at path, which must be declassifiable by the service's privilege. The
+    secret is never returned to the function.
+
+    Gates invoked asynchronously (sync = false) return an invocation handle as fd right away. Their
+    result is collected with invoke_poll or invoke_await. Synchronous invocations wait for the result
+    at most as long as the runtime's configured invocation timeout. On a timeout, the result is
+    discarded and the invocation fails with "timeout" as data. Every gate invocation is sent to the
+    scheduler configured as scheduler_addr over a connection of its own, so asynchronous gates overlap
+    with each other and with the function's own work. Gates can't be invoked without a scheduler.
+
+    Services return the response's headers. Their status code, final url and content length are returned
+    by service_invoke. Services whose faasten-fail-on-error-status header is "true" fail on non-2xx
+    statuses, but still return the response. Responses larger than the service's max_response_bytes
+    fail the invocation, or are written to a blob (returned as fd) if the service allows it.
 
     Returns ****************************** UNCLEAR e.g headers?
  */
-host_fn!(
-    dent_invoke(user_data: SyscallProcessor; dent_invoke_json: Json<DentInvoke>) -> Json<DentInvokeResult> {
-        let state = user_data.get()?;
-        let mut state = state.lock().unwrap();
-
-        use faasten_core::sched;
-
-        let Json(DentInvoke { fd, sync, payload, toblob, parameters }) = dent_invoke_json;
-
-        let (blobfd, data, headers) = state.dents.get(&fd)
-            .cloned()
-            .and_then(|entry| 
-                match entry {
-                    DirEntry::Gate(gate_objref) => {
-                        let gate = gate_objref.to_invokable(&state.env.fs);
-
-                        // invocation check. Current privilege must be at least as strong as gate's invoker clearance requirement
-                        if !fs::utils::get_privilege().implies(&gate.invoker_integrity_clearance) {
-                            return None;
-                        }
-
-                        sched::rpc::labeled_invoke(
-                            state.env.sched_conn.as_mut().unwrap(), 
-                            sched::message::LabeledInvoke {
-                                function: Some(gate.function.into()),
-                                label: Some(CURRENT_LABEL.with(|cl| cl.borrow().clone().into())),
-                                gate_privilege: Some(gate.privilege.into()),
-                                blobs: Default::default(),
-                                payload,
-                                headers: parameters,
-                                sync,
-                                invoker: Some(PRIVILEGE.with(|p| p.borrow().clone().into()))
-                            }
-                        ).ok()?;
-                        
-                        // whether to wait for result or not
-                        if !sync {
-                            Some((None::<u64>, Some(vec![]), None::<HashMap<String, Vec<u8>>>))
-                        } else {
-                            let res = sched::message::read::<TaskReturn>(state.env.sched_conn.as_mut().unwrap())
-                                .ok()?;
-                            
-                            let res_label: Buckle = res.label
-                                .clone()
-                                .map(|rpc_label| -> Buckle {rpc_label.into()})
-                            .unwrap_or(Buckle::public());
-                            
-                            fs::utils::taint_with_label(res_label);
-
-                            // whether to write function's result to a blob or not
-                            if !toblob {
-                                Some((None, res.payload.unwrap().body, None))
-                            } else {
-                                let mut newblob = state.env.blobstore.create().expect("Invoke failed. Couldn't create blob.");
-                                newblob.write_all(res.payload.unwrap().body()).expect("Invoke failed. Couldn't write to blob.");
-
-                                // finalize new blob
-                                let blob = state.env.blobstore.save(newblob).expect("Invoke failed. Couldn't save blob.");
-                                let blobfd = state.max_blob_id;
-                                state.max_blob_id += 1;
-                                state.blobs.insert(blobfd, blob);
-
-                                Some((Some(blobfd), None, None))
-                            }
-                        }
-                    }
-                    DirEntry::Service(service_objref) => {
-                        let service = service_objref.to_invokable(&state.env.fs);
-
-                        // invocation check. Current privilege must be at least as strong as gate's invoker clearance requirement
-                        if !fs::utils::get_privilege().implies(&service.invoker_integrity_clearance) {
-                            return None;
-                        }
-
-                        // declassify the current label before making http request
-                        fs::utils::declassify_with(&service.privilege);
-                        let send_res = state.http_send(&service, Some(payload), parameters);
-
-                        // re-taint the current label according to the service taint
-                        fs::utils::taint_with_label(service.taint);
-
-                        // process the response of the http request
-                        match send_res {
-                            Ok(mut response) => {
-                                // convert response headers from a HeaderMap to a Map of String to bytes
-                                let headers: HashMap<String, Vec<u8>> = response.headers().iter()
-                                    .map(|(a, b)| (a.to_string(), Vec::from(b.as_bytes())))
-                                .collect();
-
-                                // whether to write response to blob or not
-                                if !toblob {
-                                    Some((
-                                        None,
-                                        response.bytes().map(|bytes| bytes.to_vec()).ok(),
-                                        Some(headers)
-                                    ))
-                                } else {
-                                    let mut newblob = state.env.blobstore.create().expect("Create blob");
-                                    response.copy_to(&mut newblob).expect("Copy to blob.");
-
-                                    let blob = state.env.blobstore.save(newblob).expect("Save blob.");
-                                    let blobfd = state.max_blob_id;
-                                    state.max_blob_id += 1;
-                                    state.blobs.insert(blobfd, blob);
-
-                                    Some((Some(blobfd), None, Some(headers)))
-                                }
-                            },
-                            Err(_) => None
-                        }
-                    },
-                    _ => None
-                }
-            )
-        .unwrap_or((None, None, None));
-
-        Ok(Json(DentInvokeResult {
-            success: blobfd.is_some() || data.is_some(), 
-            fd: blobfd, 
-            data, 
-            headers: headers.unwrap_or(Default::default())
-        }))
+cloudcall!(
+    dent_invoke, dent_invoke_pb, |state, dent_invoke: msg DentInvoke| -> DentInvokeResult { state.dent_invoke(dent_invoke) }
+);
+
+/*
+    Same as dent_invoke, but also passes blobs to the invoked gate. Takes the invocation, a list of blob
+    file descriptors (in the blobs table), and optionally a blob file descriptor whose contents are used
+    as the payload. The callee finds the blobs in its own blobs table with the input_blobs cloudcall.
+    Gates get the payload blob's contents, so it is read into memory and may be at most max_blob_read_bytes
+    long. Services don't receive blobs, but a payload blob is streamed to them as the request body, so it
+    is never read into memory.
+
+    Returns the same as dent_invoke. Returns false if any of the blobs isn't a finalized blob.
+ */
+cloudcall!(
+    dent_invoke_blobs, dent_invoke_blobs_pb, |state, dent_invoke_blobs: msg DentInvokeBlobs| -> DentInvokeResult {
+        state.dent_invoke_blobs(dent_invoke_blobs)
+    }
+);
+
+/*
+    Same as dent_invoke_blobs, but only invokes services, and also returns the metadata of the service's
+    response: its status code, final url (after following redirects) and the length of the body returned
+    as data or written to a blob.
+
+    Returns the same as dent_invoke, along with the response's metadata if a response was received.
+    Returns false if the file descriptor isn't a service.
+ */
+cloudcall!(
+    service_invoke, service_invoke_pb, |state, dent_invoke_blobs: msg DentInvokeBlobs| -> ServiceInvokeResult {
+        state.service_invoke(dent_invoke_blobs)
     }
 );
 
+/*
+    Collects the result of an asynchronous gate invocation given its invocation handle, without waiting.
+    Taints the current label with the result's label once the result is collected. A result can only be
+    collected once.
+
+    Returns true and whether the result is ready, along with the result (same as dent_invoke) if it is.
+    Returns false if there is no pending invocation with the given handle.
+ */
+cloudcall!(
+    invoke_poll, invoke_poll_pb, |state, handle: raw u64| -> InvokeHandleResult { state.invoke_poll(handle) }
+);
+
+/*
+    Same as invoke_poll, but waits for the result to arrive, for at most the given timeout
+    in milliseconds (forever if unset). A result that starts arriving is read whole under the same
+    timeout. If it stops arriving midway, the invocation is collected as failed.
+ */
+cloudcall!(
+    invoke_await, invoke_await_pb, |state, invoke_await: msg InvokeAwait| -> InvokeHandleResult {
+        state.invoke_await(invoke_await)
+    }
+);
+
+/*
+    Invokes a list of gates and services and waits for all of them. All invocations run concurrently, and
+    the runtime's invocation timeout applies to the fan-out as a whole rather than to every invocation.
+    Takes the same invocations as dent_invoke (sync is ignored). Each invocation performs the same invocation check as dent_invoke and
+    starts at the current label, so no invocation sees another's result. Afterwards, the current label is
+    the lub of the labels the invocations would have left it at one by one.
+
+    Returns true and the results of all invocations (same as service_invoke, without response metadata for
+    gates) if every invocation succeeded. Returns false and the results of all invocations otherwise.
+ */
+cloudcall!(
+    fan_out, fan_out_pb, |state, fan_out: msg FanOut| -> FanOutResult { state.fan_out(fan_out) }
+);
+
+/*
+    Cancels an asynchronous gate invocation given its invocation handle by closing its connection to the
+    scheduler. The invocation may still run, but its result is discarded and can no longer be collected.
+
+    Returns true if successful, false if there is no uncollected invocation with the given handle.
+ */
+cloudcall!(
+    invoke_cancel, invoke_cancel_pb, |state, handle: raw u64| -> DentResult { state.invoke_cancel(handle) }
+);
+
+/*
+    Gets the file descriptors (in the blobs table) of the blobs passed to this function by its invoker,
+    in the order the invoker passed them.
+ */
+cloudcall!(
+    input_blobs, input_blobs_pb, |state| -> InputBlobs { state.input_blobs() }
+);
+
 /*
     Uses the content-hash of a blob to find it in the blobstore and 
     place it in the blobs table.
@@ -980,30 +462,8 @@ host_fn!(
     reading a Blob object (a wrapper around the content hash), so a label
     raise is performed.
  */
-host_fn!(
-    dent_get_blob(user_data: SyscallProcessor; fd: u64) -> Json<BlobResult> {
-        let state = user_data.get()?;
-        let mut state = state.lock().unwrap();
-
-        match state.dents.get(&fd) {
-            Some(DirEntry::Blob(blob_objref)) => {
-                // get the blob from the blobstore using it's name in the fs
-                let blob = state
-                    .env.blobstore
-                    .open(blob_objref.read(&state.env.fs))
-                .expect("Get blob failed. Couldn't open blob");
-                
-                // insert the fetched blob in the table of open blobs
-                let blobfd = state.max_blob_id;
-                state.max_blob_id += 1;
-                let len = blob.len().expect("Get blob failed. Couldn't find blob length");
-                state.blobs.insert(blobfd, blob);
-
-                Ok(Json( BlobResult {success: true, fd: blobfd, len, data: None} ))
-            }
-            _ => Ok(Json(BlobResult {success: false, fd: 0, len: 0, data: None}))
-        }
-    }
+cloudcall!(
+    dent_get_blob, dent_get_blob_pb, |state, fd: raw u64| -> BlobResult { state.dent_get_blob(fd) }
 );
 
 /*
@@ -1012,22 +472,8 @@ host_fn!(
 
     Returns true and the new blob's file descriptor if successful. Returns false otherwise.
  */
-host_fn!(
-    blob_create(user_data: SyscallProcessor;) -> Json<BlobResult> {
-        let state = user_data.get()?;
-        let mut state = state.lock().unwrap();
-
-        match state.env.blobstore.create() {
-            Ok(newblob) => {
-                let blobfd = state.max_blob_id;
-                state.max_blob_id += 1;
-                state.create_blobs.insert(blobfd, newblob);
-
-                Ok(Json(BlobResult {success: true, fd: blobfd, len: 0, data: None}))
-            },
-            Err(e) => Ok(Json(BlobResult{success: false, fd: 0, len: 0, data: Some(e.to_string().into())}))
-        }
-    }
+cloudcall!(
+    blob_create, blob_create_pb, |state| -> BlobResult { state.blob_create() }
 );
 
 /*
@@ -1035,22 +481,8 @@ host_fn!(
 
     Returns true and the number of bytes written if successful. Returns false otherwise.
  */
-host_fn!(
-    blob_write(user_data: SyscallProcessor; blob_write_json: Json<BlobWrite>) -> Json<BlobResult> {
-        let state = user_data.get()?;
-        let mut state = state.lock().unwrap();
-
-        let Json(BlobWrite { fd, data }) = blob_write_json;
-
-        if let Some(newblob) = state.create_blobs.get_mut(&fd) {
-            match newblob.write(&data) {
-                Ok(len) => Ok(Json(BlobResult { success: true, fd, len: len as u64, data: None })),
-                Err(e) => Ok(Json(BlobResult { success: false, fd, len: 0, data: Some(e.to_string().into()) }))
-            }
-        } else {
-            Ok(Json(BlobResult {success: false, fd: 0, len: 0, data: None }))
-        }
-    }
+cloudcall!(
+    blob_write, blob_write_pb, |state, blob_write: msg BlobWrite| -> BlobResult { state.blob_write(blob_write) }
 );
 
 /*
@@ -1060,82 +492,224 @@ host_fn!(
     Returns true as well as the length and file descriptor of the blob if successful. 
     Returns false otherwise.
  */
-host_fn!(
-    blob_finalize(user_data: SyscallProcessor; blob_finalize_json: Json<BlobFinalize>) -> Json<BlobResult> {
-        let state = user_data.get()?;
-        let mut state = state.lock().unwrap();
-
-        let Json(BlobFinalize { fd }) = blob_finalize_json;
-
-        if let Some(newblob) = state.create_blobs.remove(&fd) {
-            let len = newblob.len() as u64;
-            
-            match state.env.blobstore.save(newblob) {
-                Ok(finalized_blob) => {
-                    state.blobs.insert(fd, finalized_blob);
-                    Ok(Json(BlobResult { success: true, fd,  len, data: None}))
+cloudcall!(
+    blob_finalize, blob_finalize_pb, |state, blob_finalize: msg BlobFinalize| -> BlobResult { state.blob_finalize(blob_finalize) }
+);
+
+/*
+    Reads a blob given its file descriptor (in the blobs table), an offset (default = 0), and 
+    length (default = 4KB).
+
+    Returns true, the bytes read and their length if successful. Returns false otherwise.
+ */
+cloudcall!(
+    blob_read, blob_read_pb, |state, blob_read: msg BlobRead| -> BlobResult { state.blob_read(blob_read) }
+);
+
+/*
+    Reads a chunk of a blob into a block of extism memory allocated by the guest (e.g with Memory::new),
+    without encoding the bytes. Takes the blob's file descriptor (in the blobs table), an offset into the blob,
+    and the offset of the memory block. The chunk is at most as long as the block. Host functions can
+    only write to extism memory, not to the guest's own linear memory, so the guest copies the bytes out of
+    the block afterwards (e.g with Memory::load). This saves encoding the bytes and allocating a result
+    message, but not that copy. Since nothing is encoded, there is no protobuf variant of this cloudcall.
+
+    Returns the number of bytes read if successful. Returns -1 otherwise.
+ */
+fn blob_read_into(
+    plugin: &mut CurrentPlugin,
+    inputs: &[Val],
+    outputs: &mut [Val],
+    user_data: UserData<SyscallProcessor>
+) -> Result<(), Error> {
+    let state = user_data.get()?;
+    let state = state.lock().unwrap();
+
+    let fd = inputs[0].unwrap_i64() as u64;
+    let offset = inputs[1].unwrap_i64() as u64;
+    let buf_offset = inputs[2].unwrap_i64() as u64;
+
+    let num_bytes_read = match (state.blobs.get(&fd), plugin.memory_handle(buf_offset)) {
+        (Some(blob), Some(buf_handle)) => {
+            let buf = plugin.memory_bytes_mut(buf_handle)?;
+            match blob.read_at(buf, offset) {
+                Ok(num_bytes_read) => num_bytes_read as i64,
+                Err(e) => {
+                    log::info!("Read into guest memory failed. {}", e);
+                    -1
                 }
-                Err(e) => Ok(Json(BlobResult { success: false, fd, len, data: Some(e.to_string().into()) }))
             }
-        } else {
-            Ok(Json(BlobResult { success: false, fd, len: 0, data: None }))
         }
+        _ => -1
+    };
+
+    outputs[0] = Val::I64(num_bytes_read);
+    Ok(())
+}
+
+/*
+    Moves the cursor of a finalized blob given its file descriptor, an offset, and whence the offset is
+    relative to (start, current position, or end). Seeking past the end of the blob is allowed, reads
+    from there return no bytes. The cursor is only used by blob_read_next and blob_read_to_end.
+
+    Returns true and the new cursor position (as len) if successful. Returns false otherwise.
+ */
+cloudcall!(
+    blob_seek, blob_seek_pb, |state, blob_seek: msg BlobSeek| -> BlobResult { state.blob_seek(blob_seek) }
+);
+
+/*
+    Gets the cursor position of a blob given its file descriptor. Blobs that are still being written
+    are only ever appended to, so their cursor position is their length.
+
+    Returns true and the cursor position (as len) if successful. Returns false otherwise.
+ */
+cloudcall!(
+    blob_tell, blob_tell_pb, |state, fd: raw u64| -> BlobResult { state.blob_tell(fd) }
+);
+
+/*
+    Reads a finalized blob from its cursor given its file descriptor and a length (default = 4KB),
+    and moves the cursor past the bytes read.
+
+    Returns true, the bytes read and their length if successful. Returns false otherwise.
+ */
+cloudcall!(
+    blob_read_next, blob_read_next_pb, |state, blob_read_next: msg BlobReadNext| -> BlobResult {
+        state.blob_read_next(blob_read_next)
     }
 );
 
 /*
-    Reads a blob given its file descriptor (in the blobs table), an offset (default = 0), and 
-    length (default = 4KB).
+    Reads a finalized blob from its cursor to its end given its file descriptor, and moves the cursor
+    to the end. The bytes are returned at once, so reading more than max_blob_read_bytes fails and
+    leaves the cursor where it was. Use blob_read_next to read larger blobs in chunks.
 
     Returns true, the bytes read and their length if successful. Returns false otherwise.
  */
-host_fn!(
-    blob_read(user_data: SyscallProcessor; blob_read_json: Json<BlobRead>) -> Json<BlobResult> {
-        let state = user_data.get()?;
-        let state = state.lock().unwrap();
+cloudcall!(
+    blob_read_to_end, blob_read_to_end_pb, |state, fd: raw u64| -> BlobResult { state.blob_read_to_end(fd) }
+);
 
-        let Json(BlobRead { fd, offset: maybe_offset, length: maybe_length }) = blob_read_json;
+/*
+    Copies the contents of a File object into a new blob, given the file's file descriptor (in the
+    dents table). Raises the current label to the file's label. The new blob is finalized and placed
+    in the blobs table, so it can be linked into the file system with dent_create.
 
-        // offset defaults to 0 and length defaults to 4096 bytes (4KB)
-        let offset = maybe_offset.unwrap_or(0);
-        let length = maybe_length.unwrap_or(4096);
+    Returns true, the new blob's file descriptor and its length if successful. Returns false otherwise.
+ */
+cloudcall!(
+    file_to_blob, file_to_blob_pb, |state, fd: raw u64| -> BlobResult { state.file_to_blob(fd) }
+);
 
-        if let Some(blob) = state.blobs.get(&fd) {
-            let mut buf = vec![0; length as usize];
+/*
+    Replaces the contents of a File object with the contents of a finalized blob, given the blob's
+    file descriptor (in the blobs table) and the file's file descriptor (in the dents table). Fails
+    if the current label can't flow to the file's label, or if the blob is larger than max_file_bytes
+    (see the runtime config), since files are written whole and the blob is held in memory meanwhile.
 
-            match blob.read_at(&mut buf, offset) {
-                Ok(num_bytes_read) => {
-                    buf.resize(num_bytes_read, 0);
-                    Ok(Json(BlobResult { success: true, fd, len: num_bytes_read as u64, data: Some(buf) }))
-                },
-                Err(e) => Ok(Json(BlobResult { success: false, fd, len: 0, data: Some(e.to_string().into()) }))
-            }
-        } else {
-            Ok(Json(BlobResult { success: false, fd, len: 0, data: None }))
-        }
+    Returns true if successful, false otherwise.
+ */
+cloudcall!(
+    blob_to_file, blob_to_file_pb, |state, blob_fd: raw u64, file_fd: raw u64| -> DentResult {
+        state.blob_to_file(blob_fd, file_fd)
     }
 );
 
 /*
-    Removes a blob from the blobs table.
+    Gets the name (content hash) and length of a blob given its file descriptor. Works for finalized blobs
+    and for blobs that are still being written. The name of the latter is the content hash of the bytes
+    written so far, i.e the name the blob gets if it is finalized without further writes.
+
+    Returns true along with the name, length, and whether the blob is finalized if successful.
+    Returns false otherwise.
+ */
+cloudcall!(
+    blob_stat, blob_stat_pb, |state, fd: raw u64| -> BlobStat { state.blob_stat(fd) }
+);
+
+/*
+    Removes a blob from the blobs table. Blobs that are still being written (i.e not finalized yet)
+    are discarded, same as with blob_abort.
 
     Returns true if successful, false otherwise.
  */
-host_fn!(
-    blob_close(user_data: SyscallProcessor; blob_close_json: Json<BlobClose>) -> Json<BlobResult> {
-        let state = user_data.get()?;
-        let mut state = state.lock().unwrap();
+cloudcall!(
+    blob_close, blob_close_pb, |state, blob_close: msg BlobClose| -> BlobResult { state.blob_close(blob_close) }
+);
 
-        let Json(BlobClose { fd }) = blob_close_json;
+/*
+    Discards a NewBlob that is still being written, given its file descriptor. The written
+    data is never added to the blobstore, and its temporary file is removed right away.
 
-        if state.blobs.remove(&fd).is_some() {
-            Ok(Json(BlobResult { success: true, fd, len: 0, data: None }))
-        } else {
-            Ok(Json(BlobResult { success: false, fd, len: 0, data: None }))
+    Returns true and the number of bytes that had been written if successful. Returns false otherwise.
+ */
+cloudcall!(
+    blob_abort, blob_abort_pb, |state, fd: raw u64| -> BlobResult { state.blob_abort(fd) }
+);
+
+/*
+    Executes a batch of dent operations (open, create, link, unlink, update, read, list, close) in order
+    and returns the results of all of them, saving a host call per operation. An operation can refer to
+    an earlier operation of the same batch to use the file descriptor it produced, e.g to link an object
+    created earlier in the batch. Each operation performs the same label raises and checks as the
+    corresponding cloudcall.
+
+    Returns true and the results of all executed operations if every operation succeeded.
+    Returns false and the results executed so far otherwise.
+ */
+cloudcall!(
+    batch, batch_pb, |state, batch: msg Batch| -> BatchResult { state.batch(batch) }
+);
+
+/*
+    Starts a transaction. Until it is committed, changes to FS objects (files, directories, blob objects,
+    gates and services) are only visible to this function. Blobs in the blobstore are not part of the transaction.
+    Transactions don't nest.
+
+    Returns true if a transaction was started, false if one is already open.
+ */
+cloudcall!(
+    txn_begin, txn_begin_pb, |state| -> DentResult {
+        let res = state.env.txn.begin();
+        if let Err(e) = &res {
+            log::info!("Failed to begin transaction. {:?}", e);
+        }
+
+        DentResult{success: res.is_ok(), fd: None, data: None}
+    }
+);
+
+/*
+    Commits the open transaction, storing all of its changes to the backing store at once.
+    Changes of a transaction that is still open when the function returns or traps are discarded.
+
+    Objects the transaction updated conditionally (e.g directories linked into, or newly created objects) must
+    not have been changed by another function since the transaction first looked at them. Otherwise nothing
+    is stored and the commit fails.
+
+    Returns true if the changes were stored, false otherwise. The transaction is closed either way.
+ */
+cloudcall!(
+    txn_commit, txn_commit_pb, |state| -> DentResult {
+        let res = state.env.txn.commit();
+        if let Err(e) = &res {
+            log::info!("Failed to commit transaction. {:?}", e);
         }
+
+        DentResult{success: res.is_ok(), fd: None, data: None}
     }
 );
 
+/*
+    Aborts the open transaction, discarding all of its changes.
+
+    Returns true if a transaction was open, false otherwise.
+ */
+cloudcall!(
+    txn_abort, txn_abort_pb, |state| -> DentResult { DentResult{success: state.env.txn.abort().is_ok(), fd: None, data: None} }
+);
+
 
 #[derive(Debug)]
 pub enum SyscallProcessorError {
@@ -1146,28 +720,107 @@ pub enum SyscallProcessorError {
     HttpAuth,
     BadStrPath,
     BadUrlArgs,
+    BadHeaderArgs,
+    BadBodyArgs,
+    Timeout,
+    HttpStatus(u16),
+    ResponseTooLarge,
+    EgressDenied,
+    SecretUnavailable,
+    NoFixture,
+    Fixture(std::io::Error),
+}
+
+impl SyscallProcessorError {
+    // timeouts and redirects denied by the egress policy are told apart from other http errors
+    pub fn from_http(e: reqwest::Error) -> Self {
+        let redirect_denied = std::error::Error::source(&e)
+            .map_or(false, |source| source.is::<service::RedirectDenied>());
+        if e.is_timeout() {
+            Self::Timeout
+        } else if redirect_denied {
+            Self::EgressDenied
+        } else {
+            Self::Http(e)
+        }
+    }
+}
+
+// describes the error to the guest, e.g in the data of a failed DentInvokeResult
+impl std::fmt::Display for SyscallProcessorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnreachableScheduler => write!(f, "unreachable scheduler"),
+            Self::Blob(e) => write!(f, "blob error: {}", e),
+            Self::Database => write!(f, "database error"),
+            Self::Http(e) => write!(f, "http error: {}", e),
+            Self::HttpAuth => write!(f, "http authentication error"),
+            Self::BadStrPath => write!(f, "bad path"),
+            Self::BadUrlArgs => write!(f, "bad url arguments"),
+            Self::BadHeaderArgs => write!(f, "bad header arguments"),
+            Self::BadBodyArgs => write!(f, "bad body arguments"),
+            Self::Timeout => write!(f, "timeout"),
+            Self::HttpStatus(status) => write!(f, "http status {}", status),
+            Self::ResponseTooLarge => write!(f, "response too large"),
+            Self::EgressDenied => write!(f, "egress denied"),
+            Self::SecretUnavailable => write!(f, "secret unavailable"),
+            Self::NoFixture => write!(f, "no recorded response"),
+            Self::Fixture(e) => write!(f, "fixture error: {}", e),
+        }
+    }
+}
+
+pub(crate) fn current_label() -> Buckle {
+    CURRENT_LABEL.with(|cl| cl.borrow().clone())
+}
+
+/*
+    Replaces the current label, bypassing label checks. Only used by the runtime itself to undo
+    label changes the function doesn't observe, e.g between the invocations of a fan-out.
+ */
+pub(crate) fn set_label(label: Buckle) {
+    fs::utils::clear_label();
+    fs::utils::taint_with_label(label);
 }
 
 
 pub struct SyscallGlobalEnv {
-    pub sched_conn: Option<TcpStream>,
     pub fs: FS<Box<dyn BackingStore>>,
-    pub blobstore: Blobstore
+    pub txn: TxnHandle,
+    pub blobstore: Blobstore,
+    pub config: RuntimeConfig,
+    // recorded service responses, if services are replayed or recorded
+    pub fixtures: Option<Fixtures>,
+    // base directory of the blobstore, where finalized blobs are stored
+    pub blobstore_base_dir: PathBuf,
+    // tmp directory of the blobstore. Owned by the processor, removed when it is dropped
+    pub blobstore_tmp_dir: PathBuf
 }
 
 
 pub struct SyscallProcessor {
     env: SyscallGlobalEnv,
     dents: HashMap<u64, DirEntry>,
+    dent_origins: HashMap<u64, (DirEntry, String)>,
     max_dent_id: u64,
     blobs: HashMap<u64, Blob>,
+    blob_cursors: HashMap<u64, u64>,
+    // blobs passed to this function by its invoker, opened with open_input_blobs
+    input_blobs: Vec<u64>,
     max_blob_id: u64,
+    // gate invocations whose result wasn't collected yet, keyed by invocation handle
+    pending_invokes: HashMap<u64, PendingInvoke>,
+    max_invoke_id: u64,
     create_blobs: HashMap<u64, NewBlob>,
-    http_client: reqwest::blocking::Client
+    // content hash of the bytes written so far to each blob in create_blobs
+    create_blob_hashers: HashMap<u64, Sha256>,
+    http_client: reqwest::blocking::Client,
+    // clients of services with a configured connect timeout, keyed by the timeout in milliseconds
+    http_clients: HashMap<u64, reqwest::blocking::Client>
 }
 
 impl SyscallProcessor {
-    pub fn new(env: SyscallGlobalEnv, label: Buckle, privilege: Component) -> Self {
+    pub fn new(env: SyscallGlobalEnv, label: Buckle, privilege: Component) -> Result<Self, SyscallProcessorError> {
         {
             // set up label & privilege
             fs::utils::clear_label();
@@ -1179,83 +832,245 @@ impl SyscallProcessor {
         let mut dents: HashMap<u64, fs::DirEntry> = Default::default();
         dents.insert(0, DirEntry::Directory(fs::ROOT_REF));
 
-        Self {
+        // connect timeouts can only be set per client, so each configured one gets a client
+        let http_clients = env.config.connect_timeouts()
+            .map(|connect_timeout| {
+                service::http_client(env.config.egress.clone(), Some(std::time::Duration::from_millis(connect_timeout)))
+                    .map(|client| (connect_timeout, client))
+                    .map_err(|e| {
+                        log::error!("Couldn't build http client with a {}ms connect timeout. {}", connect_timeout, e);
+                        SyscallProcessorError::Http(e)
+                    })
+            })
+        .collect::<Result<_, _>>()?;
+        let http_client = service::http_client(env.config.egress.clone(), None)
+            .map_err(|e| {
+                log::error!("Couldn't build http client. {}", e);
+                SyscallProcessorError::Http(e)
+            })?;
+
+        Ok(Self {
             env,
             create_blobs: Default::default(),
+            create_blob_hashers: Default::default(),
             blobs: Default::default(),
+            blob_cursors: Default::default(),
+            input_blobs: Default::default(),
             dents,
+            dent_origins: Default::default(),
             max_dent_id: 1,
             max_blob_id: 1,
-            http_client: reqwest::blocking::Client::new(),
-        }
+            pending_invokes: Default::default(),
+            max_invoke_id: 1,
+            http_client,
+            http_clients,
+        })
     }
 
-    fn http_send(
-        &self,
-        service: &fs::Service, 
-        body: Option<Vec<u8>>, 
-        parameters: HashMap<String, String>
-    ) -> Result<reqwest::blocking::Response, SyscallProcessorError> {
-        let url = strfmt::strfmt(&service.url, &parameters)
-            .map_err(|_| SyscallProcessorError::BadUrlArgs)?;
-        let method = service.verb.clone().into();
-        let headers = service.headers.iter()
-            .map(|(a, b)| {
-                (
-                    reqwest::header::HeaderName::from_bytes(a.as_bytes()).unwrap(),
-                    reqwest::header::HeaderValue::from_bytes(b.as_bytes()).unwrap()
-                )
-            })
-        .collect::<reqwest::header::HeaderMap>();
+    /*
+        A processor running with label and privilege over an empty backing store and blobstore in a
+        temporary directory. The directory is removed when the returned TempDir is dropped.
+     */
+    #[cfg(test)]
+    pub(crate) fn for_test(label: Buckle, privilege: Component) -> (tempfile::TempDir, Self) {
+        let dir = tempfile::tempdir().unwrap();
+        let dbenv = Box::leak(Box::new(::lmdb::Environment::new().open(dir.path()).unwrap()));
+        let store = TxnStore::new(dbenv);
+        let txn = store.handle();
+        let fs: FS<Box<dyn BackingStore>> = FS::new(Box::new(store));
+        fs.initialize();
+
+        let blobstore_base_dir = dir.path().join("blobs");
+        let blobstore_tmp_dir = dir.path().join("tmp");
+        std::fs::create_dir_all(&blobstore_base_dir).unwrap();
+        std::fs::create_dir_all(&blobstore_tmp_dir).unwrap();
+
+        let env = SyscallGlobalEnv {
+            fs,
+            txn,
+            blobstore: Blobstore::new(blobstore_base_dir.clone(), blobstore_tmp_dir.clone()),
+            config: Default::default(),
+            fixtures: None,
+            blobstore_base_dir,
+            blobstore_tmp_dir
+        };
+        (dir, Self::new(env, label, privilege).unwrap())
+    }
 
-        let mut request = self.http_client.request(method, url).headers(headers);
-        if let Some(body) = body {
-            request = request.body(body);
-        }
+    // a public direct gate with privilege and declassify, returns its file descriptor in the dents table
+    #[cfg(test)]
+    pub(crate) fn gate_for_test(&mut self, privilege: Component, declassify: Component) -> u64 {
+        let gate = self.env.fs.create_direct_gate(Buckle::public(), fs::DirectGate {
+            privilege,
+            invoker_integrity_clearance: Component::dc_true(),
+            declassify,
+            function: fs::Function {
+                memory: 128,
+                app_image: "app".to_string(),
+                runtime_image: "runtime".to_string(),
+                kernel: "Kernel Not Used".to_string()
+            }
+        }).unwrap();
+
+        let fd = self.max_dent_id;
+        self.dents.insert(fd, gate);
+        self.max_dent_id += 1;
+        fd
+    }
 
-        request.send().map_err(|e| SyscallProcessorError::Http(e))
+    // a finalized blob holding data, returns its file descriptor in the blobs table
+    #[cfg(test)]
+    pub(crate) fn blob_for_test(&mut self, data: &[u8]) -> u64 {
+        let fd = self.blob_create().fd;
+        assert!(self.blob_write(BlobWrite { fd, data: data.to_vec() }).success);
+        assert!(self.blob_finalize(BlobFinalize { fd }).success);
+        fd
     }
 
-    pub fn run(self, path_to_wasm: &String) -> Result<TaskReturn, SyscallProcessorError> {
+    /*
+        Runs the module's run export with payload as its input. blobs are the blobs passed by the invoker,
+        keyed by their position in its list like the blobs of a LabeledInvoke. They are opened before the
+        function starts, so it finds them with input_blobs. A function that returns an error (e.g through
+        #[faasten::function]) or traps fails with ProcessRequestFailed, and so does a function whose blobs
+        can't be opened. The function's output, or its error message, is the returned payload.
+     */
+    pub fn run(
+        mut self,
+        path_to_wasm: &String,
+        payload: &[u8],
+        blobs: HashMap<String, String>
+    ) -> Result<TaskReturn, SyscallProcessorError> {
+        if let Err(e) = self.open_input_blobs(blobs) {
+            log::error!("Couldn't open input blobs. {}", e);
+            return Ok(task_return(ReturnCode::ProcessRequestFailed, format!("couldn't open input blobs: {}", e).into_bytes()));
+        }
 
         let wasm_obj = Wasm::file(path_to_wasm);
         let manifest = Manifest::new([wasm_obj]);
 
+        let txn = self.env.txn.clone();
         let runtime_state = UserData::new(self);
 
         let mut plugin = PluginBuilder::new(manifest)
             .with_function("get_current_label", [], [PTR], runtime_state.clone(), get_current_label)
+            .with_function("get_current_label_pb", [], [PTR], runtime_state.clone(), get_current_label_pb)
             .with_function("buckle_parse", [PTR], [PTR], runtime_state.clone(), buckle_parse)
+            .with_function("buckle_parse_pb", [PTR], [PTR], runtime_state.clone(), buckle_parse_pb)
             .with_function("taint_with_label", [PTR], [PTR], runtime_state.clone(), taint_with_label)
+            .with_function("taint_with_label_pb", [PTR], [PTR], runtime_state.clone(), taint_with_label_pb)
             .with_function("declassify", [PTR], [PTR], runtime_state.clone(), declassify)
+            .with_function("declassify_pb", [PTR], [PTR], runtime_state.clone(), declassify_pb)
             .with_function("root", [], [PTR], runtime_state.clone(), root)
+            .with_function("root_pb", [], [PTR], runtime_state.clone(), root_pb)
             .with_function("dent_open", [PTR], [PTR], runtime_state.clone(), dent_open)
+            .with_function("dent_open_pb", [PTR], [PTR], runtime_state.clone(), dent_open_pb)
             .with_function("dent_close", [ValType::I64], [PTR], runtime_state.clone(), dent_close)
+            .with_function("dent_close_pb", [ValType::I64], [PTR], runtime_state.clone(), dent_close_pb)
             .with_function("dent_create", [PTR], [PTR], runtime_state.clone(), dent_create)
+            .with_function("dent_create_pb", [PTR], [PTR], runtime_state.clone(), dent_create_pb)
             .with_function("dent_update", [PTR], [PTR], runtime_state.clone(), dent_update)
+            .with_function("dent_update_pb", [PTR], [PTR], runtime_state.clone(), dent_update_pb)
+            .with_function("dent_update_service", [PTR], [PTR], runtime_state.clone(), dent_update_service)
+            .with_function("dent_update_service_pb", [PTR], [PTR], runtime_state.clone(), dent_update_service_pb)
+            .with_function("dent_relabel", [ValType::I64, PTR], [PTR], runtime_state.clone(), dent_relabel)
+            .with_function("dent_relabel_pb", [ValType::I64, PTR], [PTR], runtime_state.clone(), dent_relabel_pb)
             .with_function("dent_read", [ValType::I64], [PTR], runtime_state.clone(), dent_read)
+            .with_function("dent_read_pb", [ValType::I64], [PTR], runtime_state.clone(), dent_read_pb)
             .with_function("dent_link", [PTR], [PTR], runtime_state.clone(), dent_link)
+            .with_function("dent_link_pb", [PTR], [PTR], runtime_state.clone(), dent_link_pb)
             .with_function("dent_unlink", [PTR], [PTR], runtime_state.clone(), dent_unlink)
+            .with_function("dent_unlink_pb", [PTR], [PTR], runtime_state.clone(), dent_unlink_pb)
             .with_function("dent_list", [ValType::I64], [PTR], runtime_state.clone(), dent_list)
+            .with_function("dent_list_pb", [ValType::I64], [PTR], runtime_state.clone(), dent_list_pb)
             .with_function("dent_ls_faceted", [PTR], [PTR], runtime_state.clone(), dent_ls_faceted)
+            .with_function("dent_ls_faceted_pb", [PTR], [PTR], runtime_state.clone(), dent_ls_faceted_pb)
             .with_function("dent_ls_gate", [ValType::I64], [PTR], runtime_state.clone(), dent_ls_gate)
+            .with_function("dent_ls_gate_pb", [ValType::I64], [PTR], runtime_state.clone(), dent_ls_gate_pb)
             .with_function("dent_invoke", [PTR], [PTR], runtime_state.clone(), dent_invoke)
+            .with_function("dent_invoke_pb", [PTR], [PTR], runtime_state.clone(), dent_invoke_pb)
+            .with_function("dent_invoke_blobs", [PTR], [PTR], runtime_state.clone(), dent_invoke_blobs)
+            .with_function("dent_invoke_blobs_pb", [PTR], [PTR], runtime_state.clone(), dent_invoke_blobs_pb)
+            .with_function("service_invoke", [PTR], [PTR], runtime_state.clone(), service_invoke)
+            .with_function("service_invoke_pb", [PTR], [PTR], runtime_state.clone(), service_invoke_pb)
+            .with_function("invoke_poll", [ValType::I64], [PTR], runtime_state.clone(), invoke_poll)
+            .with_function("invoke_poll_pb", [ValType::I64], [PTR], runtime_state.clone(), invoke_poll_pb)
+            .with_function("invoke_await", [PTR], [PTR], runtime_state.clone(), invoke_await)
+            .with_function("invoke_await_pb", [PTR], [PTR], runtime_state.clone(), invoke_await_pb)
+            .with_function("invoke_cancel", [ValType::I64], [PTR], runtime_state.clone(), invoke_cancel)
+            .with_function("invoke_cancel_pb", [ValType::I64], [PTR], runtime_state.clone(), invoke_cancel_pb)
+            .with_function("fan_out", [PTR], [PTR], runtime_state.clone(), fan_out)
+            .with_function("fan_out_pb", [PTR], [PTR], runtime_state.clone(), fan_out_pb)
+            .with_function("input_blobs", [], [PTR], runtime_state.clone(), input_blobs)
+            .with_function("input_blobs_pb", [], [PTR], runtime_state.clone(), input_blobs_pb)
             .with_function("dent_get_blob", [ValType::I64], [PTR], runtime_state.clone(), dent_get_blob)
+            .with_function("dent_get_blob_pb", [ValType::I64], [PTR], runtime_state.clone(), dent_get_blob_pb)
             .with_function("blob_create", [], [PTR], runtime_state.clone(), blob_create)
+            .with_function("blob_create_pb", [], [PTR], runtime_state.clone(), blob_create_pb)
             .with_function("blob_write", [PTR], [PTR], runtime_state.clone(), blob_write)
+            .with_function("blob_write_pb", [PTR], [PTR], runtime_state.clone(), blob_write_pb)
             .with_function("blob_finalize", [PTR], [PTR], runtime_state.clone(), blob_finalize)
+            .with_function("blob_finalize_pb", [PTR], [PTR], runtime_state.clone(), blob_finalize_pb)
             .with_function("blob_read", [PTR], [PTR], runtime_state.clone(), blob_read)
+            .with_function("blob_read_pb", [PTR], [PTR], runtime_state.clone(), blob_read_pb)
+            .with_function("blob_read_into", [ValType::I64, ValType::I64, ValType::I64], [ValType::I64], runtime_state.clone(), blob_read_into)
+            .with_function("blob_seek", [PTR], [PTR], runtime_state.clone(), blob_seek)
+            .with_function("blob_seek_pb", [PTR], [PTR], runtime_state.clone(), blob_seek_pb)
+            .with_function("blob_tell", [ValType::I64], [PTR], runtime_state.clone(), blob_tell)
+            .with_function("blob_tell_pb", [ValType::I64], [PTR], runtime_state.clone(), blob_tell_pb)
+            .with_function("blob_read_next", [PTR], [PTR], runtime_state.clone(), blob_read_next)
+            .with_function("blob_read_next_pb", [PTR], [PTR], runtime_state.clone(), blob_read_next_pb)
+            .with_function("blob_read_to_end", [ValType::I64], [PTR], runtime_state.clone(), blob_read_to_end)
+            .with_function("blob_read_to_end_pb", [ValType::I64], [PTR], runtime_state.clone(), blob_read_to_end_pb)
+            .with_function("file_to_blob", [ValType::I64], [PTR], runtime_state.clone(), file_to_blob)
+            .with_function("file_to_blob_pb", [ValType::I64], [PTR], runtime_state.clone(), file_to_blob_pb)
+            .with_function("blob_to_file", [ValType::I64, ValType::I64], [PTR], runtime_state.clone(), blob_to_file)
+            .with_function("blob_to_file_pb", [ValType::I64, ValType::I64], [PTR], runtime_state.clone(), blob_to_file_pb)
+            .with_function("blob_stat", [ValType::I64], [PTR], runtime_state.clone(), blob_stat)
+            .with_function("blob_stat_pb", [ValType::I64], [PTR], runtime_state.clone(), blob_stat_pb)
             .with_function("blob_close", [PTR], [PTR], runtime_state.clone(), blob_close)
+            .with_function("blob_close_pb", [PTR], [PTR], runtime_state.clone(), blob_close_pb)
+            .with_function("blob_abort", [ValType::I64], [PTR], runtime_state.clone(), blob_abort)
+            .with_function("blob_abort_pb", [ValType::I64], [PTR], runtime_state.clone(), blob_abort_pb)
+            .with_function("batch", [PTR], [PTR], runtime_state.clone(), batch)
+            .with_function("batch_pb", [PTR], [PTR], runtime_state.clone(), batch_pb)
+            .with_function("txn_begin", [], [PTR], runtime_state.clone(), txn_begin)
+            .with_function("txn_begin_pb", [], [PTR], runtime_state.clone(), txn_begin_pb)
+            .with_function("txn_commit", [], [PTR], runtime_state.clone(), txn_commit)
+            .with_function("txn_commit_pb", [], [PTR], runtime_state.clone(), txn_commit_pb)
+            .with_function("txn_abort", [], [PTR], runtime_state.clone(), txn_abort)
+            .with_function("txn_abort_pb", [], [PTR], runtime_state.clone(), txn_abort_pb)
             .build()
         .unwrap();
 
-        let res = plugin.call::<(), &str>("run", ()).unwrap();
-        println!("Return: {}", res);
+        let res = plugin.call::<&[u8], Vec<u8>>("run", payload);
+
+        // discard a transaction that wasn't committed, e.g because the guest trapped
+        if txn.abort().is_ok() {
+            log::info!("Discarded uncommitted transaction");
+        }
 
-        Ok(TaskReturn { code: ReturnCode::Success as i32, payload: None, label: None })
+        // the output, or the error message of a failed function, is returned to the invoker as the payload
+        match res {
+            Ok(output) => {
+                println!("Return: {}", String::from_utf8_lossy(&output));
+                Ok(task_return(ReturnCode::Success, output))
+            }
+            Err(e) => {
+                log::error!("Failed: {}", e);
+                Ok(task_return(ReturnCode::ProcessRequestFailed, e.to_string().into_bytes()))
+            }
+        }
     }
 }
 
+fn task_return(code: ReturnCode, body: Vec<u8>) -> TaskReturn {
+    let mut task_return = TaskReturn { code: code as i32, payload: Some(Default::default()), label: None };
+    if let Some(payload) = task_return.payload.as_mut() {
+        payload.body = Some(body);
+    }
+    task_return
+}
+
 
 fn main() -> Result<(), & 'static str> {
     // set RUST_LOG environment variable to configure log level
@@ -1263,36 +1078,125 @@ fn main() -> Result<(), & 'static str> {
         .filter_or("RUST_LOG", "warn");
     env_logger::init_from_env(env);
 
-    let mut args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        return Err("Usage: cargo run -- pathToWasm");
+    /*
+        the payload is optional and passed to the function as its input. Any further arguments are names of
+        blobs in the blobstore, passed to the function as if its invoker passed them
+     */
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        return Err("Usage: cargo run -- pathToWasm [payload [blobName...]]");
     }
-    let file_path = args.pop().unwrap();
+    let file_path = args[1].clone();
+    let payload = args.get(2).cloned().unwrap_or_default();
+    let blobs: HashMap<String, String> = args.iter().skip(3).cloned().enumerate().map(|(i, name)| (i.to_string(), name)).collect();
 
     // set up FS object, label, and privilege
     let dbenv = std::boxed::Box::leak(Box::new(lmdb::get_dbenv(BACKING_STORE_PATH)));
-    let fs: FS<Box<dyn BackingStore>> = FS::new(Box::new(&*dbenv));
+    let store = TxnStore::new(&*dbenv);
+    let txn = store.handle();
+    let fs: FS<Box<dyn BackingStore>> = FS::new(Box::new(store));
 
     // initialize fs
     if !fs.initialize() {
         println!("Existing root detected.");
     }
 
+    // set up directories for blobstore. Each runtime process writes new blobs to a tmp directory of its own
+    // and removes it when it exits. Processes that were killed leave theirs behind, so they are removed here
+    blobs::remove_stale_tmp_dirs(Path::new(BLOBSTORE_TMP_PATH), |pid| Path::new("/proc").join(pid.to_string()).exists());
+    let blobstore_tmp_dir = PathBuf::from(BLOBSTORE_TMP_PATH).join(std::process::id().to_string());
+    let _ = std::fs::create_dir(BLOBSTORE_BASE_DIR_PATH);
+    let _ = std::fs::create_dir_all(&blobstore_tmp_dir);
+
+    // read the runtime configuration, if there is one
+    let config = match RuntimeConfig::load() {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("{}", e);
+            return Err("Invalid runtime configuration");
+        }
+    };
+
+    let fixtures = match config.service_fixtures.as_ref().map(Fixtures::load).transpose() {
+        Ok(fixtures) => fixtures,
+        Err(e) => {
+            log::error!("{}", e);
+            return Err("Invalid service fixtures");
+        }
+    };
+
     let env = SyscallGlobalEnv {
-        sched_conn: None,
         fs,
-        blobstore: Blobstore::new(BLOBSTORE_BASE_DIR_PATH.into(), BLOBSTORE_TMP_PATH.into())
+        txn,
+        blobstore: Blobstore::new(BLOBSTORE_BASE_DIR_PATH.into(), blobstore_tmp_dir.clone()),
+        config,
+        fixtures,
+        blobstore_base_dir: BLOBSTORE_BASE_DIR_PATH.into(),
+        blobstore_tmp_dir
     };
-
-    // set up directories for blobstore
-    let _ = std::fs::create_dir(BLOBSTORE_BASE_DIR_PATH);
-    let _ = std::fs::create_dir(BLOBSTORE_TMP_PATH);
  
 
     /* PRIVILEGE SET TO FALSE FOR TESTING PURPOSES (OVERRIDES LABEL CHECKS). EVENTUALLY SET BACK TO TRUE! */
-    let processor = SyscallProcessor::new(env, Buckle::public(), Component::dc_false());
-    let _res = processor.run(&file_path);
+    let Ok(processor) = SyscallProcessor::new(env, Buckle::public(), Component::dc_false()) else {
+        return Err("Couldn't set up the syscall processor");
+    };
+    let _res = processor.run(&file_path, payload.as_bytes(), blobs);
 
     Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /*
+        A guest calling blob_read_into the way the SDK does. Its input is the blob's file descriptor, the
+        offset to read from and the length of the memory block to read into, as little endian u64s. Its
+        output is the chunk that was read. It fails if the read fails.
+     */
+    const READ_INTO_GUEST: &str = r#"
+        (module
+            (import "extism:host/env" "alloc" (func $alloc (param i64) (result i64)))
+            (import "extism:host/env" "input_load_u64" (func $input_load_u64 (param i64) (result i64)))
+            (import "extism:host/env" "output_set" (func $output_set (param i64 i64)))
+            (import "extism:host/user" "blob_read_into" (func $blob_read_into (param i64 i64 i64) (result i64)))
+            (func (export "run") (result i32)
+                (local $buf i64)
+                (local $num_bytes_read i64)
+                (local.set $buf (call $alloc (call $input_load_u64 (i64.const 16))))
+                (local.set $num_bytes_read
+                    (call $blob_read_into
+                        (call $input_load_u64 (i64.const 0))
+                        (call $input_load_u64 (i64.const 8))
+                        (local.get $buf)))
+                (if (i64.lt_s (local.get $num_bytes_read) (i64.const 0))
+                    (then (return (i32.const 1))))
+                (call $output_set (local.get $buf) (local.get $num_bytes_read))
+                (i32.const 0)))
+    "#;
+
+    fn read_into(plugin: &mut Plugin, fd: u64, offset: u64, len: u64) -> Result<Vec<u8>, Error> {
+        let input: Vec<u8> = [fd, offset, len].iter().flat_map(|n| n.to_le_bytes()).collect();
+        plugin.call::<&[u8], Vec<u8>>("run", &input)
+    }
+
+    #[test]
+    fn blob_read_into_reads_chunks_into_extism_memory() {
+        let (_dir, mut processor) = SyscallProcessor::for_test(Buckle::public(), Component::dc_true());
+        let contents: Vec<u8> = (0..10_000).map(|i| i as u8).collect();
+        let fd = processor.blob_for_test(&contents);
+
+        let mut plugin = PluginBuilder::new(Manifest::new([Wasm::data(READ_INTO_GUEST)]))
+            .with_function("blob_read_into", [ValType::I64, ValType::I64, ValType::I64], [ValType::I64], UserData::new(processor), blob_read_into)
+            .build()
+            .unwrap();
+
+        assert_eq!(read_into(&mut plugin, fd, 0, 10_000).unwrap(), contents);
+        // the chunk is as long as the block, or the rest of the blob if that is shorter
+        assert_eq!(read_into(&mut plugin, fd, 4096, 100).unwrap(), &contents[4096..4196]);
+        assert_eq!(read_into(&mut plugin, fd, 9_990, 100).unwrap(), &contents[9_990..]);
+        assert!(read_into(&mut plugin, fd, 10_000, 100).unwrap().is_empty());
+        // unknown blobs fail the read
+        assert!(read_into(&mut plugin, fd + 1, 0, 100).is_err());
+    }
+}
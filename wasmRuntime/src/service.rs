@@ -0,0 +1,777 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read, Write};
+use std::time::Duration;
+use faasten_core::fs::{self, DirEntry};
+use faasten_runtime_interface::ServiceInvokeResult;
+use labeled::{buckle::{Buckle, Component}, HasPrivilege};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use crate::blobs::BlobReader;
+use crate::config::{egress_allowed, EgressRule, RetryPolicy, ServiceConfig};
+use crate::fixtures::Fixtures;
+use crate::{current_label, set_label, SyscallProcessor, SyscallProcessorError};
+
+/*
+    Prefix of header values that refer to a secret stored in a File object, e.g
+    "faasten-secret:/home/alice/api_key". The runtime fills in the file's contents when the
+    request is sent.
+ */
+pub const SECRET_HEADER_PREFIX: &str = "faasten-secret:";
+
+/*
+    Name of the service header holding the template of the request body, e.g
+    {{"name": "{name}"}}. The header itself isn't sent. Parameters are escaped according
+    to the service's Content-Type, and the payload is available as the payload parameter.
+ */
+pub const BODY_TEMPLATE_HEADER: &str = "faasten-body-template";
+
+/*
+    Name of the service header listing the headers whose values are templates, separated by
+    commas, e.g "authorization, x-scope". The header itself isn't sent. Other header values are
+    sent as they are, so they may hold literal braces.
+ */
+pub const TEMPLATED_HEADERS_HEADER: &str = "faasten-templated-headers";
+
+/*
+    Name of the service header that makes responses with a non-2xx status count as failed invocations
+    when its value is "true". The header itself isn't sent. Failed invocations still return the response.
+ */
+pub const FAIL_ON_ERROR_STATUS_HEADER: &str = "faasten-fail-on-error-status";
+
+// service headers that configure the request instead of being sent
+const RESERVED_HEADERS: [&str; 3] = [BODY_TEMPLATE_HEADER, TEMPLATED_HEADERS_HEADER, FAIL_ON_ERROR_STATUS_HEADER];
+
+// characters of url parameters that are percent-encoded. Everything but the unreserved characters
+const URL_PARAMETER: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+/* Metadata of a service's response, handed to the function along with the invocation's result */
+pub(crate) struct ResponseMeta {
+    pub status: u16,
+    // final url, after following redirects
+    pub url: String,
+    pub content_length: Option<u64>,
+    pub headers: HashMap<String, Vec<u8>>,
+}
+
+impl ResponseMeta {
+    pub fn new(response: &reqwest::blocking::Response) -> Self {
+        // convert response headers from a HeaderMap to a Map of String to bytes
+        let headers = response.headers().iter()
+            .map(|(a, b)| (a.to_string(), Vec::from(b.as_bytes())))
+        .collect();
+
+        Self {
+            status: response.status().as_u16(),
+            url: response.url().to_string(),
+            content_length: response.content_length(),
+            headers,
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    // the result of an invocation that received this response
+    pub fn into_result(self, success: bool, fd: Option<u64>, data: Option<Vec<u8>>) -> ServiceInvokeResult {
+        ServiceInvokeResult {
+            success,
+            fd,
+            data,
+            headers: self.headers,
+            status: Some(self.status.into()),
+            url: Some(self.url),
+            content_length: self.content_length,
+        }
+    }
+}
+
+// whether responses of the service with a non-2xx status count as failed invocations
+pub(crate) fn fails_on_error_status(service: &fs::Service) -> bool {
+    service.headers.get(FAIL_ON_ERROR_STATUS_HEADER).is_some_and(|value| value.trim().eq_ignore_ascii_case("true"))
+}
+
+/*
+    Body of a request to a service. Bodies from a blob are streamed from the blobstore
+    instead of being read into memory, and so are body templates filled with a blob.
+ */
+pub(crate) enum RequestBody {
+    Bytes(Vec<u8>),
+    Blob(BlobReader),
+    // a body of unknown length, sent chunked
+    Stream(Box<dyn Read + Send>),
+}
+
+// how parameters filled into a body template are escaped, depending on the body's content type
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BodyEscape {
+    // JSON string escapes, for application/json and +json media types
+    Json,
+    // form encoding, for application/x-www-form-urlencoded
+    Form,
+    None,
+}
+
+/*
+    How a response body is delivered to the function. Bodies are returned as data, unless the
+    function asked for a blob or the body is over the service's max_response_bytes.
+ */
+pub(crate) enum ResponseBody {
+    Data(Vec<u8>),
+    Blob(u64),
+}
+
+/*
+    HTTP requests to services.
+ */
+impl SyscallProcessor {
+    pub(crate) fn http_send(
+        &self,
+        service: &fs::Service, 
+        body: Option<RequestBody>, 
+        parameters: HashMap<String, String>,
+        timeout: Option<Duration>,
+        settings: &ServiceConfig
+    ) -> Result<reqwest::blocking::Response, SyscallProcessorError> {
+        let request = self.http_request(service, body, parameters, timeout, settings)?;
+        let policy = settings.retry_policy(&service.verb.clone().into(), timeout);
+        send_with_retries(request, policy, settings.max_response_bytes, self.env.fixtures.as_ref())
+    }
+
+    /*
+        Builds the http request to a service without sending it. The request times out after the
+        service's timeout or the given timeout, whichever is shorter.
+     */
+    pub(crate) fn http_request(
+        &self,
+        service: &fs::Service, 
+        body: Option<RequestBody>, 
+        parameters: HashMap<String, String>,
+        timeout: Option<Duration>,
+        settings: &ServiceConfig
+    ) -> Result<reqwest::blocking::RequestBuilder, SyscallProcessorError> {
+        // parameters are percent-encoded, so they can't change the url's structure (e.g its host)
+        let url_parameters: HashMap<String, String> = parameters.iter()
+            .map(|(name, value)| (name.clone(), utf8_percent_encode(value, URL_PARAMETER).to_string()))
+            .collect();
+        let url = strfmt::strfmt(&service.url, &url_parameters)
+            .map_err(|_| SyscallProcessorError::BadUrlArgs)?;
+        let url = reqwest::Url::parse(&url).map_err(|_| SyscallProcessorError::BadUrlArgs)?;
+
+        // the final url is checked against the egress policy, at the label the request is sent at
+        let label = current_label();
+        if !self.env.config.egress_allowed(&url, &label) {
+            log::info!("Egress to {} denied", url);
+            return Err(SyscallProcessorError::EgressDenied);
+        }
+
+        let method = service.verb.clone().into();
+        let templated: Vec<String> = service.headers.get(TEMPLATED_HEADERS_HEADER)
+            .map(|names| names.split(',').map(|name| name.trim().to_ascii_lowercase()).collect())
+            .unwrap_or_default();
+        let mut headers = reqwest::header::HeaderMap::new();
+        for (name, value) in service.headers.iter().filter(|(name, _)| !RESERVED_HEADERS.contains(&name.as_str())) {
+            let Ok(name) = reqwest::header::HeaderName::from_bytes(name.as_bytes()) else {
+                log::info!("Invalid service header name {:?}", name);
+                return Err(SyscallProcessorError::BadHeaderArgs);
+            };
+            let value = match value.strip_prefix(SECRET_HEADER_PREFIX) {
+                Some(path) => {
+                    let secret = self.read_secret(path, &label, &service.privilege)?;
+                    let mut value = reqwest::header::HeaderValue::from_bytes(&secret)
+                        .map_err(|_| SyscallProcessorError::SecretUnavailable)?;
+                    value.set_sensitive(true);
+                    value
+                }
+                // parameters can't add headers, since header values can't hold line breaks
+                None if templated.iter().any(|templated| templated == name.as_str()) => strfmt::strfmt(value, &parameters)
+                    .ok()
+                    .and_then(|value| reqwest::header::HeaderValue::from_str(&value).ok())
+                    .ok_or(SyscallProcessorError::BadHeaderArgs)?,
+                None => reqwest::header::HeaderValue::from_str(value).map_err(|_| SyscallProcessorError::BadHeaderArgs)?
+            };
+            headers.insert(name, value);
+        }
+
+        let body = match service.headers.get(BODY_TEMPLATE_HEADER) {
+            Some(template) => {
+                let content_type = service.headers.iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+                    .map(|(_, value)| value.as_str());
+                Some(render_body(template, content_type, parameters, body)?)
+            }
+            None => body
+        };
+
+        // connect timeouts are set per client, one client per configured connect timeout
+        let client = settings.connect_timeout_ms
+            .and_then(|connect_timeout| self.http_clients.get(&connect_timeout))
+            .unwrap_or(&self.http_client);
+
+        let mut request = client.request(method, url).headers(headers);
+        match body {
+            Some(RequestBody::Bytes(body)) => request = request.body(body),
+            Some(RequestBody::Blob(reader)) => {
+                let len = reader.len();
+                request = request.body(reqwest::blocking::Body::sized(reader, len));
+            }
+            Some(RequestBody::Stream(reader)) => request = request.body(reqwest::blocking::Body::new(reader)),
+            None => {}
+        }
+        if let Some(timeout) = settings.timeout(timeout) {
+            request = request.timeout(timeout);
+        }
+
+        Ok(request)
+    }
+
+    /*
+        Reads a secret header value from the File object at path (e.g /home/alice/api_key). The
+        secret, and every directory on its path, must be able to flow to label (the label the request
+        is sent at) with the service's privilege. Reading the secret doesn't raise the current label,
+        since the function never sees it.
+     */
+    fn read_secret(&self, path: &str, label: &Buckle, privilege: &Component) -> Result<Vec<u8>, SyscallProcessorError> {
+        // reading the directories and the file raises the current label to the secret's label
+        let mut entry = Some(DirEntry::Directory(fs::ROOT_REF));
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            entry = match entry {
+                Some(DirEntry::Directory(dir_objref)) => dir_objref.list(&self.env.fs).get(name).cloned(),
+                _ => None
+            };
+        }
+        let secret = match &entry {
+            Some(DirEntry::File(file_objref)) => Some(file_objref.read(&self.env.fs)),
+            _ => None
+        };
+
+        let secret_label = current_label();
+        set_label(label.clone());
+
+        match secret {
+            Some(secret) if secret_label.can_flow_to_with_privilege(label, privilege) => Ok(secret),
+            Some(_) => {
+                log::info!("Secret {} denied. Service lacks the privilege to send it", path);
+                Err(SyscallProcessorError::SecretUnavailable)
+            }
+            None => {
+                log::info!("Secret {} not found", path);
+                Err(SyscallProcessorError::SecretUnavailable)
+            }
+        }
+    }
+
+    /*
+        Delivers a response body to the function. body holds what was read of the response
+        already and rest the remainder, if any. Bodies with a remainder are written to a blob if
+        the function asked for one or the service allows it, and fail the invocation otherwise.
+
+        Returns the delivered body and its length.
+     */
+    pub(crate) fn deliver_response(
+        &mut self,
+        body: Vec<u8>,
+        rest: Option<reqwest::blocking::Response>,
+        toblob: bool,
+        settings: &ServiceConfig
+    ) -> Result<(ResponseBody, u64), SyscallProcessorError> {
+        let len = body.len() as u64;
+        match rest {
+            None if !toblob => return Ok((ResponseBody::Data(body), len)),
+            Some(_) if !toblob && !settings.oversized_to_blob() => return Err(SyscallProcessorError::ResponseTooLarge),
+            _ => {}
+        }
+
+        let mut newblob = self.env.blobstore.create().map_err(SyscallProcessorError::Blob)?;
+        newblob.write_all(&body).map_err(SyscallProcessorError::Blob)?;
+        let rest_len = match rest {
+            Some(mut rest) => io::copy(&mut rest, &mut newblob).map_err(SyscallProcessorError::Blob)?,
+            None => 0
+        };
+
+        let blob = self.env.blobstore.save(newblob).map_err(SyscallProcessorError::Blob)?;
+        let blobfd = self.max_blob_id;
+        self.max_blob_id += 1;
+        self.blobs.insert(blobfd, blob);
+
+        Ok((ResponseBody::Blob(blobfd), len + rest_len))
+    }
+}
+
+// longest wait between two attempts of a request
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(30);
+
+// redirects followed at most, same as reqwest's default policy
+const MAX_REDIRECTS: usize = 10;
+
+// error of a request redirected to a url the egress policy doesn't allow
+#[derive(Debug)]
+pub(crate) struct RedirectDenied(pub String);
+
+impl std::fmt::Display for RedirectDenied {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "redirect to {} denied by the egress policy", self.0)
+    }
+}
+
+impl std::error::Error for RedirectDenied {}
+
+/*
+    Builds a client that checks every redirect against the egress rules, since only the service's
+    own url is checked before sending. The policy can't tell which request is redirected, so hops are
+    checked at the top label: they must be allowed at every label, i.e by rules without a label.
+ */
+pub(crate) fn http_client(
+    egress: Option<Vec<EgressRule>>,
+    connect_timeout: Option<Duration>
+) -> reqwest::Result<reqwest::blocking::Client> {
+    let redirect = reqwest::redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            attempt.error("too many redirects")
+        } else if !egress_allowed(egress.as_deref(), attempt.url(), &Buckle::top()) {
+            log::info!("Egress to {} denied", attempt.url());
+            let url = attempt.url().to_string();
+            attempt.error(RedirectDenied(url))
+        } else {
+            attempt.follow()
+        }
+    });
+
+    let mut builder = reqwest::blocking::Client::builder().redirect(redirect);
+    if let Some(connect_timeout) = connect_timeout {
+        builder = builder.connect_timeout(connect_timeout);
+    }
+    builder.build()
+}
+
+/*
+    Sends a request, retrying failed attempts up to policy.retries times. Attempts that fail to
+    connect, time out, or get a 5xx status are retried after a backoff that doubles every time, up to
+    MAX_RETRY_BACKOFF. Attempts are cut short at the policy's deadline, and the last failed attempt's
+    result is returned if the deadline would pass during the backoff.
+    Responses recorded to fixtures are read whole, so they are capped at max_response_bytes.
+ */
+pub(crate) fn send_with_retries(
+    request: reqwest::blocking::RequestBuilder,
+    policy: RetryPolicy,
+    max_response_bytes: Option<u64>,
+    fixtures: Option<&Fixtures>
+) -> Result<reqwest::blocking::Response, SyscallProcessorError> {
+    match fixtures {
+        Some(fixtures) => fixtures.send(request, policy, max_response_bytes),
+        None => send_over_network(request, policy)
+    }
+}
+
+pub(crate) fn send_over_network(
+    request: reqwest::blocking::RequestBuilder,
+    policy: RetryPolicy
+) -> Result<reqwest::blocking::Response, SyscallProcessorError> {
+    let mut backoff = policy.backoff.min(MAX_RETRY_BACKOFF);
+
+    for _ in 0..policy.retries {
+        // requests with a streamed body can't be cloned, so they are only sent once
+        let Some(attempt) = request.try_clone() else {
+            break;
+        };
+
+        let result = send_attempt(attempt, &policy);
+        let retry = match &result {
+            Ok(response) => response.status().is_server_error(),
+            Err(e) => e.is_connect() || e.is_timeout()
+        };
+        if !retry || policy.remaining().is_some_and(|remaining| remaining <= backoff) {
+            return result.map_err(SyscallProcessorError::from_http);
+        }
+        std::thread::sleep(backoff);
+        backoff = next_backoff(backoff);
+    }
+
+    send_attempt(request, &policy).map_err(SyscallProcessorError::from_http)
+}
+
+fn send_attempt(request: reqwest::blocking::RequestBuilder, policy: &RetryPolicy) -> reqwest::Result<reqwest::blocking::Response> {
+    match policy.attempt_timeout() {
+        Some(timeout) => request.timeout(timeout).send(),
+        None => request.send()
+    }
+}
+
+fn next_backoff(backoff: Duration) -> Duration {
+    backoff.saturating_mul(2).min(MAX_RETRY_BACKOFF)
+}
+
+/*
+    Reads a response body, at most max bytes of it. Returns the body and, if the body is longer,
+    the response to read the rest from.
+ */
+pub(crate) fn read_capped(
+    mut response: reqwest::blocking::Response,
+    max: Option<u64>
+) -> Result<(Vec<u8>, Option<reqwest::blocking::Response>), SyscallProcessorError> {
+    let mut body = Vec::new();
+
+    let Some(max) = max else {
+        response.read_to_end(&mut body).map_err(SyscallProcessorError::Blob)?;
+        return Ok((body, None));
+    };
+
+    // reading one byte past max tells whether there is more
+    (&mut response).take(max + 1).read_to_end(&mut body).map_err(SyscallProcessorError::Blob)?;
+    if body.len() as u64 > max {
+        Ok((body, Some(response)))
+    } else {
+        Ok((body, None))
+    }
+}
+
+/*
+    Fills the parameters into a body template. Parameters are escaped for the body's content type:
+    JSON string escapes for JSON bodies, form encoding for form bodies, and none for other bodies.
+    A payload from a blob (or other stream) is streamed into the body instead of being read into
+    memory, so it can only be filled in once, and it isn't checked to be UTF-8.
+ */
+fn render_body(
+    template: &str,
+    content_type: Option<&str>,
+    parameters: HashMap<String, String>,
+    payload: Option<RequestBody>
+) -> Result<RequestBody, SyscallProcessorError> {
+    let escape = BodyEscape::of(content_type);
+    let mut parameters: HashMap<String, String> = parameters.into_iter()
+        .map(|(name, value)| (name, escape.escape_str(&value)))
+        .collect();
+
+    let stream: Box<dyn Read + Send> = match payload {
+        Some(RequestBody::Bytes(payload)) => {
+            let payload = String::from_utf8(payload).map_err(|_| SyscallProcessorError::BadBodyArgs)?;
+            parameters.insert(String::from("payload"), escape.escape_str(&payload));
+            return render_template(template, &parameters).map(|body| RequestBody::Bytes(body.into_bytes()));
+        }
+        Some(RequestBody::Blob(reader)) => Box::new(reader),
+        Some(RequestBody::Stream(reader)) => reader,
+        None => return render_template(template, &parameters).map(|body| RequestBody::Bytes(body.into_bytes()))
+    };
+
+    // the template is rendered around a marker standing in for the payload, which is streamed in its place
+    let marker = format!("faasten-payload-{:016x}", RandomState::new().build_hasher().finish());
+    parameters.insert(String::from("payload"), marker.clone());
+    let body = render_template(template, &parameters)?;
+
+    let Some((before, after)) = body.split_once(&marker) else {
+        return Ok(RequestBody::Bytes(body.into_bytes()));
+    };
+    if after.contains(&marker) {
+        log::info!("Body template fills in a streamed payload more than once");
+        return Err(SyscallProcessorError::BadBodyArgs);
+    }
+
+    let body = io::Cursor::new(before.as_bytes().to_vec())
+        .chain(EscapingReader { inner: stream, escape, escaped: Vec::new(), position: 0 })
+        .chain(io::Cursor::new(after.as_bytes().to_vec()));
+    Ok(RequestBody::Stream(Box::new(body)))
+}
+
+fn render_template(template: &str, parameters: &HashMap<String, String>) -> Result<String, SyscallProcessorError> {
+    strfmt::strfmt(template, parameters).map_err(|_| SyscallProcessorError::BadBodyArgs)
+}
+
+impl BodyEscape {
+    // matches the media type only, ignoring parameters such as charset
+    fn of(content_type: Option<&str>) -> Self {
+        let media_type = content_type
+            .and_then(|content_type| content_type.split(';').next())
+            .map(|media_type| media_type.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+
+        if media_type == "application/json" || (media_type.starts_with("application/") && media_type.ends_with("+json")) {
+            Self::Json
+        } else if media_type == "application/x-www-form-urlencoded" {
+            Self::Form
+        } else {
+            Self::None
+        }
+    }
+
+    fn escape_str(self, value: &str) -> String {
+        match self {
+            // a JSON string without its quotes
+            Self::Json => {
+                let quoted = serde_json::to_string(value).unwrap();
+                quoted[1..quoted.len() - 1].to_string()
+            }
+            Self::Form => form_urlencoded::byte_serialize(value.as_bytes()).collect(),
+            Self::None => value.to_string()
+        }
+    }
+
+    // escapes one byte of a streamed value. Both escapes only change ASCII bytes, so they can work byte by byte
+    fn escape_byte(self, byte: u8, out: &mut Vec<u8>) {
+        match self {
+            Self::Json => match byte {
+                b'"' => out.extend_from_slice(b"\\\""),
+                b'\\' => out.extend_from_slice(b"\\\\"),
+                // the short escapes serde_json uses for strings, so both escapes agree
+                b'\n' => out.extend_from_slice(b"\\n"),
+                b'\r' => out.extend_from_slice(b"\\r"),
+                b'\t' => out.extend_from_slice(b"\\t"),
+                0x08 => out.extend_from_slice(b"\\b"),
+                0x0c => out.extend_from_slice(b"\\f"),
+                0..=0x1f => out.extend_from_slice(format!("\\u{:04x}", byte).as_bytes()),
+                _ => out.push(byte)
+            },
+            Self::Form => out.extend(form_urlencoded::byte_serialize(&[byte]).flat_map(str::bytes)),
+            Self::None => out.push(byte)
+        }
+    }
+}
+
+// escapes a stream as it is read
+struct EscapingReader {
+    inner: Box<dyn Read + Send>,
+    escape: BodyEscape,
+    // escaped bytes not returned yet, from position on
+    escaped: Vec<u8>,
+    position: usize,
+}
+
+impl Read for EscapingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.escaped.len() {
+            let mut chunk = vec![0; buf.len().max(1)];
+            let num_bytes_read = self.inner.read(&mut chunk)?;
+            self.escaped.clear();
+            self.position = 0;
+            for byte in &chunk[..num_bytes_read] {
+                self.escape.escape_byte(*byte, &mut self.escaped);
+            }
+        }
+
+        let len = buf.len().min(self.escaped.len() - self.position);
+        buf[..len].copy_from_slice(&self.escaped[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /*
+        Serves a redirect to localhost for requests to 127.0.0.1, and "ok" for requests to localhost.
+        Returns the port it listens on.
+     */
+    fn redirecting_server() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let mut request = Vec::new();
+                let mut byte = [0; 1];
+                while !request.ends_with(b"\r\n\r\n") && stream.read(&mut byte).map_or(false, |n| n == 1) {
+                    request.push(byte[0]);
+                }
+
+                let response = if String::from_utf8_lossy(&request).contains("host: 127.0.0.1") {
+                    format!("HTTP/1.1 302 Found\r\nlocation: http://localhost:{}/\r\ncontent-length: 0\r\nconnection: close\r\n\r\n", port)
+                } else {
+                    String::from("HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok")
+                };
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+        port
+    }
+
+    // answers every request with a 503 status, returns its url and the number of requests it received
+    fn unavailable_server() -> (String, std::sync::Arc<std::sync::atomic::AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://127.0.0.1:{}/", listener.local_addr().unwrap().port());
+        let requests = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+        let received = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let mut request = Vec::new();
+                let mut byte = [0; 1];
+                while !request.ends_with(b"\r\n\r\n") && stream.read(&mut byte).map_or(false, |n| n == 1) {
+                    request.push(byte[0]);
+                }
+                received.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let _ = stream.write_all(b"HTTP/1.1 503 Service Unavailable\r\ncontent-length: 0\r\nconnection: close\r\n\r\n");
+            }
+        });
+        (url, requests)
+    }
+
+    fn retrying(retries: u32, backoff_ms: u64, invoke_timeout_ms: Option<u64>) -> RetryPolicy {
+        let settings = ServiceConfig { retries: Some(retries), retry_backoff_ms: Some(backoff_ms), ..Default::default() };
+        settings.retry_policy(&reqwest::Method::GET, invoke_timeout_ms.map(Duration::from_millis))
+    }
+
+    #[test]
+    fn retries_stop_at_the_deadline() {
+        let (url, requests) = unavailable_server();
+        let client = http_client(None, None).unwrap();
+
+        // without the deadline, the backoffs alone would take over 3 seconds
+        let start = std::time::Instant::now();
+        let response = send_over_network(client.get(&url), retrying(5, 200, Some(700))).unwrap();
+        assert_eq!(response.status(), 503);
+        assert!(start.elapsed() < Duration::from_millis(1500));
+        let sent = requests.load(std::sync::atomic::Ordering::SeqCst);
+        assert!((2..=3).contains(&sent), "{} attempts", sent);
+    }
+
+    #[test]
+    fn retries_without_deadline_use_all_attempts() {
+        let (url, requests) = unavailable_server();
+        let client = http_client(None, None).unwrap();
+
+        let response = send_over_network(client.get(&url), retrying(2, 10, None)).unwrap();
+        assert_eq!(response.status(), 503);
+        assert_eq!(requests.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        assert_eq!(next_backoff(Duration::from_millis(100)), Duration::from_millis(200));
+        assert_eq!(next_backoff(Duration::from_secs(20)), MAX_RETRY_BACKOFF);
+        assert_eq!(next_backoff(Duration::MAX), MAX_RETRY_BACKOFF);
+    }
+
+    #[test]
+    fn attempts_are_cut_short_at_the_deadline() {
+        let policy = retrying(0, 100, Some(50));
+        assert!(policy.attempt_timeout().unwrap() <= Duration::from_millis(50));
+
+        let settings = ServiceConfig { timeout_ms: Some(10), ..Default::default() };
+        let policy = settings.retry_policy(&reqwest::Method::GET, Some(Duration::from_secs(60)));
+        assert_eq!(policy.attempt_timeout(), Some(Duration::from_millis(10)));
+        assert_eq!(settings.retry_policy(&reqwest::Method::GET, None).attempt_timeout(), Some(Duration::from_millis(10)));
+    }
+
+    fn rule(hosts: &[&str]) -> EgressRule {
+        EgressRule {
+            schemes: vec![String::from("http")],
+            hosts: hosts.iter().map(|host| host.to_string()).collect(),
+            ports: Vec::new(),
+            label: None,
+        }
+    }
+
+    #[test]
+    fn redirects_are_checked_against_egress_rules() {
+        let port = redirecting_server();
+        let url = format!("http://127.0.0.1:{}/", port);
+
+        let client = http_client(Some(vec![rule(&["127.0.0.1"])]), None).unwrap();
+        let denied = client.get(&url).send().map_err(SyscallProcessorError::from_http);
+        assert!(matches!(denied, Err(SyscallProcessorError::EgressDenied)));
+
+        let client = http_client(Some(vec![rule(&["127.0.0.1", "localhost"])]), None).unwrap();
+        let response = client.get(&url).send().unwrap();
+        assert_eq!(response.url().host_str(), Some("localhost"));
+        assert_eq!(response.text().unwrap(), "ok");
+    }
+
+    #[test]
+    fn labeled_rules_deny_redirects() {
+        let port = redirecting_server();
+        let labeled = EgressRule { label: Some(String::from("alice,true")), ..rule(&["localhost"]) };
+
+        let client = http_client(Some(vec![rule(&["127.0.0.1"]), labeled]), None).unwrap();
+        let denied = client.get(format!("http://127.0.0.1:{}/", port)).send().map_err(SyscallProcessorError::from_http);
+        assert!(matches!(denied, Err(SyscallProcessorError::EgressDenied)));
+    }
+
+    #[test]
+    fn invalid_header_names_fail_the_request() {
+        let (_dir, processor) = SyscallProcessor::for_test(Buckle::public(), Component::dc_true());
+        let service = fs::Service {
+            taint: Buckle::public(),
+            privilege: Component::dc_true(),
+            invoker_integrity_clearance: Component::dc_true(),
+            url: "https://example.com/".to_string(),
+            verb: fs::HttpVerb::GET,
+            headers: [("bad name".to_string(), "value".to_string())].into_iter().collect()
+        };
+
+        let request = processor.http_request(&service, None, HashMap::new(), None, &ServiceConfig::default());
+        assert!(matches!(request, Err(SyscallProcessorError::BadHeaderArgs)));
+    }
+
+    fn rendered(body: RequestBody) -> String {
+        let bytes = match body {
+            RequestBody::Bytes(bytes) => bytes,
+            RequestBody::Stream(mut reader) => {
+                let mut bytes = Vec::new();
+                reader.read_to_end(&mut bytes).unwrap();
+                bytes
+            }
+            RequestBody::Blob(_) => panic!("body templates are never sent as a blob")
+        };
+        String::from_utf8(bytes).unwrap()
+    }
+
+    fn stream(payload: &str) -> Option<RequestBody> {
+        Some(RequestBody::Stream(Box::new(io::Cursor::new(payload.as_bytes().to_vec()))))
+    }
+
+    #[test]
+    fn body_escape_matches_media_types() {
+        assert_eq!(BodyEscape::of(Some("application/json")), BodyEscape::Json);
+        assert_eq!(BodyEscape::of(Some("Application/JSON; charset=utf-8")), BodyEscape::Json);
+        assert_eq!(BodyEscape::of(Some("application/vnd.api+json")), BodyEscape::Json);
+        assert_eq!(BodyEscape::of(Some("application/x-www-form-urlencoded")), BodyEscape::Form);
+        // only the media type counts, not json anywhere in the value
+        assert_eq!(BodyEscape::of(Some("text/plain; profile=json")), BodyEscape::None);
+        assert_eq!(BodyEscape::of(Some("application/jsonl")), BodyEscape::None);
+        assert_eq!(BodyEscape::of(None), BodyEscape::None);
+    }
+
+    #[test]
+    fn parameters_are_escaped_for_the_content_type() {
+        let parameters = HashMap::from([(String::from("name"), String::from("a\"b &c"))]);
+
+        let json = render_body(r#"{{"name": "{name}"}}"#, Some("application/json"), parameters.clone(), None).unwrap();
+        assert_eq!(rendered(json), r#"{"name": "a\"b &c"}"#);
+        let form = render_body("name={name}", Some("application/x-www-form-urlencoded"), parameters.clone(), None).unwrap();
+        assert_eq!(rendered(form), "name=a%22b+%26c");
+        let text = render_body("{name}", Some("text/plain"), parameters, None).unwrap();
+        assert_eq!(rendered(text), "a\"b &c");
+    }
+
+    #[test]
+    fn streamed_payloads_are_escaped_like_parameters() {
+        let payload = "line\nquote\ttab\u{1}\" back\\ é &=";
+
+        for content_type in ["application/json", "application/x-www-form-urlencoded", "text/plain"] {
+            let bytes = render_body("<{payload}>", Some(content_type), HashMap::new(), Some(RequestBody::Bytes(payload.into()))).unwrap();
+            let streamed = render_body("<{payload}>", Some(content_type), HashMap::new(), stream(payload)).unwrap();
+            assert!(matches!(streamed, RequestBody::Stream(_)));
+            assert_eq!(rendered(streamed), rendered(bytes));
+        }
+    }
+
+    #[test]
+    fn streamed_payloads_are_filled_in_once() {
+        let twice = render_body("{payload}{payload}", None, HashMap::new(), stream("a"));
+        assert!(matches!(twice, Err(SyscallProcessorError::BadBodyArgs)));
+
+        // templates that don't use the payload don't read it
+        let unused = render_body("{{}}", None, HashMap::new(), stream("a")).unwrap();
+        assert_eq!(rendered(unused), "{}");
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use faasten_core::fs::BackingStore;
+use lmdb::{Transaction as _, WriteFlags};
+
+/*
+    Writes buffered by an open transaction, keyed by backing store key.
+    A value of None marks a deleted key.
+ */
+type WriteSet = BTreeMap<Vec<u8>, Option<Vec<u8>>>;
+
+/*
+    An open transaction. reads holds the value LMDB had for every key an add or cas of the
+    transaction compared against, when it first compared against it. The transaction only
+    commits if all of them still hold.
+ */
+#[derive(Default)]
+struct Pending {
+    writes: WriteSet,
+    reads: WriteSet,
+}
+
+impl Pending {
+    // the value the transaction sees for key, remembering it if it was read from LMDB
+    fn observe(&mut self, dbenv: &lmdb::Environment, key: &[u8]) -> Option<Vec<u8>> {
+        if let Some(value) = self.writes.get(key).or_else(|| self.reads.get(key)) {
+            return value.clone();
+        }
+        let value = dbenv.get(key);
+        self.reads.insert(key.to_vec(), value.clone());
+        value
+    }
+}
+
+#[derive(Debug)]
+pub enum TxnError {
+    AlreadyOpen,
+    NotOpen,
+    // a key an add or cas compared against changed before the transaction committed
+    Conflict,
+    Lmdb(lmdb::Error),
+}
+
+/*
+    A backing store wrapping the LMDB environment which can group writes into a transaction.
+
+    Outside a transaction, reads and writes go straight to LMDB. Inside a transaction, writes
+    are buffered in memory and reads see the buffered writes first. The buffered writes only
+    reach LMDB when the transaction is committed, all in one LMDB write transaction. add and cas
+    compare against LMDB as of the first comparison, and the commit fails if LMDB changed since.
+ */
+pub struct TxnStore {
+    dbenv: &'static lmdb::Environment,
+    pending: Arc<Mutex<Option<Pending>>>,
+}
+
+/*
+    Controls the transactions of a TxnStore. The store itself is owned by the FS object, so
+    the syscall processor begins, commits and aborts transactions through this handle.
+ */
+#[derive(Clone)]
+pub struct TxnHandle {
+    dbenv: &'static lmdb::Environment,
+    pending: Arc<Mutex<Option<Pending>>>,
+}
+
+impl TxnStore {
+    pub fn new(dbenv: &'static lmdb::Environment) -> Self {
+        Self { dbenv, pending: Default::default() }
+    }
+
+    pub fn handle(&self) -> TxnHandle {
+        TxnHandle { dbenv: self.dbenv, pending: self.pending.clone() }
+    }
+}
+
+impl BackingStore for TxnStore {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        if let Some(pending) = self.pending.lock().unwrap().as_ref() {
+            if let Some(value) = pending.writes.get(key) {
+                return value.clone();
+            }
+        }
+        self.dbenv.get(key)
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) {
+        match self.pending.lock().unwrap().as_mut() {
+            Some(pending) => { pending.writes.insert(key.to_vec(), Some(value.to_vec())); }
+            None => self.dbenv.put(key, value)
+        }
+    }
+
+    fn add(&self, key: &[u8], value: &[u8]) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+        match pending.as_mut() {
+            Some(pending) => {
+                let exists = pending.observe(self.dbenv, key).is_some();
+                if !exists {
+                    pending.writes.insert(key.to_vec(), Some(value.to_vec()));
+                }
+                !exists
+            }
+            None => self.dbenv.add(key, value)
+        }
+    }
+
+    fn cas(&self, key: &[u8], expected: Option<&[u8]>, value: &[u8]) -> Result<(), Option<Vec<u8>>> {
+        let mut pending = self.pending.lock().unwrap();
+        match pending.as_mut() {
+            Some(pending) => {
+                let current = pending.observe(self.dbenv, key);
+                if current.as_deref() == expected {
+                    pending.writes.insert(key.to_vec(), Some(value.to_vec()));
+                    Ok(())
+                } else {
+                    Err(current)
+                }
+            }
+            None => self.dbenv.cas(key, expected, value)
+        }
+    }
+
+    fn del(&self, key: &[u8]) {
+        match self.pending.lock().unwrap().as_mut() {
+            Some(pending) => { pending.writes.insert(key.to_vec(), None); }
+            None => self.dbenv.del(key)
+        }
+    }
+}
+
+impl TxnHandle {
+    #[cfg(test)]
+    pub fn is_open(&self) -> bool {
+        self.pending.lock().unwrap().is_some()
+    }
+
+    /*
+        Starts buffering writes. Transactions don't nest, so fails if one is already open.
+     */
+    pub fn begin(&self) -> Result<(), TxnError> {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.is_some() {
+            return Err(TxnError::AlreadyOpen);
+        }
+        *pending = Some(Default::default());
+        Ok(())
+    }
+
+    /*
+        Writes all buffered writes to LMDB in a single write transaction, so either all
+        of them are stored or none are. Fails with Conflict, storing nothing, if a key that
+        an add or cas of the transaction compared against changed since. The transaction
+        is closed either way.
+     */
+    pub fn commit(&self) -> Result<(), TxnError> {
+        let Some(Pending { writes, reads }) = self.pending.lock().unwrap().take() else {
+            return Err(TxnError::NotOpen);
+        };
+
+        let db = self.dbenv.open_db(None).map_err(TxnError::Lmdb)?;
+        let mut txn = self.dbenv.begin_rw_txn().map_err(TxnError::Lmdb)?;
+
+        // the write transaction excludes other writers, so the values checked here can't change before it commits
+        for (key, expected) in reads {
+            let current = match txn.get(db, &key) {
+                Ok(value) => Some(value),
+                Err(lmdb::Error::NotFound) => None,
+                Err(e) => return Err(TxnError::Lmdb(e))
+            };
+            if current != expected.as_deref() {
+                return Err(TxnError::Conflict);
+            }
+        }
+
+        for (key, value) in writes {
+            match value {
+                Some(value) => txn.put(db, &key, &value, WriteFlags::empty()).map_err(TxnError::Lmdb)?,
+                None => match txn.del(db, &key, None) {
+                    Ok(()) | Err(lmdb::Error::NotFound) => {}
+                    Err(e) => return Err(TxnError::Lmdb(e))
+                }
+            }
+        }
+        txn.commit().map_err(TxnError::Lmdb)
+    }
+
+    /*
+        Discards all buffered writes and closes the transaction.
+     */
+    pub fn abort(&self) -> Result<(), TxnError> {
+        match self.pending.lock().unwrap().take() {
+            Some(_) => Ok(()),
+            None => Err(TxnError::NotOpen)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> (tempfile::TempDir, &'static lmdb::Environment, TxnStore) {
+        let dir = tempfile::tempdir().unwrap();
+        let dbenv = Box::leak(Box::new(lmdb::Environment::new().open(dir.path()).unwrap()));
+        (dir, dbenv, TxnStore::new(dbenv))
+    }
+
+    #[test]
+    fn commit_stores_buffered_writes() {
+        let (_dir, dbenv, store) = store();
+        let txn = store.handle();
+
+        txn.begin().unwrap();
+        store.put(b"a", b"1");
+        assert!(store.add(b"b", b"2"));
+        assert_eq!(store.cas(b"a", Some(&b"1"[..]), b"3"), Ok(()));
+        assert_eq!(dbenv.get(b"a"), None);
+
+        txn.commit().unwrap();
+        assert_eq!(dbenv.get(b"a"), Some(b"3".to_vec()));
+        assert_eq!(dbenv.get(b"b"), Some(b"2".to_vec()));
+    }
+
+    #[test]
+    fn commit_fails_if_cas_key_changed() {
+        let (_dir, dbenv, store) = store();
+        let txn = store.handle();
+        dbenv.put(b"a", b"1");
+
+        txn.begin().unwrap();
+        assert_eq!(store.cas(b"a", Some(&b"1"[..]), b"2"), Ok(()));
+        store.put(b"b", b"2");
+        // another function writes the key in the meantime
+        dbenv.put(b"a", b"other");
+
+        assert!(matches!(txn.commit(), Err(TxnError::Conflict)));
+        assert!(!txn.is_open());
+        assert_eq!(dbenv.get(b"a"), Some(b"other".to_vec()));
+        assert_eq!(dbenv.get(b"b"), None);
+    }
+
+    #[test]
+    fn commit_fails_if_added_key_was_created() {
+        let (_dir, dbenv, store) = store();
+        let txn = store.handle();
+
+        txn.begin().unwrap();
+        assert!(store.add(b"a", b"1"));
+        assert!(dbenv.add(b"a", b"other"));
+
+        assert!(matches!(txn.commit(), Err(TxnError::Conflict)));
+        assert_eq!(dbenv.get(b"a"), Some(b"other".to_vec()));
+    }
+
+    #[test]
+    fn failed_cas_is_checked_at_commit() {
+        let (_dir, dbenv, store) = store();
+        let txn = store.handle();
+        dbenv.put(b"a", b"1");
+
+        // the transaction acted on cas failing, so the commit depends on the key still being 1
+        txn.begin().unwrap();
+        assert_eq!(store.cas(b"a", None, b"2"), Err(Some(b"1".to_vec())));
+        store.put(b"b", b"2");
+        dbenv.del(b"a");
+
+        assert!(matches!(txn.commit(), Err(TxnError::Conflict)));
+    }
+
+    #[test]
+    fn keys_only_written_dont_conflict() {
+        let (_dir, dbenv, store) = store();
+        let txn = store.handle();
+
+        txn.begin().unwrap();
+        store.put(b"a", b"1");
+        dbenv.put(b"a", b"other");
+
+        txn.commit().unwrap();
+        assert_eq!(dbenv.get(b"a"), Some(b"1".to_vec()));
+    }
+
+    #[test]
+    fn abort_discards_writes() {
+        let (_dir, dbenv, store) = store();
+        let txn = store.handle();
+
+        txn.begin().unwrap();
+        assert!(matches!(txn.begin(), Err(TxnError::AlreadyOpen)));
+        store.put(b"a", b"1");
+        txn.abort().unwrap();
+
+        assert_eq!(store.get(b"a"), None);
+        assert_eq!(dbenv.get(b"a"), None);
+        assert!(matches!(txn.commit(), Err(TxnError::NotOpen)));
+    }
+}